@@ -0,0 +1,169 @@
+//! Benchmarks for key generation and the session cipher, run once for each
+//! enabled crypto provider.
+//!
+//! ```console
+//! $ cargo bench --all-features
+//! ```
+
+use criterion::{
+    criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion,
+    Throughput,
+};
+use libsignal_protocol::{
+    self as sig,
+    messages::{CiphertextMessage, SignalMessage},
+    test_utils::TestParty,
+    Context, SessionBuilder,
+};
+use std::convert::TryFrom;
+
+const PRE_KEY_COUNTS: &[u32] = &[1, 100];
+const PAYLOAD_SIZES: &[usize] = &[16, 1024, 64 * 1024];
+
+/// A [`Context`] for each crypto provider that was compiled in.
+fn providers() -> Vec<(&'static str, Context)> {
+    let mut providers = Vec::new();
+
+    #[cfg(feature = "crypto-native")]
+    {
+        let crypto = sig::crypto::DefaultCrypto;
+        providers.push(("native", Context::new(crypto).unwrap()));
+    }
+    #[cfg(feature = "crypto-openssl")]
+    {
+        let crypto = sig::crypto::OpenSSLCrypto;
+        providers.push(("openssl", Context::new(crypto).unwrap()));
+    }
+    #[cfg(feature = "crypto-ring")]
+    {
+        let crypto = sig::crypto::RingCrypto;
+        providers.push(("ring", Context::new(crypto).unwrap()));
+    }
+
+    providers
+}
+
+/// Alice and Bob, with a session which both sides have acknowledged so
+/// everything is sent as a [`SignalMessage`].
+fn established_session(ctx: &Context) -> (TestParty, TestParty) {
+    let alice = TestParty::new(ctx, "alice", 1).unwrap();
+    let bob = TestParty::new(ctx, "bob", 1).unwrap();
+    alice.start_session_with(&bob).unwrap();
+
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+    bob_cipher
+        .decrypt(&alice_cipher.encrypt(b"Hello, Bob").unwrap())
+        .unwrap();
+    alice_cipher
+        .decrypt(&bob_cipher.encrypt(b"Hello, Alice").unwrap())
+        .unwrap();
+
+    (alice, bob)
+}
+
+fn key_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_pre_keys");
+
+    for (provider, ctx) in providers() {
+        for &count in PRE_KEY_COUNTS {
+            group.throughput(Throughput::Elements(u64::from(count)));
+            group.bench_with_input(
+                BenchmarkId::new(provider, count),
+                &count,
+                |b, &count| {
+                    b.iter(|| {
+                        sig::generate_pre_keys(&ctx, 1, count).unwrap().count()
+                    })
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn process_pre_key_bundle(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_pre_key_bundle");
+
+    for (provider, ctx) in providers() {
+        let alice = TestParty::new(&ctx, "alice", 1).unwrap();
+        let bob = TestParty::new(&ctx, "bob", 1).unwrap();
+        let builder =
+            SessionBuilder::new(&ctx, &alice.store_context, &bob.address);
+
+        group.bench_function(provider, |b| {
+            b.iter(|| {
+                builder.process_pre_key_bundle(&bob.pre_key_bundle).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn encrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encrypt");
+
+    for (provider, ctx) in providers() {
+        let (alice, bob) = established_session(&ctx);
+        let cipher = alice.session_cipher(&bob.address).unwrap();
+
+        for &size in PAYLOAD_SIZES {
+            let plaintext = vec![0x42; size];
+
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(provider, size),
+                &plaintext,
+                |b, plaintext| b.iter(|| cipher.encrypt(plaintext).unwrap()),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn decrypt_message(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decrypt_message");
+
+    for (provider, ctx) in providers() {
+        let (alice, bob) = established_session(&ctx);
+        let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+        let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+
+        for &size in PAYLOAD_SIZES {
+            let plaintext = vec![0x42; size];
+
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(provider, size),
+                &plaintext,
+                |b, plaintext| {
+                    // every message can only be decrypted once, so each
+                    // iteration needs a fresh one
+                    b.iter_batched(
+                        || {
+                            let message: CiphertextMessage =
+                                alice_cipher.encrypt(plaintext).unwrap();
+                            SignalMessage::try_from(message).unwrap()
+                        },
+                        |message| bob_cipher.decrypt_message(&message).unwrap(),
+                        BatchSize::SmallInput,
+                    )
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    key_generation,
+    process_pre_key_bundle,
+    encrypt,
+    decrypt_message
+);
+criterion_main!(benches);
@@ -36,6 +36,8 @@ cfg_if::cfg_if! {
         type Crypto = sig::crypto::DefaultCrypto;
     } else if #[cfg(feature = "crypto-openssl")] {
         type Crypto = sig::crypto::OpenSSLCrypto;
+    } else if #[cfg(feature = "crypto-ring")] {
+        type Crypto = sig::crypto::RingCrypto;
     } else {
         compile_error!("These tests require one of the crypto features to be enabled");
     }
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{
+    envelope::Envelope, Context, Deserializable, Serializable,
+};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(envelope) = Envelope::deserialize(ctx, data) {
+            let serialized = envelope.serialize().unwrap();
+            let round_tripped =
+                Envelope::deserialize(ctx, serialized.as_slice()).unwrap();
+            assert_eq!(round_tripped, envelope);
+        }
+    });
+});
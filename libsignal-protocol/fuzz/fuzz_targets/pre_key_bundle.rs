@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{Context, Deserializable, PreKeyBundle, Serializable};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(bundle) = PreKeyBundle::deserialize(ctx, data) {
+            let _ = bundle.verify_signature();
+            bundle.serialize().unwrap();
+        }
+    });
+});
@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{
+    messages::PreKeySignalMessage, Context, Deserializable,
+};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(message) = PreKeySignalMessage::deserialize(ctx, data) {
+            let _ = message.message_version();
+            let _ = message.identity_key();
+            let _ = message.registration_id();
+            let _ = message.pre_key_id();
+            let _ = message.signed_pre_key_id();
+            let _ = message.base_key();
+
+            let inner = message.signal_message();
+            let _ = inner.sender_ratchet_key();
+            let _ = inner.counter();
+            let _ = inner.body();
+        }
+    });
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{keys::PrivateKey, Context};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(key) = PrivateKey::decode_point(ctx, data) {
+            let public = key.generate_public_key().unwrap();
+            public.calculate_agreement(&key).unwrap();
+        }
+    });
+});
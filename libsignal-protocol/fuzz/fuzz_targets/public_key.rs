@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{keys::PublicKey, Context};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(key) = PublicKey::decode_point(ctx, data) {
+            let _ = key.verify_signature(data, data);
+            key.to_bytes().unwrap();
+        }
+    });
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::time::SystemTime;
+
+use libsignal_protocol::{
+    sealed_sender::SenderCertificate, Context, Deserializable, Serializable,
+};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(certificate) = SenderCertificate::deserialize(ctx, data) {
+            let trust_root = certificate.signer().key();
+            let _ = certificate.validate(trust_root, SystemTime::UNIX_EPOCH);
+            let serialized = certificate.serialize().unwrap();
+            assert_eq!(serialized.as_slice(), data);
+        }
+    });
+});
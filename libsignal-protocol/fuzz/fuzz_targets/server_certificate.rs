@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{
+    sealed_sender::ServerCertificate, Context, Deserializable, Serializable,
+};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(certificate) = ServerCertificate::deserialize(ctx, data) {
+            let _ = certificate.validate(certificate.key());
+            let serialized = certificate.serialize().unwrap();
+            assert_eq!(serialized.as_slice(), data);
+        }
+    });
+});
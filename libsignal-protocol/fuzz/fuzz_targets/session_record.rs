@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{Context, Deserializable, SessionRecord};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        if let Ok(record) = SessionRecord::deserialize(ctx, data) {
+            // this re-serializes the record and walks every state, which
+            // covers most of the session_state accessors
+            let _ = record.diagnostics();
+        }
+    });
+});
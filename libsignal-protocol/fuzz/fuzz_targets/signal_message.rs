@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use libsignal_protocol::{messages::SignalMessage, Context, Deserializable};
+
+thread_local! {
+    static CTX: Context = Context::default();
+}
+
+fuzz_target!(|data: &[u8]| {
+    CTX.with(|ctx| {
+        let _ = SignalMessage::is_legacy(data);
+
+        if let Ok(message) = SignalMessage::deserialize(ctx, data) {
+            let _ = message.sender_ratchet_key();
+            let _ = message.message_version();
+            let _ = message.counter();
+            let _ = message.body();
+        }
+    });
+});
@@ -0,0 +1,468 @@
+//! Encrypting attachments (images, voice notes, documents, etc.) separately
+//! from the session they are sent over.
+//!
+//! Like the Signal apps, each attachment is encrypted under its own random
+//! [`AttachmentKey`] and uploaded somewhere else (e.g. a CDN). The sender then
+//! sends the recipient an [`Attachment`] (the key, the size of the plaintext
+//! and a digest of the ciphertext) inside a normal [`SessionCipher`] message,
+//! along with wherever the ciphertext can be downloaded from.
+//!
+//! An encrypted attachment is laid out as
+//!
+//! ```text
+//! IV || AES-256-CBC(plaintext || padding) || HMAC-SHA256(IV || ciphertext)
+//! ```
+//!
+//! where the plaintext is padded with zeroes up to one of a set of size
+//! buckets (see [`padded_size()`]), so the ciphertext only gives away a rough
+//! idea of the attachment's size. The digest is a SHA-512 hash of the whole
+//! thing.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use libsignal_protocol::{attachments, Context};
+//! # use anyhow::Error;
+//! # use std::io::Cursor;
+//! # fn main() -> Result<(), Error> {
+//! # cfg_if::cfg_if! {
+//! #  if #[cfg(feature = "crypto-native")] {
+//! #      type Crypto = libsignal_protocol::crypto::DefaultCrypto;
+//! #  } else if #[cfg(feature = "crypto-openssl")] {
+//! #      type Crypto = libsignal_protocol::crypto::OpenSSLCrypto;
+//! #  } else if #[cfg(feature = "crypto-ring")] {
+//! #      type Crypto = libsignal_protocol::crypto::RingCrypto;
+//! #  } else {
+//! #      compile_error!("These tests require one of the crypto features to be enabled");
+//! #  }
+//! # }
+//! let ctx = Context::new(Crypto::default()).unwrap();
+//! let photo = b"definitely a JPEG";
+//!
+//! let mut uploaded = Vec::new();
+//! let attachment = attachments::encrypt(&ctx, &photo[..], &mut uploaded)?;
+//! assert_eq!(
+//!     uploaded.len() as u64,
+//!     attachments::ciphertext_size(attachment.size)
+//! );
+//!
+//! // ... send the attachment to the recipient inside a session message ...
+//!
+//! let mut downloaded = Vec::new();
+//! let uploaded = Cursor::new(uploaded);
+//! attachments::decrypt(&ctx, &attachment, uploaded, &mut downloaded)?;
+//! assert_eq!(downloaded, photo);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`SessionCipher`]: crate::SessionCipher
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{
+    crypto::{
+        constant_time_eq, CipherMode, Crypto, Sha256Hmac, Sha512Digest,
+        SignalCipherType,
+    },
+    Context, Error, InternalError,
+};
+
+const AES_KEY_LENGTH: usize = 32;
+const MAC_KEY_LENGTH: usize = 32;
+const IV_LENGTH: usize = 16;
+const BLOCK_SIZE: usize = 16;
+const MAC_LENGTH: usize = 32;
+/// How much of an attachment is read into memory at a time.
+const CHUNK_SIZE: usize = 16 * 1024;
+/// The smallest bucket attachments are padded up to.
+const MIN_PADDED_SIZE: u64 = 541;
+/// Each bucket is this much bigger than the one before it.
+const BUCKET_GROWTH: f64 = 1.05;
+
+/// The key used to encrypt a single attachment (an AES-256 key followed by
+/// a HMAC-SHA256 key).
+#[derive(Clone)]
+pub struct AttachmentKey {
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl AttachmentKey {
+    /// How many bytes are in an [`AttachmentKey`].
+    pub const LENGTH: usize = AES_KEY_LENGTH + MAC_KEY_LENGTH;
+
+    /// Generate a new random [`AttachmentKey`].
+    pub fn generate(crypto: &dyn Crypto) -> Result<AttachmentKey, Error> {
+        let mut key = Zeroizing::new(vec![0; AttachmentKey::LENGTH]);
+        crypto.fill_random(&mut key)?;
+
+        Ok(AttachmentKey { key })
+    }
+
+    /// Load an [`AttachmentKey`] received from another client.
+    pub fn from_bytes(key: &[u8]) -> Result<AttachmentKey, Error> {
+        if key.len() != AttachmentKey::LENGTH {
+            return Err(InternalError::InvalidKey.into());
+        }
+
+        Ok(AttachmentKey {
+            key: Zeroizing::new(key.to_vec()),
+        })
+    }
+
+    /// The raw key material, for sending to the recipient.
+    pub fn as_bytes(&self) -> &[u8] { &self.key }
+
+    fn aes_key(&self) -> &[u8] { &self.key[..AES_KEY_LENGTH] }
+
+    fn mac_key(&self) -> &[u8] { &self.key[AES_KEY_LENGTH..] }
+}
+
+impl Debug for AttachmentKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttachmentKey")
+            .field("key", &"<elided>")
+            .finish()
+    }
+}
+
+/// Everything the recipient needs to decrypt an attachment, normally sent to
+/// them inside a session message.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    /// The key the attachment was encrypted with.
+    pub key: AttachmentKey,
+    /// A SHA-512 digest of the encrypted attachment, so the recipient can
+    /// make sure they downloaded what the sender uploaded.
+    pub digest: Vec<u8>,
+    /// The size of the plaintext, before it was padded.
+    pub size: u64,
+}
+
+/// The size an attachment of `size` bytes will be padded up to before it is
+/// encrypted.
+///
+/// Attachments are padded to the nearest power of 1.05, with a minimum of 541
+/// bytes, so at most 5% of the ciphertext is padding.
+pub fn padded_size(size: u64) -> u64 {
+    let exponent = ((size as f64).ln() / BUCKET_GROWTH.ln()).ceil();
+    let bucket = BUCKET_GROWTH.powf(exponent).floor() as u64;
+
+    // the max(size) guards against floating point rounding errors
+    bucket.max(MIN_PADDED_SIZE).max(size)
+}
+
+/// The number of bytes [`encrypt()`] will write for an attachment of `size`
+/// bytes, including the IV and MAC.
+pub fn ciphertext_size(size: u64) -> u64 {
+    // PKCS#5 always adds at least one byte of padding
+    let blocks = padded_size(size) / BLOCK_SIZE as u64 + 1;
+
+    IV_LENGTH as u64 + blocks * BLOCK_SIZE as u64 + MAC_LENGTH as u64
+}
+
+/// Encrypt an attachment under a freshly generated [`AttachmentKey`],
+/// writing the result to `ciphertext`.
+///
+/// The attachment is processed a chunk at a time, so it never needs to be
+/// held in memory all at once.
+pub fn encrypt<R: Read, W: Write>(
+    ctx: &Context,
+    plaintext: R,
+    mut ciphertext: W,
+) -> Result<Attachment, Error> {
+    let crypto = ctx.crypto();
+    let key = AttachmentKey::generate(crypto)?;
+    let mut iv = [0; IV_LENGTH];
+    crypto.fill_random(&mut iv)?;
+
+    let mut auth = Authenticator::new(crypto, &key)?;
+    let mut cipher = crypto.cipher_stream(
+        CipherMode::Encrypt,
+        SignalCipherType::AesCbcPkcs5,
+        key.aes_key(),
+        &iv,
+    )?;
+    let mut encrypted = Vec::with_capacity(CHUNK_SIZE + BLOCK_SIZE);
+
+    ciphertext.write_all(&iv)?;
+    auth.update(&iv)?;
+
+    let mut process = |chunk: &[u8]| -> Result<(), Error> {
+        cipher.update(chunk, &mut encrypted)?;
+        ciphertext.write_all(&encrypted)?;
+        auth.update(&encrypted)?;
+        encrypted.clear();
+
+        Ok(())
+    };
+
+    let mut size = 0;
+    for_each_chunk(plaintext, |chunk| {
+        size += chunk.len() as u64;
+        process(chunk)
+    })?;
+    let padding = padded_size(size) - size;
+    for_each_chunk(io::repeat(0).take(padding), process)?;
+
+    cipher.finalize(&mut encrypted)?;
+    ciphertext.write_all(&encrypted)?;
+    auth.update(&encrypted)?;
+
+    let mac = auth.mac()?;
+    let digest = auth.digest(&mac)?;
+    ciphertext.write_all(&mac)?;
+
+    Ok(Attachment { key, digest, size })
+}
+
+/// Decrypt an attachment, writing the original plaintext to `plaintext`.
+///
+/// The MAC and digest are checked before anything is decrypted, which means
+/// `ciphertext` is read twice (hence the [`Seek`] bound). It must not change
+/// between the two passes.
+pub fn decrypt<R: Read + Seek, W: Write>(
+    ctx: &Context,
+    attachment: &Attachment,
+    mut ciphertext: R,
+    mut plaintext: W,
+) -> Result<(), Error> {
+    let crypto = ctx.crypto();
+    let key = &attachment.key;
+
+    let total_length = ciphertext.seek(SeekFrom::End(0))?;
+    let body_length = total_length.saturating_sub(MAC_LENGTH as u64);
+    let encrypted_length = body_length.saturating_sub(IV_LENGTH as u64);
+    if encrypted_length == 0 || encrypted_length % BLOCK_SIZE as u64 > 0 {
+        return Err(InternalError::InvalidMessage.into());
+    }
+
+    // make sure the attachment is intact before touching the plaintext
+    ciphertext.seek(SeekFrom::Start(0))?;
+    let mut auth = Authenticator::new(crypto, key)?;
+    for_each_chunk((&mut ciphertext).take(body_length), |chunk| {
+        auth.update(chunk).map_err(Error::from)
+    })?;
+    let mut their_mac = [0; MAC_LENGTH];
+    ciphertext.read_exact(&mut their_mac)?;
+
+    let our_mac = auth.mac()?;
+    // the digest covers what was uploaded, even if the MAC is wrong
+    let digest = auth.digest(&their_mac)?;
+    if !constant_time_eq(&our_mac, &their_mac) {
+        return Err(InternalError::InvalidMAC.into());
+    }
+    if !constant_time_eq(&digest, &attachment.digest) {
+        return Err(Error::DigestMismatch);
+    }
+
+    ciphertext.seek(SeekFrom::Start(0))?;
+    let mut iv = [0; IV_LENGTH];
+    ciphertext.read_exact(&mut iv)?;
+    let mut cipher = crypto.cipher_stream(
+        CipherMode::Decrypt,
+        SignalCipherType::AesCbcPkcs5,
+        key.aes_key(),
+        &iv,
+    )?;
+    let mut decrypted = Zeroizing::new(Vec::with_capacity(CHUNK_SIZE));
+    let mut remaining = attachment.size;
+
+    for_each_chunk((&mut ciphertext).take(encrypted_length), |chunk| {
+        cipher.update(chunk, &mut decrypted)?;
+        write_unpadded(&mut plaintext, &mut decrypted, &mut remaining)
+    })?;
+    cipher.finalize(&mut decrypted)?;
+    write_unpadded(&mut plaintext, &mut decrypted, &mut remaining)?;
+
+    if remaining > 0 {
+        // the sender claimed the attachment was bigger than it actually is
+        return Err(InternalError::InvalidMessage.into());
+    }
+
+    Ok(())
+}
+
+/// Write up to `remaining` bytes of `decrypted` to the `writer`, dropping
+/// anything after that (i.e. the padding).
+fn write_unpadded<W: Write>(
+    writer: &mut W,
+    decrypted: &mut Vec<u8>,
+    remaining: &mut u64,
+) -> Result<(), Error> {
+    let wanted = if (decrypted.len() as u64) < *remaining {
+        decrypted.len()
+    } else {
+        *remaining as usize
+    };
+
+    writer.write_all(&decrypted[..wanted])?;
+    *remaining -= wanted as u64;
+    decrypted.zeroize();
+
+    Ok(())
+}
+
+/// Read `reader` to the end, passing it to `f` a chunk at a time.
+fn for_each_chunk<R, F>(mut reader: R, mut f: F) -> Result<(), Error>
+where
+    R: Read,
+    F: FnMut(&[u8]) -> Result<(), Error>,
+{
+    let mut buffer = Zeroizing::new(vec![0; CHUNK_SIZE]);
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => f(&buffer[..n])?,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Calculates the MAC and digest as the encrypted attachment goes past.
+struct Authenticator {
+    hmac: Box<dyn Sha256Hmac>,
+    digest: Box<dyn Sha512Digest>,
+}
+
+impl Authenticator {
+    fn new(
+        crypto: &dyn Crypto,
+        key: &AttachmentKey,
+    ) -> Result<Authenticator, InternalError> {
+        Ok(Authenticator {
+            hmac: crypto.hmac_sha256(key.mac_key())?,
+            digest: crypto.sha512_digest()?,
+        })
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<(), InternalError> {
+        self.hmac.update(data)?;
+        self.digest.update(data)
+    }
+
+    fn mac(&mut self) -> Result<Vec<u8>, InternalError> {
+        self.hmac.finalize()
+    }
+
+    /// Finish the digest, which also covers the trailing MAC.
+    fn digest(&mut self, mac: &[u8]) -> Result<Vec<u8>, InternalError> {
+        self.digest.update(mac)?;
+        self.digest.finalize()
+    }
+}
+
+#[cfg(all(test, feature = "crypto-native"))]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn round_trip(ctx: &Context, data: &[u8]) -> Vec<u8> {
+        let mut ciphertext = Vec::new();
+        let attachment = encrypt(ctx, data, &mut ciphertext).unwrap();
+        assert_eq!(attachment.size, data.len() as u64);
+        assert_eq!(
+            ciphertext.len() as u64,
+            ciphertext_size(data.len() as u64)
+        );
+
+        let mut plaintext = Vec::new();
+        decrypt(ctx, &attachment, Cursor::new(ciphertext), &mut plaintext)
+            .unwrap();
+        plaintext
+    }
+
+    #[test]
+    fn attachments_are_padded_into_buckets() {
+        assert_eq!(padded_size(0), 541);
+        assert_eq!(padded_size(1), 541);
+        assert_eq!(padded_size(541), 541);
+        assert_eq!(padded_size(542), 568);
+        assert_eq!(padded_size(1_000_000), 1_041_743);
+
+        for size in (0..100_000).step_by(7) {
+            let padded = padded_size(size);
+            assert!(padded >= size);
+            assert!(padded <= (size * 21 / 20).max(541) + 1);
+        }
+    }
+
+    #[test]
+    fn round_trip_attachments_of_various_sizes() {
+        let ctx = Context::default();
+
+        for &size in &[0, 1, 15, 16, 541, 542, CHUNK_SIZE, 3 * CHUNK_SIZE + 5]
+        {
+            let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+
+            let got = round_trip(&ctx, &data);
+
+            assert_eq!(got, data, "{} bytes", size);
+        }
+    }
+
+    #[test]
+    fn tampered_attachments_are_rejected() {
+        let ctx = Context::default();
+        let mut ciphertext = Vec::new();
+        let attachment = encrypt(&ctx, &b"Hello, World!"[..], &mut ciphertext)
+            .unwrap();
+        ciphertext[IV_LENGTH + 3] ^= 0x01;
+
+        let got =
+            decrypt(&ctx, &attachment, Cursor::new(ciphertext), io::sink())
+                .unwrap_err();
+
+        match got {
+            Error::InternalError(InternalError::InvalidMAC) => {},
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_digest_must_match() {
+        let ctx = Context::default();
+        let mut ciphertext = Vec::new();
+        let mut attachment =
+            encrypt(&ctx, &b"Hello, World!"[..], &mut ciphertext).unwrap();
+        attachment.digest[0] ^= 0x01;
+
+        let got =
+            decrypt(&ctx, &attachment, Cursor::new(ciphertext), io::sink())
+                .unwrap_err();
+
+        match got {
+            Error::DigestMismatch => {},
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_attachments_are_rejected() {
+        let ctx = Context::default();
+        let attachment = Attachment {
+            key: AttachmentKey::generate(ctx.crypto()).unwrap(),
+            digest: Vec::new(),
+            size: 0,
+        };
+        let ciphertext = vec![0; IV_LENGTH + MAC_LENGTH];
+
+        let got =
+            decrypt(&ctx, &attachment, Cursor::new(ciphertext), io::sink());
+
+        assert!(got.is_err());
+    }
+
+    #[test]
+    fn keys_must_be_the_right_length() {
+        assert!(AttachmentKey::from_bytes(&[0; 32]).is_err());
+        assert!(AttachmentKey::from_bytes(&[0; AttachmentKey::LENGTH]).is_ok());
+    }
+}
@@ -5,8 +5,13 @@ use std::{
     mem,
     ops::{Index, IndexMut},
 };
+use zeroize::Zeroize;
 
 /// A byte buffer (e.g. `Vec<u8>`).
+///
+/// The underlying memory is zeroed before it is freed, so it's safe to use
+/// for serialized keys and sessions. See [`SecretBuffer`](crate::SecretBuffer)
+/// for a buffer which won't leak its contents through [`Debug`] either.
 pub struct Buffer {
     raw: *mut sys::signal_buffer,
 }
@@ -72,12 +77,18 @@ impl Buffer {
     ///
     /// # Note
     ///
-    /// Every append results in a re-allocation of the underlying buffer.
+    /// Every append results in a re-allocation of the underlying buffer. The
+    /// old allocation is zeroed before it is freed.
     pub fn append(&mut self, data: &[u8]) {
-        unsafe {
-            self.raw =
-                sys::signal_buffer_append(self.raw, data.as_ptr(), data.len());
-        }
+        let len = self.len();
+        let mut bigger = Buffer::with_capacity(len + data.len());
+
+        let dest = bigger.as_slice_mut();
+        dest[..len].copy_from_slice(self.as_slice());
+        dest[len..].copy_from_slice(data);
+
+        // dropping the old buffer wipes it
+        *self = bigger;
     }
 }
 
@@ -122,8 +133,10 @@ impl Debug for Buffer {
 }
 
 impl From<Vec<u8>> for Buffer {
-    fn from(other: Vec<u8>) -> Buffer {
-        Buffer::from(other.as_slice())
+    fn from(mut other: Vec<u8>) -> Buffer {
+        let buffer = Buffer::from(other.as_slice());
+        other.zeroize();
+        buffer
     }
 }
 
@@ -191,10 +204,14 @@ impl Clone for Buffer {
     }
 }
 
+impl Zeroize for Buffer {
+    fn zeroize(&mut self) { self.as_slice_mut().zeroize() }
+}
+
 impl Drop for Buffer {
     fn drop(&mut self) {
         unsafe {
-            sys::signal_buffer_free(self.raw);
+            sys::signal_buffer_bzero_free(self.raw);
         }
     }
 }
@@ -239,4 +256,13 @@ mod tests {
         let got = std::str::from_utf8(buffer.as_slice()).unwrap();
         assert_eq!("Hello, World!\n", got);
     }
+
+    #[test]
+    fn zeroize_a_buffer() {
+        let mut buffer = Buffer::from(&[1, 2, 3][..]);
+
+        buffer.zeroize();
+
+        assert_eq!(buffer.as_slice(), &[0, 0, 0]);
+    }
 }
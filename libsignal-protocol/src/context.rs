@@ -81,6 +81,8 @@ pub fn generate_key_pair(ctx: &Context) -> Result<KeyPair, Error> {
 /// #      type Crypto = libsignal_protocol::crypto::DefaultCrypto;
 /// #  } else if #[cfg(feature = "crypto-openssl")] {
 /// #      type Crypto = libsignal_protocol::crypto::OpenSSLCrypto;
+/// #  } else if #[cfg(feature = "crypto-ring")] {
+/// #      type Crypto = libsignal_protocol::crypto::RingCrypto;
 /// #  } else {
 /// #      compile_error!("These tests require one of the crypto features to be enabled");
 /// #  }
@@ -474,4 +476,13 @@ mod tests {
 
         drop(ctx);
     }
+
+    #[cfg(feature = "crypto-ring")]
+    #[test]
+    fn library_initialization_example_from_readme_ring() {
+        use crate::crypto::RingCrypto;
+        let ctx = Context::new(RingCrypto::default()).unwrap();
+
+        drop(ctx);
+    }
 }
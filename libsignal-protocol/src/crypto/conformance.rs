@@ -0,0 +1,438 @@
+//! A conformance suite for [`Crypto`] implementations.
+//!
+//! `libsignal-protocol-c` makes a handful of assumptions about the
+//! cryptographic primitives it is given (PKCS#5 padding for AES-CBC, a
+//! 128-bit big-endian counter for AES-CTR, hashing contexts which can be
+//! reused after `finalize()`, etc.). Breaking any of them usually only shows
+//! up as a failed session much later on, so these checks exercise each
+//! primitive against published known-answer tests instead. The AES vectors
+//! are also run through [`Crypto::encrypt_in_place()`] and
+//! [`Crypto::cipher_stream()`], so overriding those can't silently diverge
+//! from [`Crypto::encrypt()`].
+//!
+//! Every check panics with a description of what went wrong, so they are
+//! meant to be called from a `#[test]`. This module is available when the
+//! `test-utils` feature is enabled.
+
+use crate::crypto::{CipherMode, Crypto, SignalCipherType};
+
+/// Run every check in this module against `crypto`.
+pub fn check_all(crypto: &dyn Crypto) {
+    check_fill_random(crypto);
+    check_hmac_sha256(crypto);
+    check_sha512(crypto);
+    check_aes_ctr(crypto);
+    check_aes_cbc(crypto);
+    check_finalize_resets(crypto);
+    check_rejects_bad_lengths(crypto);
+}
+
+/// [`Crypto::fill_random()`] must succeed and actually write to the buffer.
+pub fn check_fill_random(crypto: &dyn Crypto) {
+    let mut buffer = [0; 64];
+    crypto
+        .fill_random(&mut buffer)
+        .expect("fill_random() failed");
+
+    assert!(
+        buffer.iter().any(|&b| b != 0),
+        "fill_random() left the buffer zeroed"
+    );
+}
+
+/// HMAC-SHA256 test vectors from RFC 4231 (test cases 1, 2 and 6).
+pub fn check_hmac_sha256(crypto: &dyn Crypto) {
+    let cases: &[(&[u8], &[u8], &str)] = &[
+        (
+            &[0x0b; 20],
+            b"Hi There",
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+        ),
+        (
+            b"Jefe",
+            b"what do ya want for nothing?",
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+        ),
+        (
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First",
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+        ),
+    ];
+
+    for (i, &(key, data, expected)) in cases.iter().enumerate() {
+        let mut mac = crypto
+            .hmac_sha256(key)
+            .expect("unable to create a HMAC-SHA256 context");
+
+        // feed the data in two halves to make sure update() accumulates
+        let (first, second) = data.split_at(data.len() / 2);
+        mac.update(first).unwrap();
+        mac.update(second).unwrap();
+
+        assert_eq!(
+            mac.finalize().unwrap(),
+            hex(expected),
+            "HMAC-SHA256 test vector {} failed",
+            i
+        );
+    }
+}
+
+/// SHA-512 test vectors from FIPS 180-2.
+pub fn check_sha512(crypto: &dyn Crypto) {
+    let cases: &[(&[u8], &str)] = &[
+        (
+            b"",
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+             47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e",
+        ),
+        (
+            b"abc",
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+        ),
+        (
+            b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn\
+              hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+            "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018\
+             501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909",
+        ),
+    ];
+
+    for (i, &(data, expected)) in cases.iter().enumerate() {
+        let mut digest = crypto
+            .sha512_digest()
+            .expect("unable to create a SHA-512 context");
+        digest.update(data).unwrap();
+
+        assert_eq!(
+            digest.finalize().unwrap(),
+            hex(expected),
+            "SHA-512 test vector {} failed",
+            i
+        );
+    }
+}
+
+// The keys and plaintext from NIST SP 800-38A, appendix F.
+const AES_128_KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+const AES_192_KEY: &str = "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b";
+const AES_256_KEY: &str =
+    "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+const PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172a\
+                         ae2d8a571e03ac9c9eb76fac45af8e51\
+                         30c81c46a35ce411e5fbc1191a0a52ef\
+                         f69f2445df4f9b17ad2b417be66c3710";
+
+/// AES-CTR test vectors from NIST SP 800-38A (F.5.1, F.5.3 and F.5.5).
+pub fn check_aes_ctr(crypto: &dyn Crypto) {
+    let counter = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff");
+    let cases = &[
+        (
+            AES_128_KEY,
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff\
+             5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee",
+        ),
+        (
+            AES_192_KEY,
+            "1abc932417521ca24f2b0459fe7e6e0b090339ec0aa6faefd5ccc2c6f4ce8e94\
+             1e36b26bd1ebc670d1bd1d665620abf74f78a7f6d29809585a97daec58c6b050",
+        ),
+        (
+            AES_256_KEY,
+            "601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5\
+             2b0930daa23de94ce87017ba2d84988ddfc9c58db67aada613c2dd08457941a6",
+        ),
+    ];
+
+    for &(key, expected) in cases {
+        check_cipher(
+            crypto,
+            SignalCipherType::AesCtrNoPadding,
+            &hex(key),
+            &counter,
+            &hex(PLAINTEXT),
+            &hex(expected),
+        );
+    }
+
+    // a partial block must not be padded
+    let key = hex(AES_128_KEY);
+    let encrypted = crypto
+        .encrypt(SignalCipherType::AesCtrNoPadding, &key, &counter, &[1; 7])
+        .unwrap();
+    assert_eq!(encrypted.len(), 7, "AES-CTR output should not be padded");
+}
+
+/// AES-CBC test vectors from NIST SP 800-38A (F.2.1, F.2.3 and F.2.5), with
+/// the trailing block of PKCS#5 padding `libsignal-protocol-c` expects.
+pub fn check_aes_cbc(crypto: &dyn Crypto) {
+    let iv = hex("000102030405060708090a0b0c0d0e0f");
+    let cases = &[
+        (
+            AES_128_KEY,
+            "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2\
+             73bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7\
+             8cb82807230e1321d3fae00d18cc2012",
+        ),
+        (
+            AES_192_KEY,
+            "4f021db243bc633d7178183a9fa071e8b4d9ada9ad7dedf4e5e738763f69145a\
+             571b242012fb7ae07fa9baac3df102e008b0e27988598881d920a9e64f5615cd\
+             612ccd79224b350935d45dd6a98f8176",
+        ),
+        (
+            AES_256_KEY,
+            "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d\
+             39f23369a9d9bacfa530e26304231461b2eb05e2c39be9fcda6c19078c6a9d1b\
+             3f461796d6b0d6b2e0c2a72b4d80e644",
+        ),
+    ];
+
+    for &(key, expected) in cases {
+        check_cipher(
+            crypto,
+            SignalCipherType::AesCbcPkcs5,
+            &hex(key),
+            &iv,
+            &hex(PLAINTEXT),
+            &hex(expected),
+        );
+    }
+
+    // even an empty message gets a full block of padding
+    let key = hex(AES_128_KEY);
+    let encrypted = crypto
+        .encrypt(SignalCipherType::AesCbcPkcs5, &key, &iv, &[])
+        .unwrap();
+    assert_eq!(encrypted.len(), 16, "AES-CBC should always add padding");
+    let decrypted = crypto
+        .decrypt(SignalCipherType::AesCbcPkcs5, &key, &iv, &encrypted)
+        .unwrap();
+    assert!(decrypted.is_empty(), "the padding wasn't removed");
+}
+
+/// [`Sha256Hmac::finalize()`](crate::crypto::Sha256Hmac::finalize) and
+/// [`Sha512Digest::finalize()`](crate::crypto::Sha512Digest::finalize) must
+/// leave the context ready to hash a new message.
+pub fn check_finalize_resets(crypto: &dyn Crypto) {
+    let mut mac = crypto.hmac_sha256(&[0x0b; 20]).unwrap();
+    mac.update(b"some unrelated message").unwrap();
+    mac.finalize().unwrap();
+    mac.update(b"Hi There").unwrap();
+    assert_eq!(
+        mac.finalize().unwrap(),
+        hex("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"),
+        "HMAC-SHA256 context wasn't reset by finalize()"
+    );
+
+    let mut digest = crypto.sha512_digest().unwrap();
+    digest.update(b"some unrelated message").unwrap();
+    digest.finalize().unwrap();
+    digest.update(b"abc").unwrap();
+    assert_eq!(
+        digest.finalize().unwrap(),
+        hex("ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"),
+        "SHA-512 context wasn't reset by finalize()"
+    );
+}
+
+/// Invalid key and IV lengths must be reported as an error instead of
+/// panicking, as must AES-CBC ciphertext which isn't a whole number of
+/// blocks.
+pub fn check_rejects_bad_lengths(crypto: &dyn Crypto) {
+    let ciphers =
+        [SignalCipherType::AesCtrNoPadding, SignalCipherType::AesCbcPkcs5];
+    let good_key = [0; 32];
+    let good_iv = [0; 16];
+
+    for &cipher in &ciphers {
+        for &key_len in &[0, 15, 17, 31, 33] {
+            let description = format!("a {} byte key", key_len);
+            let key = vec![0; key_len];
+            expect_rejected(crypto, cipher, &key, &good_iv, &description);
+        }
+
+        for &iv_len in &[0, 8, 15, 17] {
+            let description = format!("a {} byte IV", iv_len);
+            let iv = vec![0; iv_len];
+            expect_rejected(crypto, cipher, &good_key, &iv, &description);
+        }
+    }
+
+    let partial_block = crypto.decrypt(
+        SignalCipherType::AesCbcPkcs5,
+        &good_key,
+        &good_iv,
+        &[0; 15],
+    );
+    assert!(
+        partial_block.is_err(),
+        "decrypting a partial AES-CBC block should have failed"
+    );
+}
+
+fn check_cipher(
+    crypto: &dyn Crypto,
+    cipher: SignalCipherType,
+    key: &[u8],
+    iv: &[u8],
+    plaintext: &[u8],
+    ciphertext: &[u8],
+) {
+    let key_len = key.len();
+
+    let encrypted = match crypto.encrypt(cipher, key, iv, plaintext) {
+        Ok(encrypted) => encrypted,
+        Err(e) => panic!(
+            "{:?} encryption with a {} byte key failed: {}",
+            cipher, key_len, e
+        ),
+    };
+    assert_eq!(
+        encrypted, ciphertext,
+        "{:?} encryption with a {} byte key gave the wrong result",
+        cipher, key_len
+    );
+
+    let decrypted = match crypto.decrypt(cipher, key, iv, ciphertext) {
+        Ok(decrypted) => decrypted,
+        Err(e) => panic!(
+            "{:?} decryption with a {} byte key failed: {}",
+            cipher, key_len, e
+        ),
+    };
+    assert_eq!(
+        decrypted, plaintext,
+        "{:?} decryption with a {} byte key gave the wrong result",
+        cipher, key_len
+    );
+
+    let mut buffer = plaintext.to_vec();
+    if let Err(e) = crypto.encrypt_in_place(cipher, key, iv, &mut buffer) {
+        panic!(
+            "{:?} in-place encryption with a {} byte key failed: {}",
+            cipher, key_len, e
+        );
+    }
+    assert_eq!(
+        buffer, ciphertext,
+        "{:?} in-place encryption with a {} byte key gave the wrong result",
+        cipher, key_len
+    );
+    if let Err(e) = crypto.decrypt_in_place(cipher, key, iv, &mut buffer) {
+        panic!(
+            "{:?} in-place decryption with a {} byte key failed: {}",
+            cipher, key_len, e
+        );
+    }
+    assert_eq!(
+        buffer, plaintext,
+        "{:?} in-place decryption with a {} byte key gave the wrong result",
+        cipher, key_len
+    );
+
+    // odd sizes make sure partial blocks are carried between calls
+    let whole = plaintext.len().max(1);
+    for &chunk_size in &[1, 5, 16, 17, whole] {
+        let got = streamed(
+            crypto,
+            CipherMode::Encrypt,
+            cipher,
+            key,
+            iv,
+            plaintext,
+            chunk_size,
+        );
+        assert_eq!(
+            got, ciphertext,
+            "streaming {:?} encryption in {} byte chunks with a {} byte key \
+             gave the wrong result",
+            cipher, chunk_size, key_len
+        );
+
+        let got = streamed(
+            crypto,
+            CipherMode::Decrypt,
+            cipher,
+            key,
+            iv,
+            ciphertext,
+            chunk_size,
+        );
+        assert_eq!(
+            got, plaintext,
+            "streaming {:?} decryption in {} byte chunks with a {} byte key \
+             gave the wrong result",
+            cipher, chunk_size, key_len
+        );
+    }
+}
+
+fn streamed(
+    crypto: &dyn Crypto,
+    mode: CipherMode,
+    cipher: SignalCipherType,
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+    chunk_size: usize,
+) -> Vec<u8> {
+    let mut stream = match crypto.cipher_stream(mode, cipher, key, iv) {
+        Ok(stream) => stream,
+        Err(e) => panic!(
+            "unable to start a {:?} {:?} stream with a {} byte key: {}",
+            cipher,
+            mode,
+            key.len(),
+            e
+        ),
+    };
+    let mut output = Vec::new();
+
+    for chunk in data.chunks(chunk_size) {
+        if let Err(e) = stream.update(chunk, &mut output) {
+            panic!("streaming {:?} {:?} failed: {}", cipher, mode, e);
+        }
+    }
+    if let Err(e) = stream.finalize(&mut output) {
+        panic!("finalizing a {:?} {:?} stream failed: {}", cipher, mode, e);
+    }
+
+    output
+}
+
+fn expect_rejected(
+    crypto: &dyn Crypto,
+    cipher: SignalCipherType,
+    key: &[u8],
+    iv: &[u8],
+    description: &str,
+) {
+    let data = [0; 32];
+
+    assert!(
+        crypto.encrypt(cipher, key, iv, &data).is_err(),
+        "encrypting with {:?} and {} should have failed",
+        cipher,
+        description
+    );
+    assert!(
+        crypto.decrypt(cipher, key, iv, &data).is_err(),
+        "decrypting with {:?} and {} should have failed",
+        cipher,
+        description
+    );
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    let digits: Vec<u8> = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).expect("invalid hex digit") as u8)
+        .collect();
+
+    digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect()
+}
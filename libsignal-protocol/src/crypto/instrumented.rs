@@ -0,0 +1,265 @@
+use std::sync::Arc;
+
+use crate::{
+    crypto::{
+        CipherMode, CipherStream, Crypto, Sha256Hmac, Sha512Digest,
+        SignalCipherType,
+    },
+    errors::InternalError,
+    metrics::{MetricsSink, Operation, Timer},
+};
+
+/// A [`Crypto`] wrapper which reports every call to a [`MetricsSink`].
+///
+/// See the [`metrics`](crate::metrics) module for more.
+#[derive(Debug, Clone)]
+pub struct InstrumentedCrypto<C> {
+    inner: C,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl<C: Crypto> InstrumentedCrypto<C> {
+    /// Wrap an existing [`Crypto`] provider.
+    pub fn new(inner: C, sink: Arc<dyn MetricsSink>) -> InstrumentedCrypto<C> {
+        InstrumentedCrypto { inner, sink }
+    }
+
+    /// Get a reference to the wrapped provider.
+    pub const fn inner(&self) -> &C { &self.inner }
+
+    /// Unwrap the underlying provider.
+    pub fn into_inner(self) -> C { self.inner }
+}
+
+impl<C: Crypto> Crypto for InstrumentedCrypto<C> {
+    fn fill_random(&self, buffer: &mut [u8]) -> Result<(), InternalError> {
+        let timer = Timer::start(Operation::FillRandom);
+        let result = self.inner.fill_random(buffer);
+        timer.finish(&*self.sink, buffer.len(), result.is_ok());
+
+        result
+    }
+
+    fn hmac_sha256(
+        &self,
+        key: &[u8],
+    ) -> Result<Box<dyn Sha256Hmac>, InternalError> {
+        let timer = Timer::start(Operation::HmacInit);
+        let result = self.inner.hmac_sha256(key);
+        timer.finish(&*self.sink, 0, result.is_ok());
+
+        let inner = result?;
+        Ok(Box::new(InstrumentedHmac {
+            inner,
+            sink: Arc::clone(&self.sink),
+        }))
+    }
+
+    fn sha512_digest(&self) -> Result<Box<dyn Sha512Digest>, InternalError> {
+        let timer = Timer::start(Operation::DigestInit);
+        let result = self.inner.sha512_digest();
+        timer.finish(&*self.sink, 0, result.is_ok());
+
+        let inner = result?;
+        Ok(Box::new(InstrumentedDigest {
+            inner,
+            sink: Arc::clone(&self.sink),
+        }))
+    }
+
+    fn encrypt(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        let timer = Timer::start(Operation::Encrypt);
+        let result = self.inner.encrypt(cipher, key, iv, data);
+        timer.finish(&*self.sink, data.len(), result.is_ok());
+
+        result
+    }
+
+    fn decrypt(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        let timer = Timer::start(Operation::Decrypt);
+        let result = self.inner.decrypt(cipher, key, iv, data);
+        timer.finish(&*self.sink, data.len(), result.is_ok());
+
+        result
+    }
+
+    fn encrypt_in_place(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let timer = Timer::start(Operation::Encrypt);
+        let len = data.len();
+        let result = self.inner.encrypt_in_place(cipher, key, iv, data);
+        timer.finish(&*self.sink, len, result.is_ok());
+
+        result
+    }
+
+    fn decrypt_in_place(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let timer = Timer::start(Operation::Decrypt);
+        let len = data.len();
+        let result = self.inner.decrypt_in_place(cipher, key, iv, data);
+        timer.finish(&*self.sink, len, result.is_ok());
+
+        result
+    }
+
+    fn cipher_stream(
+        &self,
+        mode: CipherMode,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn CipherStream + '_>, InternalError> {
+        let operation = match mode {
+            CipherMode::Encrypt => Operation::Encrypt,
+            CipherMode::Decrypt => Operation::Decrypt,
+        };
+        let inner = self.inner.cipher_stream(mode, cipher, key, iv)?;
+
+        Ok(Box::new(InstrumentedCipherStream {
+            inner,
+            operation,
+            sink: Arc::clone(&self.sink),
+        }))
+    }
+}
+
+/// Each call to [`CipherStream::update()`] and
+/// [`CipherStream::finalize()`] is recorded as a separate
+/// [`Operation::Encrypt`] or [`Operation::Decrypt`].
+struct InstrumentedCipherStream<'a> {
+    inner: Box<dyn CipherStream + 'a>,
+    operation: Operation,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl<'a> CipherStream for InstrumentedCipherStream<'a> {
+    fn update(
+        &mut self,
+        data: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let timer = Timer::start(self.operation);
+        let result = self.inner.update(data, output);
+        timer.finish(&*self.sink, data.len(), result.is_ok());
+
+        result
+    }
+
+    fn finalize(&mut self, output: &mut Vec<u8>) -> Result<(), InternalError> {
+        let timer = Timer::start(self.operation);
+        let result = self.inner.finalize(output);
+        timer.finish(&*self.sink, 0, result.is_ok());
+
+        result
+    }
+}
+
+struct InstrumentedHmac {
+    inner: Box<dyn Sha256Hmac>,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl Sha256Hmac for InstrumentedHmac {
+    fn update(&mut self, data: &[u8]) -> Result<(), InternalError> {
+        let timer = Timer::start(Operation::HmacUpdate);
+        let result = self.inner.update(data);
+        timer.finish(&*self.sink, data.len(), result.is_ok());
+
+        result
+    }
+
+    fn finalize(&mut self) -> Result<Vec<u8>, InternalError> {
+        let timer = Timer::start(Operation::HmacFinalize);
+        let result = self.inner.finalize();
+        timer.finish(&*self.sink, 0, result.is_ok());
+
+        result
+    }
+}
+
+struct InstrumentedDigest {
+    inner: Box<dyn Sha512Digest>,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl Sha512Digest for InstrumentedDigest {
+    fn update(&mut self, data: &[u8]) -> Result<(), InternalError> {
+        let timer = Timer::start(Operation::DigestUpdate);
+        let result = self.inner.update(data);
+        timer.finish(&*self.sink, data.len(), result.is_ok());
+
+        result
+    }
+
+    fn finalize(&mut self) -> Result<Vec<u8>, InternalError> {
+        let timer = Timer::start(Operation::DigestFinalize);
+        let result = self.inner.finalize();
+        timer.finish(&*self.sink, 0, result.is_ok());
+
+        result
+    }
+}
+
+#[cfg(all(test, feature = "crypto-native"))]
+mod tests {
+    use super::*;
+    use crate::{crypto::DefaultCrypto, metrics::InMemoryMetrics};
+
+    #[test]
+    fn calls_are_recorded() {
+        let metrics = Arc::new(InMemoryMetrics::default());
+        let crypto = InstrumentedCrypto::new(DefaultCrypto, metrics.clone());
+        let key = [0; 16];
+        let iv = [0; 16];
+
+        let mut random = [0; 32];
+        crypto.fill_random(&mut random).unwrap();
+        let mut hmac = crypto.hmac_sha256(&key).unwrap();
+        hmac.update(b"Hello").unwrap();
+        hmac.update(b"World").unwrap();
+        hmac.finalize().unwrap();
+        let ciphertext = crypto
+            .encrypt(SignalCipherType::AesCtrNoPadding, &key, &iv, b"Hello")
+            .unwrap();
+        // a 3-byte key is never valid
+        let bad_key = [0; 3];
+        let got = crypto.decrypt(
+            SignalCipherType::AesCbcPkcs5,
+            &bad_key,
+            &iv,
+            &ciphertext,
+        );
+        assert!(got.is_err());
+
+        assert_eq!(metrics.stats(Operation::FillRandom).bytes, 32);
+        assert_eq!(metrics.stats(Operation::HmacInit).calls, 1);
+        assert_eq!(metrics.stats(Operation::HmacUpdate).calls, 2);
+        assert_eq!(metrics.stats(Operation::HmacUpdate).bytes, 10);
+        assert_eq!(metrics.stats(Operation::HmacFinalize).calls, 1);
+        assert_eq!(metrics.stats(Operation::Encrypt).bytes, 5);
+        assert_eq!(metrics.stats(Operation::Decrypt).failures, 1);
+    }
+}
@@ -11,6 +11,8 @@ use std::{
 
 use sys::{signal_buffer, signal_crypto_provider};
 
+use zeroize::{Zeroize, Zeroizing};
+
 use crate::{
     buffer::Buffer,
     errors::{InternalError, IntoInternalErrorCode},
@@ -18,21 +20,37 @@ use crate::{
 
 #[cfg(feature = "crypto-native")]
 pub use self::native::DefaultCrypto;
+#[cfg(any(test, feature = "test-utils"))]
+pub use self::deterministic::DeterministicCrypto;
+pub use self::instrumented::InstrumentedCrypto;
 #[cfg(feature = "crypto-openssl")]
 pub use self::openssl::OpenSSLCrypto;
-
+#[cfg(feature = "crypto-ring")]
+pub use self::ring::RingCrypto;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod conformance;
+#[cfg(any(test, feature = "test-utils"))]
+mod deterministic;
+mod instrumented;
 #[cfg(feature = "crypto-native")]
 mod native;
 #[cfg(feature = "crypto-openssl")]
 mod openssl;
+#[cfg(feature = "crypto-ring")]
+mod ring;
+#[cfg(any(feature = "crypto-native", feature = "crypto-ring"))]
+mod symmetric;
 
 /// The error returned from a failed conversion to [`SignalCipherType`].
 #[derive(Debug, Copy, Clone)]
 pub struct SignalCipherTypeError(i32);
 
-#[derive(Debug, Copy, Clone)]
+/// Whether a cipher is being used to encrypt or decrypt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(C)]
-enum CipherMode {
+#[allow(missing_docs)]
+pub enum CipherMode {
     Encrypt,
     Decrypt,
 }
@@ -83,6 +101,30 @@ pub trait Sha512Digest {
     fn finalize(&mut self) -> Result<Vec<u8>, InternalError>;
 }
 
+/// Something which can encrypt or decrypt data a chunk at a time.
+///
+/// Created using [`Crypto::cipher_stream()`].
+pub trait CipherStream {
+    /// Process the next chunk of data, appending any output to `output`.
+    ///
+    /// Block ciphers may hold on to part of the input until they have a full
+    /// block (or, when decrypting AES-CBC, until they know which block is the
+    /// last one), so `output` may grow by more or less than `data.len()`.
+    fn update(
+        &mut self,
+        data: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), InternalError>;
+
+    /// Process whatever is left, appending it to `output` (e.g. the final
+    /// block of padding).
+    ///
+    /// # Note
+    ///
+    /// The stream can't be used after it has been finalized.
+    fn finalize(&mut self, output: &mut Vec<u8>) -> Result<(), InternalError>;
+}
+
 /// Cryptography routines used in the signal protocol.
 pub trait Crypto: RefUnwindSafe {
     /// Fill the provided buffer with some random bytes.
@@ -114,6 +156,115 @@ pub trait Crypto: RefUnwindSafe {
         iv: &[u8],
         data: &[u8],
     ) -> Result<Vec<u8>, InternalError>;
+
+    /// Encrypt `data` in place, growing it if padding needs to be added.
+    ///
+    /// The default implementation copies `data` into [`Crypto::encrypt()`],
+    /// so providers should override it if they can avoid the allocation.
+    fn encrypt_in_place(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let ciphertext = self.encrypt(cipher, key, iv, data)?;
+        replace_wiped(data, ciphertext);
+        Ok(())
+    }
+
+    /// Decrypt `data` in place, shrinking it if padding needs to be removed.
+    ///
+    /// The default implementation copies `data` into [`Crypto::decrypt()`],
+    /// so providers should override it if they can avoid the allocation.
+    fn decrypt_in_place(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let plaintext = self.decrypt(cipher, key, iv, data)?;
+        replace_wiped(data, plaintext);
+        Ok(())
+    }
+
+    /// Start encrypting or decrypting data a chunk at a time, so large
+    /// payloads don't need to be held in memory twice.
+    ///
+    /// The default implementation buffers everything it is given and calls
+    /// [`Crypto::encrypt()`] or [`Crypto::decrypt()`] when the stream is
+    /// finalized.
+    fn cipher_stream(
+        &self,
+        mode: CipherMode,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn CipherStream + '_>, InternalError> {
+        Ok(Box::new(BufferedCipherStream {
+            crypto: self,
+            mode,
+            cipher,
+            key: Zeroizing::new(key.to_vec()),
+            iv: iv.to_vec(),
+            data: Zeroizing::new(Vec::new()),
+        }))
+    }
+}
+
+/// Compare two MACs or digests without leaking where they differ.
+pub(crate) fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+
+    left.iter()
+        .zip(right)
+        .fold(0, |acc, (l, r)| acc | (l ^ r))
+        == 0
+}
+
+/// Overwrite `data` with `replacement`, wiping the old contents first.
+fn replace_wiped(data: &mut Vec<u8>, replacement: Vec<u8>) {
+    data.zeroize();
+    *data = replacement;
+}
+
+/// The [`CipherStream`] used when a [`Crypto`] provider doesn't have its own.
+struct BufferedCipherStream<'a, C: ?Sized> {
+    crypto: &'a C,
+    mode: CipherMode,
+    cipher: SignalCipherType,
+    key: Zeroizing<Vec<u8>>,
+    iv: Vec<u8>,
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl<'a, C: Crypto + ?Sized> CipherStream for BufferedCipherStream<'a, C> {
+    fn update(
+        &mut self,
+        data: &[u8],
+        _output: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        self.data.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn finalize(&mut self, output: &mut Vec<u8>) -> Result<(), InternalError> {
+        let (crypto, cipher) = (self.crypto, self.cipher);
+        let (key, iv, data) = (&self.key[..], &self.iv[..], &self.data[..]);
+
+        let mut result = match self.mode {
+            CipherMode::Encrypt => crypto.encrypt(cipher, key, iv, data),
+            CipherMode::Decrypt => crypto.decrypt(cipher, key, iv, data),
+        }?;
+        self.data.zeroize();
+
+        output.extend_from_slice(&result);
+        result.zeroize();
+        Ok(())
+    }
 }
 
 /// A simple vtable ([`signal_crypto_provider`]) and set of trampolines to let C
@@ -532,4 +683,103 @@ mod crypto_tests {
         assert_eq!(plain_text_native, data);
         assert_eq!(plain_text_openssl, data);
     }
+
+    #[cfg(all(feature = "crypto-native", feature = "crypto-ring"))]
+    #[test]
+    fn test_ring_hashes_match_native() {
+        let native_crypto = DefaultCrypto::default();
+        let ring_crypto = RingCrypto::default();
+        let key = [0x0b; 20];
+
+        let mut native_mac = native_crypto.hmac_sha256(&key).unwrap();
+        let mut ring_mac = ring_crypto.hmac_sha256(&key).unwrap();
+        let mut native_digest = native_crypto.sha512_digest().unwrap();
+        let mut ring_digest = ring_crypto.sha512_digest().unwrap();
+
+        // the second round makes sure finalize() resets the context
+        for data in &[&b"Hi There"[..], &b"what do ya want for nothing?"[..]] {
+            native_mac.update(data).unwrap();
+            ring_mac.update(data).unwrap();
+            assert_eq!(
+                native_mac.finalize().unwrap(),
+                ring_mac.finalize().unwrap()
+            );
+
+            native_digest.update(data).unwrap();
+            ring_digest.update(data).unwrap();
+            assert_eq!(
+                native_digest.finalize().unwrap(),
+                ring_digest.finalize().unwrap()
+            );
+        }
+    }
+
+    #[cfg(feature = "crypto-native")]
+    #[test]
+    fn native_crypto_conforms() {
+        conformance::check_all(&DefaultCrypto::default());
+    }
+
+    /// Only implements the required methods, so the default in-place and
+    /// streaming implementations get used.
+    #[cfg(feature = "crypto-native")]
+    struct RequiredMethodsOnly(DefaultCrypto);
+
+    #[cfg(feature = "crypto-native")]
+    impl Crypto for RequiredMethodsOnly {
+        fn fill_random(&self, buffer: &mut [u8]) -> Result<(), InternalError> {
+            self.0.fill_random(buffer)
+        }
+
+        fn hmac_sha256(
+            &self,
+            key: &[u8],
+        ) -> Result<Box<dyn Sha256Hmac>, InternalError> {
+            self.0.hmac_sha256(key)
+        }
+
+        fn sha512_digest(
+            &self,
+        ) -> Result<Box<dyn Sha512Digest>, InternalError> {
+            self.0.sha512_digest()
+        }
+
+        fn encrypt(
+            &self,
+            cipher: SignalCipherType,
+            key: &[u8],
+            iv: &[u8],
+            data: &[u8],
+        ) -> Result<Vec<u8>, InternalError> {
+            self.0.encrypt(cipher, key, iv, data)
+        }
+
+        fn decrypt(
+            &self,
+            cipher: SignalCipherType,
+            key: &[u8],
+            iv: &[u8],
+            data: &[u8],
+        ) -> Result<Vec<u8>, InternalError> {
+            self.0.decrypt(cipher, key, iv, data)
+        }
+    }
+
+    #[cfg(feature = "crypto-native")]
+    #[test]
+    fn default_in_place_and_streaming_methods_conform() {
+        conformance::check_all(&RequiredMethodsOnly(DefaultCrypto));
+    }
+
+    #[cfg(feature = "crypto-openssl")]
+    #[test]
+    fn openssl_crypto_conforms() {
+        conformance::check_all(&OpenSSLCrypto::default());
+    }
+
+    #[cfg(feature = "crypto-ring")]
+    #[test]
+    fn ring_crypto_conforms() {
+        conformance::check_all(&RingCrypto::default());
+    }
 }
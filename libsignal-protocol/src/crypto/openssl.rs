@@ -6,8 +6,13 @@ use openssl::{
     symm::{Cipher, Crypter, Mode},
 };
 
+use zeroize::Zeroize;
+
 use crate::{
-    crypto::{Crypto, Sha256Hmac, Sha512Digest, SignalCipherType},
+    crypto::{
+        CipherMode, CipherStream, Crypto, Sha256Hmac, Sha512Digest,
+        SignalCipherType,
+    },
     errors::InternalError,
 };
 
@@ -27,14 +32,15 @@ rental! {
 pub struct OpenSSLCrypto;
 
 impl OpenSSLCrypto {
-    fn crypter(
+    /// Create a [`Crypter`] for the cipher, returning it alongside the
+    /// cipher's block size.
+    fn new_crypter(
         &self,
         mode: Mode,
         cipher: SignalCipherType,
         key: &[u8],
         iv: &[u8],
-        data: &[u8],
-    ) -> Result<Vec<u8>, InternalError> {
+    ) -> Result<(Crypter, usize), InternalError> {
         let signal_cipher_type = match (cipher, key.len()) {
             (SignalCipherType::AesCtrNoPadding, 16) => Cipher::aes_128_ctr(),
             (SignalCipherType::AesCtrNoPadding, 24) => {
@@ -50,27 +56,48 @@ impl OpenSSLCrypto {
                     .expect("OpenSSL should have AES_192_CBC !!")
             }
             (SignalCipherType::AesCbcPkcs5, 32) => Cipher::aes_256_cbc(),
-            _ => unreachable!(),
+            // not a valid AES key size
+            _ => return Err(InternalError::InvalidArgument),
         };
         let block_size = signal_cipher_type.block_size();
+        if signal_cipher_type.iv_len() != Some(iv.len()) {
+            return Err(InternalError::InvalidArgument);
+        }
         let mut crypter = Crypter::new(signal_cipher_type, mode, key, Some(iv))
             .map_err(|_e| InternalError::Unknown)?;
-        let mut result = match cipher {
-            SignalCipherType::AesCtrNoPadding => {
-                crypter.pad(false); // in ctr we need to set padding to false
-                vec![0u8; data.len()]
-            }
-            SignalCipherType::AesCbcPkcs5 => vec![0u8; data.len() + block_size],
+        if let SignalCipherType::AesCtrNoPadding = cipher {
+            crypter.pad(false); // in ctr we need to set padding to false
+        }
+
+        Ok((crypter, block_size))
+    }
+
+    fn crypter(
+        &self,
+        mode: Mode,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        let (crypter, block_size) = self.new_crypter(mode, cipher, key, iv)?;
+        let mut stream = OpenSSLCipherStream {
+            crypter: Some(crypter),
+            block_size,
         };
-        let count = crypter
-            .update(data, &mut result)
-            .map_err(|_e| InternalError::Unknown)?;
+        let mut result = Vec::with_capacity(data.len() + block_size);
 
-        let rest = crypter
-            .finalize(&mut result[count..])
-            .map_err(|_e| InternalError::Unknown)?;
-        result.truncate(count + rest);
-        Ok(result)
+        let outcome = stream
+            .update(data, &mut result)
+            .and_then(|_| stream.finalize(&mut result));
+
+        match outcome {
+            Ok(_) => Ok(result),
+            Err(e) => {
+                result.zeroize();
+                Err(e)
+            },
+        }
     }
 }
 
@@ -118,6 +145,75 @@ impl Crypto for OpenSSLCrypto {
     ) -> Result<Vec<u8>, InternalError> {
         self.crypter(Mode::Decrypt, cipher, key, iv, data)
     }
+
+    fn cipher_stream(
+        &self,
+        mode: CipherMode,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn CipherStream + '_>, InternalError> {
+        let mode = match mode {
+            CipherMode::Encrypt => Mode::Encrypt,
+            CipherMode::Decrypt => Mode::Decrypt,
+        };
+        let (crypter, block_size) = self.new_crypter(mode, cipher, key, iv)?;
+
+        Ok(Box::new(OpenSSLCipherStream {
+            crypter: Some(crypter),
+            block_size,
+        }))
+    }
+}
+
+/// A [`CipherStream`] backed by OpenSSL's own incremental [`Crypter`].
+struct OpenSSLCipherStream {
+    /// `None` once the stream has been finalized.
+    crypter: Option<Crypter>,
+    block_size: usize,
+}
+
+impl CipherStream for OpenSSLCipherStream {
+    fn update(
+        &mut self,
+        data: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let crypter =
+            self.crypter.as_mut().ok_or(InternalError::InvalidArgument)?;
+        let start = output.len();
+        // OpenSSL may write up to one extra block
+        output.resize(start + data.len() + self.block_size, 0);
+
+        match crypter.update(data, &mut output[start..]) {
+            Ok(count) => {
+                output.truncate(start + count);
+                Ok(())
+            },
+            Err(_) => {
+                output.truncate(start);
+                Err(InternalError::Unknown)
+            },
+        }
+    }
+
+    fn finalize(&mut self, output: &mut Vec<u8>) -> Result<(), InternalError> {
+        let mut crypter =
+            self.crypter.take().ok_or(InternalError::InvalidArgument)?;
+        let start = output.len();
+        output.resize(start + self.block_size, 0);
+
+        match crypter.finalize(&mut output[start..]) {
+            Ok(count) => {
+                output.truncate(start + count);
+                Ok(())
+            },
+            Err(_) => {
+                output.truncate(start);
+                Err(InternalError::Unknown)
+            },
+        }
+    }
 }
 
 impl Default for OpenSSLCrypto {
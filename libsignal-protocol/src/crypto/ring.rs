@@ -0,0 +1,140 @@
+use ring::{
+    digest,
+    hmac::{self, HMAC_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::{
+    crypto::{
+        symmetric, CipherMode, CipherStream, Crypto, Sha256Hmac,
+        Sha512Digest, SignalCipherType,
+    },
+    errors::InternalError,
+};
+
+/// Cryptography routines using the `ring` crate.
+///
+/// `ring` doesn't expose raw AES-CBC or AES-CTR, so those are provided by the
+/// same RustCrypto crates [`DefaultCrypto`](crate::crypto::DefaultCrypto)
+/// uses.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RingCrypto;
+
+impl Crypto for RingCrypto {
+    fn fill_random(&self, buffer: &mut [u8]) -> Result<(), InternalError> {
+        SystemRandom::new()
+            .fill(buffer)
+            .map_err(|_| InternalError::Unknown)
+    }
+
+    fn hmac_sha256(
+        &self,
+        key: &[u8],
+    ) -> Result<Box<dyn Sha256Hmac>, InternalError> {
+        Ok(Box::new(RingHmac::new(key)))
+    }
+
+    fn sha512_digest(&self) -> Result<Box<dyn Sha512Digest>, InternalError> {
+        Ok(Box::new(RingSha512::new()))
+    }
+
+    fn encrypt(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        symmetric::crypter(CipherMode::Encrypt, cipher, key, iv, data)
+    }
+
+    fn decrypt(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        symmetric::crypter(CipherMode::Decrypt, cipher, key, iv, data)
+    }
+
+    fn encrypt_in_place(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        symmetric::crypt_in_place(CipherMode::Encrypt, cipher, key, iv, data)
+    }
+
+    fn decrypt_in_place(
+        &self,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+        data: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        symmetric::crypt_in_place(CipherMode::Decrypt, cipher, key, iv, data)
+    }
+
+    fn cipher_stream(
+        &self,
+        mode: CipherMode,
+        cipher: SignalCipherType,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn CipherStream + '_>, InternalError> {
+        symmetric::stream(mode, cipher, key, iv)
+    }
+}
+
+/// `ring`'s contexts are consumed when they're finished, so we keep the key
+/// around to start a fresh one.
+struct RingHmac {
+    key: hmac::Key,
+    ctx: hmac::Context,
+}
+
+impl RingHmac {
+    fn new(key: &[u8]) -> RingHmac {
+        let key = hmac::Key::new(HMAC_SHA256, key);
+        let ctx = hmac::Context::with_key(&key);
+
+        RingHmac { key, ctx }
+    }
+}
+
+impl Sha256Hmac for RingHmac {
+    fn update(&mut self, data: &[u8]) -> Result<(), InternalError> {
+        self.ctx.update(data);
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Vec<u8>, InternalError> {
+        let fresh = hmac::Context::with_key(&self.key);
+        let tag = std::mem::replace(&mut self.ctx, fresh).sign();
+
+        Ok(tag.as_ref().to_vec())
+    }
+}
+
+struct RingSha512(digest::Context);
+
+impl RingSha512 {
+    fn new() -> RingSha512 { RingSha512(digest::Context::new(&digest::SHA512)) }
+}
+
+impl Sha512Digest for RingSha512 {
+    fn update(&mut self, data: &[u8]) -> Result<(), InternalError> {
+        self.0.update(data);
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> Result<Vec<u8>, InternalError> {
+        let fresh = digest::Context::new(&digest::SHA512);
+        let digest = std::mem::replace(&mut self.0, fresh).finish();
+
+        Ok(digest.as_ref().to_vec())
+    }
+}
@@ -0,0 +1,344 @@
+//! AES routines built on the RustCrypto crates, shared by the providers which
+//! don't have their own.
+
+use aes::{
+    cipher::{NewStreamCipher, SyncStreamCipher},
+    Aes128, Aes192, Aes256,
+};
+use aes_ctr::{Aes128Ctr, Aes192Ctr, Aes256Ctr};
+use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
+use zeroize::Zeroize;
+
+use crate::{
+    crypto::{CipherMode, CipherStream, SignalCipherType},
+    errors::InternalError,
+};
+
+// FWI, PKCS5 padding is a subset of PKCS7
+type Aes128Cbc = Cbc<Aes128, Pkcs7>;
+type Aes192Cbc = Cbc<Aes192, Pkcs7>;
+type Aes256Cbc = Cbc<Aes256, Pkcs7>;
+
+const AES_BLOCK_SIZE: usize = 16;
+
+pub(crate) fn crypter(
+    mode: CipherMode,
+    cipher: SignalCipherType,
+    key: &[u8],
+    iv: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>, InternalError> {
+    let mut buffer = data.to_vec();
+
+    match crypt_in_place(mode, cipher, key, iv, &mut buffer) {
+        Ok(_) => Ok(buffer),
+        Err(e) => {
+            buffer.zeroize();
+            Err(e)
+        },
+    }
+}
+
+pub(crate) fn crypt_in_place(
+    mode: CipherMode,
+    cipher: SignalCipherType,
+    key: &[u8],
+    iv: &[u8],
+    data: &mut Vec<u8>,
+) -> Result<(), InternalError> {
+    match cipher {
+        SignalCipherType::AesCtrNoPadding => {
+            ctr(key, iv)?.apply_keystream(data);
+            Ok(())
+        },
+        SignalCipherType::AesCbcPkcs5 => {
+            let mut cbc = AesCbc::new(key, iv)?;
+
+            match mode {
+                CipherMode::Encrypt => {
+                    pad(data);
+                    cbc.encrypt_blocks(data);
+                    Ok(())
+                },
+                CipherMode::Decrypt => {
+                    let partial_block = data.len() % AES_BLOCK_SIZE;
+                    if data.is_empty() || partial_block != 0 {
+                        return Err(InternalError::Unknown);
+                    }
+
+                    cbc.decrypt_blocks(data);
+                    unpad(data)
+                },
+            }
+        },
+    }
+}
+
+pub(crate) fn stream(
+    mode: CipherMode,
+    cipher: SignalCipherType,
+    key: &[u8],
+    iv: &[u8],
+) -> Result<Box<dyn CipherStream>, InternalError> {
+    match cipher {
+        SignalCipherType::AesCtrNoPadding => {
+            Ok(Box::new(CtrStream(ctr(key, iv)?)))
+        },
+        SignalCipherType::AesCbcPkcs5 => Ok(Box::new(CbcStream {
+            mode,
+            cbc: Some(AesCbc::new(key, iv)?),
+            pending: [0; AES_BLOCK_SIZE],
+            pending_len: 0,
+        })),
+    }
+}
+
+fn ctr(
+    key: &[u8],
+    iv: &[u8],
+) -> Result<Box<dyn SyncStreamCipher>, InternalError> {
+    if iv.len() != AES_BLOCK_SIZE {
+        return Err(InternalError::InvalidArgument);
+    }
+
+    let cipher: Box<dyn SyncStreamCipher> = match key.len() {
+        16 => Box::new(
+            Aes128Ctr::new_var(key, iv).map_err(|_| InternalError::Unknown)?,
+        ),
+        24 => Box::new(
+            Aes192Ctr::new_var(key, iv).map_err(|_| InternalError::Unknown)?,
+        ),
+        32 => Box::new(
+            Aes256Ctr::new_var(key, iv).map_err(|_| InternalError::Unknown)?,
+        ),
+        // not a valid AES key size
+        _ => return Err(InternalError::InvalidArgument),
+    };
+
+    Ok(cipher)
+}
+
+/// Add PKCS#5 padding, always adding a full block if `data` is already
+/// aligned.
+fn pad(data: &mut Vec<u8>) {
+    let padding = AES_BLOCK_SIZE - data.len() % AES_BLOCK_SIZE;
+    data.resize(data.len() + padding, padding as u8);
+}
+
+/// Remove the PKCS#5 padding added by [`pad()`], wiping `data` if it's
+/// invalid.
+fn unpad(data: &mut Vec<u8>) -> Result<(), InternalError> {
+    let padding = match data.last() {
+        Some(&last) => usize::from(last),
+        None => 0,
+    };
+    let valid = (1..=AES_BLOCK_SIZE).contains(&padding)
+        && padding <= data.len()
+        && data[data.len() - padding..]
+            .iter()
+            .all(|&b| usize::from(b) == padding);
+
+    if valid {
+        data.truncate(data.len() - padding);
+        Ok(())
+    } else {
+        data.zeroize();
+        Err(InternalError::Unknown)
+    }
+}
+
+/// AES-CBC with whichever key size we were given.
+enum AesCbc {
+    Aes128(Aes128Cbc),
+    Aes192(Aes192Cbc),
+    Aes256(Aes256Cbc),
+}
+
+impl AesCbc {
+    fn new(key: &[u8], iv: &[u8]) -> Result<AesCbc, InternalError> {
+        if iv.len() != AES_BLOCK_SIZE {
+            return Err(InternalError::InvalidArgument);
+        }
+
+        let cbc = match key.len() {
+            16 => AesCbc::Aes128(
+                Aes128Cbc::new_var(key, iv)
+                    .map_err(|_| InternalError::Unknown)?,
+            ),
+            24 => AesCbc::Aes192(
+                Aes192Cbc::new_var(key, iv)
+                    .map_err(|_| InternalError::Unknown)?,
+            ),
+            32 => AesCbc::Aes256(
+                Aes256Cbc::new_var(key, iv)
+                    .map_err(|_| InternalError::Unknown)?,
+            ),
+            // not a valid AES key size
+            _ => return Err(InternalError::InvalidArgument),
+        };
+
+        Ok(cbc)
+    }
+
+    /// Encrypt whole blocks, chaining on from the previous call.
+    fn encrypt_blocks(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(AES_BLOCK_SIZE) {
+            let block = std::slice::from_mut(chunk.into());
+
+            match *self {
+                AesCbc::Aes128(ref mut c) => c.encrypt_blocks(block),
+                AesCbc::Aes192(ref mut c) => c.encrypt_blocks(block),
+                AesCbc::Aes256(ref mut c) => c.encrypt_blocks(block),
+            }
+        }
+    }
+
+    /// Decrypt whole blocks, chaining on from the previous call.
+    fn decrypt_blocks(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_mut(AES_BLOCK_SIZE) {
+            let block = std::slice::from_mut(chunk.into());
+
+            match *self {
+                AesCbc::Aes128(ref mut c) => c.decrypt_blocks(block),
+                AesCbc::Aes192(ref mut c) => c.decrypt_blocks(block),
+                AesCbc::Aes256(ref mut c) => c.decrypt_blocks(block),
+            }
+        }
+    }
+}
+
+struct CtrStream(Box<dyn SyncStreamCipher>);
+
+impl CipherStream for CtrStream {
+    fn update(
+        &mut self,
+        data: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        let start = output.len();
+        output.extend_from_slice(data);
+        self.0.apply_keystream(&mut output[start..]);
+
+        Ok(())
+    }
+
+    fn finalize(
+        &mut self,
+        _output: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        Ok(())
+    }
+}
+
+/// Streaming AES-CBC.
+///
+/// Anything which doesn't fill a block is kept in `pending` until the next
+/// call. When decrypting we also hold back the last full block, because it
+/// might be the one with the padding.
+struct CbcStream {
+    mode: CipherMode,
+    /// `None` once the stream has been finalized.
+    cbc: Option<AesCbc>,
+    pending: [u8; AES_BLOCK_SIZE],
+    pending_len: usize,
+}
+
+impl CbcStream {
+    fn process(&mut self, output: &mut Vec<u8>, data: &[u8]) {
+        let start = output.len();
+        output.extend_from_slice(data);
+
+        let blocks = &mut output[start..];
+
+        if let Some(ref mut cbc) = self.cbc {
+            match self.mode {
+                CipherMode::Encrypt => cbc.encrypt_blocks(blocks),
+                CipherMode::Decrypt => cbc.decrypt_blocks(blocks),
+            }
+        }
+    }
+
+    /// How many bytes of `remaining` input can be processed immediately?
+    fn ready(&self, remaining: usize) -> usize {
+        let whole_blocks = remaining - remaining % AES_BLOCK_SIZE;
+
+        if self.mode == CipherMode::Decrypt && whole_blocks == remaining {
+            whole_blocks.saturating_sub(AES_BLOCK_SIZE)
+        } else {
+            whole_blocks
+        }
+    }
+}
+
+impl CipherStream for CbcStream {
+    fn update(
+        &mut self,
+        mut data: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), InternalError> {
+        if self.cbc.is_none() {
+            return Err(InternalError::InvalidArgument);
+        }
+
+        // top up a partially filled block first
+        if self.pending_len > 0 {
+            let wanted = AES_BLOCK_SIZE - self.pending_len;
+            let taken = wanted.min(data.len());
+            self.pending[self.pending_len..self.pending_len + taken]
+                .copy_from_slice(&data[..taken]);
+            self.pending_len += taken;
+            data = &data[taken..];
+
+            let is_last_block =
+                self.mode == CipherMode::Decrypt && data.is_empty();
+            if self.pending_len == AES_BLOCK_SIZE && !is_last_block {
+                let block = self.pending;
+                self.process(output, &block);
+                self.pending.zeroize();
+                self.pending_len = 0;
+            }
+        }
+
+        if self.pending_len == 0 {
+            let ready = self.ready(data.len());
+            self.process(output, &data[..ready]);
+
+            let rest = &data[ready..];
+            self.pending[..rest.len()].copy_from_slice(rest);
+            self.pending_len = rest.len();
+        }
+
+        Ok(())
+    }
+
+    fn finalize(&mut self, output: &mut Vec<u8>) -> Result<(), InternalError> {
+        let mut cbc = self.cbc.take().ok_or(InternalError::InvalidArgument)?;
+        let mut last = self.pending[..self.pending_len].to_vec();
+        self.pending.zeroize();
+        self.pending_len = 0;
+
+        match self.mode {
+            CipherMode::Encrypt => {
+                pad(&mut last);
+                cbc.encrypt_blocks(&mut last);
+            },
+            CipherMode::Decrypt => {
+                if last.len() != AES_BLOCK_SIZE {
+                    last.zeroize();
+                    return Err(InternalError::Unknown);
+                }
+
+                cbc.decrypt_blocks(&mut last);
+                unpad(&mut last)?;
+            },
+        }
+
+        output.extend_from_slice(&last);
+        last.zeroize();
+        Ok(())
+    }
+}
+
+impl Drop for CbcStream {
+    fn drop(&mut self) { self.pending.zeroize(); }
+}
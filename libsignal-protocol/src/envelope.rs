@@ -0,0 +1,294 @@
+//! Envelopes carry an encrypted message along with the routing metadata
+//! needed to deliver and decrypt it.
+//!
+//! The sender wraps each [`CiphertextMessage`] in an [`Envelope`] saying who
+//! it came from, which of the recipient's registrations it was encrypted for
+//! and when it was sent. On the receiving side a [`Dispatcher`] decodes the
+//! envelope and hands the message to the right [`SessionCipher`] method.
+//!
+//! Envelopes are serialized as protobufs:
+//!
+//! ```protobuf
+//! message Envelope {
+//!   uint32 type                        = 1;
+//!   bytes  source_name                 = 2;
+//!   int32  source_device               = 3;
+//!   uint32 destination_registration_id = 4;
+//!   uint64 timestamp                   = 5; // milliseconds since the epoch
+//!   bytes  content                     = 6;
+//!   optional string server_guid        = 7;
+//! }
+//! ```
+
+use std::{
+    convert::TryFrom,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use prost::Message;
+
+use crate::{
+    errors::{Error, InternalError},
+    messages::{
+        CiphertextMessage, CiphertextType, PreKeySignalMessage, SignalMessage,
+    },
+    Address, Buffer, Context, DecryptedMessage, Deserializable, Padding,
+    Serializable, SessionCipher, SessionLimits, StoreContext,
+};
+
+/// A [`CiphertextMessage`] and the metadata needed to route it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    message_type: CiphertextType,
+    source: Address,
+    destination_registration_id: u32,
+    timestamp: SystemTime,
+    server_guid: Option<String>,
+    content: Vec<u8>,
+}
+
+impl Envelope {
+    /// Wrap a message sent by `source` to the recipient with the given
+    /// registration ID.
+    pub fn new(
+        message: &CiphertextMessage,
+        source: Address,
+        destination_registration_id: u32,
+        timestamp: SystemTime,
+    ) -> Result<Envelope, Error> {
+        Ok(Envelope {
+            message_type: message.get_type()?,
+            source,
+            destination_registration_id,
+            timestamp,
+            server_guid: None,
+            content: message.serialize()?.as_slice().to_vec(),
+        })
+    }
+
+    /// Which type of message does this envelope contain?
+    pub const fn message_type(&self) -> CiphertextType { self.message_type }
+
+    /// Who sent the message.
+    pub const fn source(&self) -> &Address { &self.source }
+
+    /// The registration ID of the recipient the message was encrypted for.
+    pub const fn destination_registration_id(&self) -> u32 {
+        self.destination_registration_id
+    }
+
+    /// When the message was sent.
+    pub const fn timestamp(&self) -> SystemTime { self.timestamp }
+
+    /// The unique ID the server assigned to this envelope, if any.
+    pub fn server_guid(&self) -> Option<&str> { self.server_guid.as_deref() }
+
+    /// Record the unique ID the server assigned to this envelope.
+    pub fn set_server_guid<S: Into<String>>(&mut self, guid: S) {
+        self.server_guid = Some(guid.into());
+    }
+
+    /// The serialized message.
+    pub fn content(&self) -> &[u8] { &self.content }
+}
+
+impl Serializable for Envelope {
+    fn serialize(&self) -> Result<Buffer, Error> {
+        let timestamp = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| InternalError::InvalidArgument)?;
+        let timestamp = u64::try_from(timestamp.as_millis())
+            .map_err(|_| InternalError::InvalidArgument)?;
+
+        let proto = proto::Envelope {
+            r#type: self.message_type as u32,
+            source_name: self.source.bytes().to_vec(),
+            source_device: self.source.device_id(),
+            destination_registration_id: self.destination_registration_id,
+            timestamp,
+            content: self.content.clone(),
+            server_guid: self.server_guid.clone(),
+        };
+
+        let mut buffer = Vec::with_capacity(proto.encoded_len());
+        proto
+            .encode(&mut buffer)
+            .map_err(|_| InternalError::SerializationError)?;
+
+        Ok(Buffer::from(buffer))
+    }
+}
+
+impl Deserializable for Envelope {
+    fn deserialize(_ctx: &Context, data: &[u8]) -> Result<Self, Error> {
+        let proto = proto::Envelope::decode(data)
+            .map_err(|_| InternalError::InvalidProtoBuf)?;
+        // the timestamp comes off the wire, and SystemTime's range is
+        // platform-specific (e.g. much narrower on Windows)
+        let timestamp = UNIX_EPOCH
+            .checked_add(Duration::from_millis(proto.timestamp))
+            .ok_or(InternalError::InvalidProtoBuf)?;
+
+        Ok(Envelope {
+            message_type: CiphertextType::from_raw(proto.r#type)?,
+            source: Address::new(&proto.source_name, proto.source_device),
+            destination_registration_id: proto.destination_registration_id,
+            timestamp,
+            server_guid: proto.server_guid,
+            content: proto.content,
+        })
+    }
+}
+
+/// Decrypts incoming [`Envelope`]s using the session with whoever sent them.
+#[derive(Debug, Clone)]
+pub struct Dispatcher {
+    ctx: Context,
+    store_ctx: StoreContext,
+    padding: Padding,
+    limits: SessionLimits,
+}
+
+impl Dispatcher {
+    /// Create a new [`Dispatcher`] which decrypts messages using the sessions
+    /// in `store_ctx`.
+    pub fn new(ctx: &Context, store_ctx: &StoreContext) -> Dispatcher {
+        Dispatcher {
+            ctx: ctx.clone(),
+            store_ctx: store_ctx.clone(),
+            padding: Padding::None,
+            limits: SessionLimits::default(),
+        }
+    }
+
+    /// How decrypted messages are expected to be padded.
+    pub const fn padding(&self) -> &Padding { &self.padding }
+
+    /// Strip padding from decrypted messages (see
+    /// [`SessionCipher::set_padding()`]).
+    pub fn set_padding(&mut self, padding: Padding) { self.padding = padding; }
+
+    /// How much old key material is kept for each session.
+    pub const fn limits(&self) -> SessionLimits { self.limits }
+
+    /// Trim sessions down to these limits (see
+    /// [`SessionCipher::set_limits()`]).
+    // a const fn can't take `&mut self` until Rust 1.83
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn set_limits(&mut self, limits: SessionLimits) {
+        self.limits = limits;
+    }
+
+    /// Decode a serialized [`Envelope`] and decrypt its contents.
+    pub fn dispatch(
+        &self,
+        data: &[u8],
+    ) -> Result<(Envelope, DecryptedMessage), Error> {
+        let envelope = Envelope::deserialize(&self.ctx, data)?;
+        let decrypted = self.decrypt(&envelope)?;
+
+        Ok((envelope, decrypted))
+    }
+
+    /// Decrypt the message inside an [`Envelope`] with
+    /// [`SessionCipher::decrypt()`].
+    ///
+    /// Sender key messages aren't encrypted with a [`SessionCipher`], so they
+    /// are rejected with [`Error::UnsupportedMessageType`].
+    pub fn decrypt(
+        &self,
+        envelope: &Envelope,
+    ) -> Result<DecryptedMessage, Error> {
+        let message: CiphertextMessage = match envelope.message_type() {
+            CiphertextType::PreKey => {
+                PreKeySignalMessage::deserialize(&self.ctx, envelope.content())?
+                    .into()
+            },
+            CiphertextType::Signal => {
+                SignalMessage::deserialize(&self.ctx, envelope.content())?
+                    .into()
+            },
+            other => return Err(Error::UnsupportedMessageType(other)),
+        };
+
+        let mut cipher =
+            SessionCipher::new(&self.ctx, &self.store_ctx, envelope.source())?;
+        cipher.set_padding(self.padding.clone());
+        cipher.set_limits(self.limits);
+        cipher.decrypt(&message)
+    }
+}
+
+mod proto {
+    /// The wire format for an [`Envelope`](super::Envelope).
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Envelope {
+        #[prost(uint32, tag = "1")]
+        pub r#type: u32,
+        #[prost(bytes, tag = "2")]
+        pub source_name: Vec<u8>,
+        #[prost(int32, tag = "3")]
+        pub source_device: i32,
+        #[prost(uint32, tag = "4")]
+        pub destination_registration_id: u32,
+        #[prost(uint64, tag = "5")]
+        pub timestamp: u64,
+        #[prost(bytes, tag = "6")]
+        pub content: Vec<u8>,
+        #[prost(string, optional, tag = "7")]
+        pub server_guid: Option<String>,
+    }
+}
+
+#[cfg(all(test, feature = "crypto-native"))]
+mod tests {
+    use super::*;
+
+    fn envelope(message_type: CiphertextType) -> Envelope {
+        Envelope {
+            message_type,
+            source: Address::new("+14159998888", 3),
+            destination_registration_id: 1234,
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_600_000_000_123),
+            server_guid: Some(String::from("a-guid")),
+            content: vec![1, 2, 3, 4, 5],
+        }
+    }
+
+    #[test]
+    fn envelopes_round_trip() {
+        let ctx = Context::default();
+        let original = envelope(CiphertextType::PreKey);
+
+        let serialized = original.serialize().unwrap();
+        let got = Envelope::deserialize(&ctx, serialized.as_slice()).unwrap();
+
+        assert_eq!(got, original);
+    }
+
+    #[test]
+    fn unknown_message_types_are_rejected() {
+        let ctx = Context::default();
+        let proto = proto::Envelope {
+            r#type: 42,
+            ..Default::default()
+        };
+        let mut serialized = Vec::new();
+        proto.encode(&mut serialized).unwrap();
+
+        match Envelope::deserialize(&ctx, &serialized) {
+            Err(Error::InternalError(
+                InternalError::UnknownCiphertextType(42),
+            )) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn garbage_is_not_an_envelope() {
+        let ctx = Context::default();
+
+        assert!(Envelope::deserialize(&ctx, &[0xff; 8]).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use std::convert::TryFrom;
 
+use crate::messages::CiphertextType;
+
 #[derive(Debug, thiserror::Error)]
 #[allow(missing_docs)]
 pub enum Error {
@@ -27,11 +29,24 @@ pub enum Error {
     IdentityKeyGetError,
     #[error("a missing field is required: {0}")]
     MissingRequiredField(RequiredField),
+    #[error("the digest doesn't match")]
+    DigestMismatch,
+    #[error("the certificate is invalid")]
+    InvalidCertificate,
+    #[error("the certificate has expired")]
+    ExpiredCertificate,
+    #[error("the message's padding is malformed")]
+    InvalidPadding,
+    #[error("session limits can't be raised above libsignal-protocol-c's")]
+    SessionLimitTooHigh,
+    #[error("{0:?} messages aren't supported here")]
+    UnsupportedMessageType(CiphertextType),
     #[error("unknown error: {reason}")]
     Unknown { reason: String },
 }
 
 #[derive(Debug, Copy, Clone, thiserror::Error)]
+#[allow(missing_docs)]
 pub enum RequiredField {
     #[error("registration ID")]
     RegistrationId,
@@ -39,6 +54,22 @@ pub enum RequiredField {
     DeviceId,
     #[error("identity key is missing")]
     IdentityKey,
+    #[error("our identity key")]
+    OurIdentityKey,
+    #[error("our base key")]
+    OurBaseKey,
+    #[error("our signed pre-key")]
+    OurSignedPreKey,
+    #[error("our ratchet key")]
+    OurRatchetKey,
+    #[error("their identity key")]
+    TheirIdentityKey,
+    #[error("their base key")]
+    TheirBaseKey,
+    #[error("their signed pre-key")]
+    TheirSignedPreKey,
+    #[error("their ratchet key")]
+    TheirRatchetKey,
 }
 
 impl Error {
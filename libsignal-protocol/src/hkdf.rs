@@ -2,7 +2,7 @@ use crate::{
     context::ContextInner,
     errors::{Error, FromInternalErrorCode, InternalError},
     raw_ptr::Raw,
-    Context,
+    Context, SecretBuffer,
 };
 use std::{ptr, rc::Rc};
 
@@ -38,7 +38,7 @@ impl HMACBasedKeyDerivationFunction {
         input_key_material: &[u8],
         salt: &[u8],
         info: &[u8],
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<SecretBuffer, Error> {
         unsafe {
             let mut secret_buf = ptr::null_mut();
             let prk_len = sys::hkdf_derive_secrets(
@@ -59,17 +59,7 @@ impl HMACBasedKeyDerivationFunction {
                     .into());
             }
 
-            let prk_len = prk_len as usize;
-
-            let mut secret = Vec::with_capacity(prk_len);
-            std::ptr::copy_nonoverlapping(
-                secret_buf,
-                secret.as_mut_ptr(),
-                prk_len,
-            );
-            secret.set_len(prk_len);
-            libc::free(secret_buf as *mut libc::c_void);
-            Ok(secret)
+            Ok(SecretBuffer::copy_and_free(secret_buf, prk_len as usize))
         }
     }
 }
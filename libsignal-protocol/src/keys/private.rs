@@ -2,7 +2,7 @@ use crate::{
     errors::{Error, FromInternalErrorCode},
     keys::PublicKey,
     raw_ptr::Raw,
-    Buffer, Context,
+    Buffer, Context, SecretBuffer,
 };
 use std::{
     cmp::{Ord, Ordering},
@@ -51,12 +51,12 @@ impl PrivateKey {
     }
 
     /// Get a copy of to the underlying private key data.
-    pub fn to_bytes(&self) -> Result<Buffer, Error> {
+    pub fn to_bytes(&self) -> Result<SecretBuffer, Error> {
         unsafe {
             let mut raw = ptr::null_mut();
             sys::ec_private_key_serialize(&mut raw, self.raw.as_const_ptr())
                 .into_result()?;
-            Ok(Buffer::from_raw(raw))
+            Ok(SecretBuffer::from(Buffer::from_raw(raw)))
         }
     }
 
@@ -8,7 +8,7 @@ use crate::{
     errors::{Error, FromInternalErrorCode, InternalError},
     keys::PrivateKey,
     raw_ptr::Raw,
-    Buffer, Context,
+    Buffer, Context, SecretBuffer,
 };
 
 /// The public part of an elliptic curve key pair.
@@ -67,7 +67,7 @@ impl PublicKey {
     pub fn calculate_agreement(
         &self,
         private_key: &PrivateKey,
-    ) -> Result<Vec<u8>, Error> {
+    ) -> Result<SecretBuffer, Error> {
         unsafe {
             let mut shared_data = std::ptr::null_mut();
             let length = sys::curve_calculate_agreement(
@@ -76,15 +76,7 @@ impl PublicKey {
                 private_key.raw.as_const_ptr(),
             ) as usize;
             if length > 0 {
-                let mut secret = Vec::with_capacity(length);
-                std::ptr::copy_nonoverlapping(
-                    shared_data,
-                    secret.as_mut_ptr(),
-                    length,
-                );
-                secret.set_len(length);
-                libc::free(shared_data as *mut libc::c_void);
-                Ok(secret)
+                Ok(SecretBuffer::copy_and_free(shared_data, length))
             } else {
                 Err(Error::SecretsCalculationError)
             }
@@ -152,7 +144,11 @@ impl_serializable!(PublicKey, ec_public_key_serialize);
 mod tests {
     use super::*;
 
-    #[cfg(any(feature = "crypto-native", feature = "crypto-openssl"))]
+    #[cfg(any(
+        feature = "crypto-native",
+        feature = "crypto-openssl",
+        feature = "crypto-ring"
+    ))]
     #[test]
     fn decode_from_binary() {
         cfg_if::cfg_if! {
@@ -160,6 +156,8 @@ mod tests {
                 type Crypto = crate::crypto::DefaultCrypto;
             } else if #[cfg(feature = "crypto-openssl")] {
                 type Crypto = crate::crypto::OpenSSLCrypto;
+            } else if #[cfg(feature = "crypto-ring")] {
+                type Crypto = crate::crypto::RingCrypto;
             } else {
                 compile_error!("These tests require one of the crypto features to be enabled");
             }
@@ -48,7 +48,16 @@
 //! 1. Session State. Clients will need to maintain the state of the sessions
 //!    they have established using a [`SessionStore`].
 //!
+//! # Serde
+//!
+//! Enabling the `serde` feature implements [`serde`][serde]'s traits for
+//! [`Address`], [`Buffer`], [`keys::PublicKey`], [`PreKeyBundle`],
+//! [`stores::SerializedSession`] and the types in [`messages`]. Types which
+//! wrap a `libsignal-protocol-c` object can only be created with a
+//! [`Context`], so they are deserialized using a `ContextSeed` instead.
+//!
 //! [libsignal-protocol-c]: https://github.com/signalapp/libsignal-protocol-c
+//! [serde]: https://serde.rs/
 
 #![deny(
     missing_docs,
@@ -77,15 +86,22 @@ pub use crate::{
     context::*,
     errors::{
         Error, FromInternalErrorCode, InternalError, IntoInternalErrorCode,
+        RequiredField,
     },
     hkdf::HMACBasedKeyDerivationFunction,
+    padding::Padding,
     pre_key_bundle::{PreKeyBundle, PreKeyBundleBuilder},
+    secret_buffer::SecretBuffer,
     session_builder::SessionBuilder,
-    session_cipher::SessionCipher,
+    session_cipher::{DecryptedMessage, SessionCipher},
+    session_diagnostics::{ChainDiagnostics, SessionDiagnostics},
+    session_limits::SessionLimits,
     session_record::SessionRecord,
-    session_state::SessionState,
+    session_state::{ReceiverChain, SessionState},
     store_context::StoreContext,
 };
+#[cfg(feature = "serde")]
+pub use crate::serde_support::{ContextSeed, DeserializeWithContext};
 // bring into scope for rustdoc
 #[allow(unused_imports)]
 use crate::messages::PreKeySignalMessage;
@@ -99,21 +115,41 @@ use crate::stores::{
 mod macros;
 
 mod address;
+pub mod attachments;
 mod buffer;
 mod context;
 pub mod crypto;
+pub mod envelope;
 mod errors;
 mod hkdf;
 pub mod keys;
 pub mod messages;
+pub mod metrics;
+mod padding;
 mod pre_key_bundle;
+pub mod ratchet;
 pub(crate) mod raw_ptr;
+pub mod sealed_sender;
+mod secret_buffer;
+#[cfg(feature = "serde")]
+mod serde_support;
 mod session_builder;
 mod session_cipher;
+mod session_diagnostics;
+mod session_limits;
 mod session_record;
 mod session_state;
 mod store_context;
 pub mod stores;
+#[cfg(all(
+    feature = "test-utils",
+    any(
+        feature = "crypto-native",
+        feature = "crypto-openssl",
+        feature = "crypto-ring"
+    )
+))]
+pub mod test_utils;
 
 /// A helper trait for something which can be serialized to protobufs.
 pub trait Serializable {
@@ -10,6 +10,11 @@ use crate::messages::{PreKeySignalMessage, SignalMessage};
 
 /// The type of ciphertext message.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum CiphertextType {
     /// A [`SignalMessage`].
     Signal = 2,
@@ -38,16 +43,25 @@ impl CiphertextMessage {
     pub fn get_type(&self) -> Result<CiphertextType, InternalError> {
         unsafe {
             let ty = sys::ciphertext_message_get_type(self.raw.as_ptr());
+            CiphertextType::from_raw(u32::try_from(ty).unwrap())
+        }
+    }
+}
 
-            match u32::try_from(ty).unwrap() {
-                sys::CIPHERTEXT_PREKEY_TYPE => Ok(CiphertextType::PreKey),
-                sys::CIPHERTEXT_SIGNAL_TYPE => Ok(CiphertextType::Signal),
-                sys::CIPHERTEXT_SENDERKEY_TYPE => Ok(CiphertextType::SenderKey),
-                sys::CIPHERTEXT_SENDERKEY_DISTRIBUTION_TYPE => {
-                    Ok(CiphertextType::SenderKeyDistribution)
-                }
-                other => Err(InternalError::UnknownCiphertextType(other)),
-            }
+impl CiphertextType {
+    /// Convert from the `CIPHERTEXT_*_TYPE` constants used by
+    /// `libsignal-protocol-c`.
+    pub(crate) const fn from_raw(
+        ty: u32,
+    ) -> Result<CiphertextType, InternalError> {
+        match ty {
+            sys::CIPHERTEXT_PREKEY_TYPE => Ok(CiphertextType::PreKey),
+            sys::CIPHERTEXT_SIGNAL_TYPE => Ok(CiphertextType::Signal),
+            sys::CIPHERTEXT_SENDERKEY_TYPE => Ok(CiphertextType::SenderKey),
+            sys::CIPHERTEXT_SENDERKEY_DISTRIBUTION_TYPE => {
+                Ok(CiphertextType::SenderKeyDistribution)
+            },
+            other => Err(InternalError::UnknownCiphertextType(other)),
         }
     }
 }
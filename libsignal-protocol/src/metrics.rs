@@ -0,0 +1,297 @@
+//! Measure how much time is spent doing cryptography and talking to storage.
+//!
+//! Wrap your [`Crypto`](crate::crypto::Crypto) provider in an
+//! [`InstrumentedCrypto`](crate::crypto::InstrumentedCrypto) and your stores
+//! in an [`InstrumentedStore`](crate::stores::InstrumentedStore), and every
+//! call will be reported to a [`MetricsSink`] as a [`Measurement`].
+//!
+//! ```rust
+//! # use libsignal_protocol::{
+//! #     metrics::{InMemoryMetrics, Operation},
+//! #     stores::{InMemoryPreKeyStore, InstrumentedStore, PreKeyStore},
+//! # };
+//! # use std::sync::Arc;
+//! let metrics = Arc::new(InMemoryMetrics::default());
+//! let store = InstrumentedStore::new(
+//!     InMemoryPreKeyStore::default(),
+//!     metrics.clone(),
+//! );
+//!
+//! store.store(1, b"pre-key").unwrap();
+//!
+//! let stats = metrics.stats(Operation::StorePreKey);
+//! assert_eq!(stats.calls, 1);
+//! assert_eq!(stats.bytes, 7);
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{self, Write},
+    panic::RefUnwindSafe,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Something which was measured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operation {
+    /// [`Crypto::fill_random()`](crate::crypto::Crypto::fill_random).
+    FillRandom,
+    /// Starting a new HMAC-SHA256.
+    HmacInit,
+    /// Feeding data into a HMAC-SHA256.
+    HmacUpdate,
+    /// Finishing a HMAC-SHA256.
+    HmacFinalize,
+    /// Starting a new SHA-512 digest.
+    DigestInit,
+    /// Feeding data into a SHA-512 digest.
+    DigestUpdate,
+    /// Finishing a SHA-512 digest.
+    DigestFinalize,
+    /// AES encryption.
+    Encrypt,
+    /// AES decryption.
+    Decrypt,
+    /// Loading a pre-key.
+    LoadPreKey,
+    /// Saving a pre-key.
+    StorePreKey,
+    /// Checking whether a pre-key exists.
+    ContainsPreKey,
+    /// Removing a pre-key.
+    RemovePreKey,
+    /// Loading a signed pre-key.
+    LoadSignedPreKey,
+    /// Saving a signed pre-key.
+    StoreSignedPreKey,
+    /// Checking whether a signed pre-key exists.
+    ContainsSignedPreKey,
+    /// Removing a signed pre-key.
+    RemoveSignedPreKey,
+    /// Loading a session.
+    LoadSession,
+    /// Listing the devices a recipient has sessions with.
+    GetSubDeviceSessions,
+    /// Checking whether a session exists.
+    ContainsSession,
+    /// Saving a session.
+    StoreSession,
+    /// Deleting a session.
+    DeleteSession,
+    /// Deleting all of a recipient's sessions.
+    DeleteAllSessions,
+    /// Loading the local identity key pair.
+    GetIdentityKeyPair,
+    /// Loading the local registration ID.
+    GetLocalRegistrationId,
+    /// Checking whether a remote identity key is trusted.
+    IsTrustedIdentity,
+    /// Saving a remote identity key.
+    SaveIdentity,
+    /// Loading a remote identity key.
+    GetIdentity,
+    /// Loading an [`IdentityRecord`](crate::stores::IdentityRecord).
+    GetIdentityRecord,
+    /// Saving an [`IdentityRecord`](crate::stores::IdentityRecord).
+    SaveIdentityRecord,
+    /// Loading a remote identity's verified status.
+    GetVerifiedStatus,
+    /// Changing a remote identity's verified status.
+    SetVerifiedStatus,
+}
+
+/// A single call to something being instrumented.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Measurement {
+    /// What was done.
+    pub operation: Operation,
+    /// How many bytes were processed, read or written.
+    pub bytes: usize,
+    /// How long it took.
+    pub elapsed: Duration,
+    /// Did the operation succeed?
+    pub succeeded: bool,
+}
+
+/// Somewhere [`Measurement`]s can be sent.
+///
+/// This is called on the hot path, so implementations should avoid doing
+/// anything expensive (e.g. aggregate locally and export in the background).
+pub trait MetricsSink: Debug + Send + Sync + RefUnwindSafe {
+    /// Record a single [`Measurement`].
+    fn record(&self, measurement: &Measurement);
+}
+
+/// Aggregated [`Measurement`]s for a single [`Operation`].
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct OperationStats {
+    /// The number of times the operation was done.
+    pub calls: u64,
+    /// How many of those calls failed.
+    pub failures: u64,
+    /// The total number of bytes processed.
+    pub bytes: u64,
+    /// The total time spent.
+    pub total_time: Duration,
+    /// The longest a single call took.
+    pub max_time: Duration,
+}
+
+impl OperationStats {
+    /// The average time taken by a single call.
+    pub fn mean_time(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::default()
+        } else {
+            let nanos = self.total_time.as_nanos() / u128::from(self.calls);
+            Duration::from_nanos(nanos as u64)
+        }
+    }
+
+    fn add(&mut self, measurement: &Measurement) {
+        self.calls += 1;
+        if !measurement.succeeded {
+            self.failures += 1;
+        }
+        self.bytes += measurement.bytes as u64;
+        self.total_time += measurement.elapsed;
+        if measurement.elapsed > self.max_time {
+            self.max_time = measurement.elapsed;
+        }
+    }
+}
+
+/// A [`MetricsSink`] which keeps running totals in memory, mainly intended
+/// for tests.
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    stats: Mutex<HashMap<Operation, OperationStats>>,
+}
+
+impl InMemoryMetrics {
+    /// Get the totals for a particular [`Operation`].
+    pub fn stats(&self, operation: Operation) -> OperationStats {
+        self.stats
+            .lock()
+            .unwrap()
+            .get(&operation)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the totals for every [`Operation`] seen so far.
+    pub fn snapshot(&self) -> HashMap<Operation, OperationStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Forget everything recorded so far.
+    pub fn reset(&self) { self.stats.lock().unwrap().clear() }
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn record(&self, measurement: &Measurement) {
+        self.stats
+            .lock()
+            .unwrap()
+            .entry(measurement.operation)
+            .or_default()
+            .add(measurement);
+    }
+}
+
+/// Times an [`Operation`], reporting it to a [`MetricsSink`] when finished.
+pub(crate) struct Timer {
+    operation: Operation,
+    started: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start(operation: Operation) -> Timer {
+        Timer {
+            operation,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(
+        self,
+        sink: &dyn MetricsSink,
+        bytes: usize,
+        succeeded: bool,
+    ) {
+        sink.record(&Measurement {
+            operation: self.operation,
+            bytes,
+            elapsed: self.started.elapsed(),
+            succeeded,
+        });
+    }
+}
+
+/// A [`Write`]r which keeps track of how many bytes pass through it.
+pub(crate) struct CountingWriter<'a> {
+    inner: &'a mut dyn Write,
+    pub(crate) written: usize,
+}
+
+impl<'a> CountingWriter<'a> {
+    pub(crate) fn new(inner: &'a mut dyn Write) -> CountingWriter<'a> {
+        CountingWriter { inner, written: 0 }
+    }
+}
+
+impl<'a> Write for CountingWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { self.inner.flush() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(elapsed_ms: u64, succeeded: bool) -> Measurement {
+        Measurement {
+            operation: Operation::Encrypt,
+            bytes: 10,
+            elapsed: Duration::from_millis(elapsed_ms),
+            succeeded,
+        }
+    }
+
+    #[test]
+    fn measurements_are_aggregated() {
+        let metrics = InMemoryMetrics::default();
+
+        metrics.record(&measurement(10, true));
+        metrics.record(&measurement(30, false));
+
+        let got = metrics.stats(Operation::Encrypt);
+        assert_eq!(got.calls, 2);
+        assert_eq!(got.failures, 1);
+        assert_eq!(got.bytes, 20);
+        assert_eq!(got.total_time, Duration::from_millis(40));
+        assert_eq!(got.max_time, Duration::from_millis(30));
+        assert_eq!(got.mean_time(), Duration::from_millis(20));
+        assert_eq!(
+            metrics.stats(Operation::Decrypt),
+            OperationStats::default()
+        );
+    }
+
+    #[test]
+    fn reset_the_totals() {
+        let metrics = InMemoryMetrics::default();
+        metrics.record(&measurement(10, true));
+
+        metrics.reset();
+
+        assert!(metrics.snapshot().is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use zeroize::Zeroizing;
+
+use crate::{Error, InternalError, SecretBuffer};
+
+/// The byte marking the end of the real message.
+const TERMINATOR: u8 = 0x80;
+
+/// How a [`SessionCipher`](crate::SessionCipher) pads messages before
+/// encrypting them, so the ciphertext doesn't give away their exact length.
+///
+/// Padding is a `0x80` byte followed by zeroes, which is stripped (and
+/// checked) again when the message is decrypted. Both sides of a session
+/// need to agree on whether padding is used, although they don't need to use
+/// the same block or bucket sizes.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Padding {
+    /// Encrypt the message as-is.
+    #[default]
+    None,
+    /// Pad messages up to a multiple of this many bytes.
+    Blocks(usize),
+    /// Pad messages up to the smallest of these sizes they'll fit in, or a
+    /// multiple of the largest size for anything bigger than that.
+    Buckets(Vec<usize>),
+}
+
+impl Padding {
+    /// The block size used by the Signal apps.
+    pub const SIGNAL_BLOCK_SIZE: usize = 160;
+
+    /// The padding scheme used by the Signal apps.
+    pub const fn signal() -> Padding {
+        Padding::Blocks(Padding::SIGNAL_BLOCK_SIZE)
+    }
+
+    /// How long will a message of `len` bytes be once it is padded?
+    pub fn padded_length(&self, len: usize) -> Result<usize, Error> {
+        // there always needs to be room for the terminator
+        let len = len + 1;
+
+        match *self {
+            Padding::None => Ok(len - 1),
+            Padding::Blocks(block_size) => round_up(len, block_size),
+            Padding::Buckets(ref buckets) => {
+                let largest = buckets.iter().copied().max().unwrap_or(0);
+
+                match buckets.iter().copied().filter(|&b| b >= len).min() {
+                    Some(bucket) => Ok(bucket),
+                    None => round_up(len, largest),
+                }
+            },
+        }
+    }
+
+    /// Pad a message before it is encrypted.
+    pub(crate) fn pad(
+        &self,
+        message: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, Error> {
+        let padded_length = self.padded_length(message.len())?;
+        let mut padded = Zeroizing::new(Vec::with_capacity(padded_length));
+        padded.extend_from_slice(message);
+
+        if *self != Padding::None {
+            padded.push(TERMINATOR);
+            padded.resize(padded_length, 0);
+        }
+
+        Ok(padded)
+    }
+
+    /// Strip the padding from a decrypted message.
+    pub(crate) fn unpad(
+        &self,
+        plaintext: SecretBuffer,
+    ) -> Result<SecretBuffer, Error> {
+        if *self == Padding::None {
+            return Ok(plaintext);
+        }
+
+        let bytes = plaintext.as_slice();
+
+        match bytes.iter().rposition(|&b| b != 0) {
+            Some(end) if bytes[end] == TERMINATOR => {
+                Ok(SecretBuffer::from(&bytes[..end]))
+            },
+            _ => Err(Error::InvalidPadding),
+        }
+    }
+}
+
+fn round_up(len: usize, multiple: usize) -> Result<usize, Error> {
+    if multiple == 0 {
+        return Err(InternalError::InvalidArgument.into());
+    }
+
+    let remainder = len % multiple;
+
+    if remainder == 0 {
+        Ok(len)
+    } else {
+        Ok(len + multiple - remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(padding: &Padding, message: &[u8]) -> usize {
+        let padded = padding.pad(message).unwrap();
+        let got = padding.unpad(SecretBuffer::from(&padded[..])).unwrap();
+        assert_eq!(got.as_slice(), message);
+
+        padded.len()
+    }
+
+    #[test]
+    fn signal_pads_to_160_byte_blocks() {
+        let padding = Padding::signal();
+
+        assert_eq!(round_trip(&padding, b""), 160);
+        assert_eq!(round_trip(&padding, b"Hello, World!"), 160);
+        assert_eq!(round_trip(&padding, &[0x80; 159]), 160);
+        assert_eq!(round_trip(&padding, &[0; 160]), 320);
+    }
+
+    #[test]
+    fn messages_are_padded_to_the_smallest_bucket() {
+        let padding = Padding::Buckets(vec![256, 64, 1024]);
+
+        assert_eq!(round_trip(&padding, &[1; 10]), 64);
+        assert_eq!(round_trip(&padding, &[1; 64]), 256);
+        assert_eq!(round_trip(&padding, &[1; 1000]), 1024);
+        assert_eq!(round_trip(&padding, &[1; 1500]), 2048);
+    }
+
+    #[test]
+    fn no_padding_leaves_the_message_alone() {
+        assert_eq!(round_trip(&Padding::None, b"Hello, World!"), 13);
+    }
+
+    #[test]
+    fn malformed_padding_is_rejected() {
+        let padding = Padding::signal();
+
+        for bad in &[&b""[..], &[0; 16], b"Hello, World!\x00\x00"] {
+            let got = padding.unpad(SecretBuffer::from(*bad));
+
+            match got {
+                Err(Error::InvalidPadding) => {},
+                other => panic!("unexpected result for {:?}: {:?}", bad, other),
+            }
+        }
+    }
+
+    #[test]
+    fn empty_blocks_are_invalid() {
+        assert!(Padding::Blocks(0).padded_length(10).is_err());
+        assert!(Padding::Buckets(Vec::new()).padded_length(10).is_err());
+    }
+}
@@ -1,11 +1,13 @@
 use crate::{
-    errors::{Error, RequiredField},
+    errors::{Error, InternalError, RequiredField},
     keys::PublicKey,
     raw_ptr::Raw,
+    Buffer, Context, Deserializable, Serializable,
 };
+use prost::Message;
 use std::{
     fmt::{self, Debug, Formatter},
-    ptr,
+    ptr, slice,
 };
 
 /// The session state used when sending a message to another user.
@@ -99,6 +101,87 @@ impl PreKeyBundle {
             }
         }
     }
+
+    /// Get the identity key's signature over the signed pre-key.
+    pub fn signed_pre_key_signature(&self) -> &[u8] {
+        unsafe {
+            let buffer =
+                sys::session_pre_key_bundle_get_signed_pre_key_signature(
+                    self.raw.as_const_ptr(),
+                );
+            if buffer.is_null() {
+                return &[];
+            }
+
+            slice::from_raw_parts(
+                sys::signal_buffer_const_data(buffer),
+                sys::signal_buffer_len(buffer),
+            )
+        }
+    }
+
+    /// Check that the signed pre-key was signed by the identity key.
+    pub fn verify_signature(&self) -> Result<(), Error> {
+        let signed_pre_key = self.signed_pre_key()?.to_bytes()?;
+
+        self.identity_key()?.verify_signature(
+            signed_pre_key.as_slice(),
+            self.signed_pre_key_signature(),
+        )
+    }
+}
+
+impl Serializable for PreKeyBundle {
+    fn serialize(&self) -> Result<Buffer, Error> {
+        let key_bytes = |key: Result<PublicKey, Error>| match key {
+            Ok(key) => key.to_bytes().map(|b| Some(b.as_slice().to_vec())),
+            Err(_) => Ok(None),
+        };
+
+        let pre_key = key_bytes(self.pre_key())?;
+        let proto = proto::PreKeyBundle {
+            registration_id: self.registration_id(),
+            device_id: self.device_id(),
+            pre_key_id: pre_key.as_ref().map(|_| self.pre_key_id()),
+            pre_key,
+            signed_pre_key_id: self.signed_pre_key_id(),
+            signed_pre_key: key_bytes(self.signed_pre_key())?,
+            signed_pre_key_signature: self.signed_pre_key_signature().to_vec(),
+            identity_key: self.identity_key()?.to_bytes()?.as_slice().to_vec(),
+        };
+
+        let mut buffer = Vec::with_capacity(proto.encoded_len());
+        proto
+            .encode(&mut buffer)
+            .map_err(|_| InternalError::SerializationError)?;
+
+        Ok(Buffer::from(buffer))
+    }
+}
+
+impl Deserializable for PreKeyBundle {
+    fn deserialize(ctx: &Context, data: &[u8]) -> Result<Self, Error> {
+        let proto = proto::PreKeyBundle::decode(data)
+            .map_err(|_| InternalError::InvalidProtoBuf)?;
+
+        let mut builder = PreKeyBundle::builder()
+            .registration_id(proto.registration_id)
+            .device_id(proto.device_id)
+            .identity_key(&PublicKey::decode_point(ctx, &proto.identity_key)?)
+            .signature(&proto.signed_pre_key_signature);
+
+        if let (Some(id), Some(key)) = (proto.pre_key_id, proto.pre_key) {
+            builder = builder.pre_key(id, &PublicKey::decode_point(ctx, &key)?);
+        }
+        if let Some(key) = proto.signed_pre_key {
+            builder = builder.signed_pre_key(
+                proto.signed_pre_key_id,
+                &PublicKey::decode_point(ctx, &key)?,
+            );
+        }
+
+        builder.build()
+    }
 }
 
 impl Debug for PreKeyBundle {
@@ -243,3 +326,39 @@ impl PreKeyBundleBuilder {
         }
     }
 }
+
+mod proto {
+    /// The wire format for a [`PreKeyBundle`](super::PreKeyBundle).
+    ///
+    /// ```protobuf
+    /// message PreKeyBundle {
+    ///   uint32 registration_id           = 1;
+    ///   int32  device_id                 = 2;
+    ///   optional uint32 pre_key_id       = 3;
+    ///   optional bytes  pre_key          = 4;
+    ///   uint32 signed_pre_key_id         = 5;
+    ///   optional bytes  signed_pre_key   = 6;
+    ///   bytes  signed_pre_key_signature  = 7;
+    ///   bytes  identity_key              = 8;
+    /// }
+    /// ```
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct PreKeyBundle {
+        #[prost(uint32, tag = "1")]
+        pub registration_id: u32,
+        #[prost(int32, tag = "2")]
+        pub device_id: i32,
+        #[prost(uint32, optional, tag = "3")]
+        pub pre_key_id: Option<u32>,
+        #[prost(bytes, optional, tag = "4")]
+        pub pre_key: Option<Vec<u8>>,
+        #[prost(uint32, tag = "5")]
+        pub signed_pre_key_id: u32,
+        #[prost(bytes, optional, tag = "6")]
+        pub signed_pre_key: Option<Vec<u8>>,
+        #[prost(bytes, tag = "7")]
+        pub signed_pre_key_signature: Vec<u8>,
+        #[prost(bytes, tag = "8")]
+        pub identity_key: Vec<u8>,
+    }
+}
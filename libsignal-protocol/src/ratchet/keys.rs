@@ -0,0 +1,274 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug, Formatter},
+    ptr,
+    rc::Rc,
+};
+
+use zeroize::Zeroize;
+
+use crate::{
+    context::ContextInner,
+    errors::{Error, FromInternalErrorCode},
+    keys::{PrivateKey, PublicKey},
+    raw_ptr::Raw,
+    Buffer, Context, HMACBasedKeyDerivationFunction, SecretBuffer,
+};
+
+/// A key used to derive the [`MessageKeys`] for one step of a sending or
+/// receiving chain.
+#[derive(Debug, Clone)]
+pub struct ChainKey {
+    pub(crate) raw: Raw<sys::ratchet_chain_key>,
+    pub(crate) _ctx: Rc<ContextInner>,
+}
+
+impl ChainKey {
+    /// Create a [`ChainKey`] from the raw key data and its position in the
+    /// chain.
+    pub fn new(
+        ctx: &Context,
+        kdf: &HMACBasedKeyDerivationFunction,
+        key: &[u8],
+        index: u32,
+    ) -> Result<ChainKey, Error> {
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::ratchet_chain_key_create(
+                &mut raw,
+                kdf.raw.as_ptr(),
+                key.as_ptr(),
+                key.len(),
+                index,
+                ctx.raw(),
+            )
+            .into_result()?;
+
+            Ok(ChainKey {
+                raw: Raw::from_ptr(raw),
+                _ctx: Rc::clone(&ctx.0),
+            })
+        }
+    }
+
+    /// Get a copy of the underlying key data.
+    pub fn key(&self) -> Result<SecretBuffer, Error> {
+        unsafe {
+            let mut buffer = ptr::null_mut();
+            sys::ratchet_chain_key_get_key(
+                self.raw.as_const_ptr(),
+                &mut buffer,
+            )
+            .into_result()?;
+
+            Ok(SecretBuffer::from(Buffer::from_raw(buffer)))
+        }
+    }
+
+    /// How far along the chain is this key?
+    pub fn index(&self) -> u32 {
+        unsafe { sys::ratchet_chain_key_get_index(self.raw.as_const_ptr()) }
+    }
+
+    /// Derive the keys used to encrypt the message at this point in the
+    /// chain.
+    pub fn message_keys(&self) -> Result<MessageKeys, Error> {
+        unsafe {
+            let mut keys = sys::ratchet_message_keys {
+                cipher_key: [0; MessageKeys::CIPHER_KEY_LENGTH],
+                mac_key: [0; MessageKeys::MAC_KEY_LENGTH],
+                iv: [0; MessageKeys::IV_LENGTH],
+                counter: 0,
+            };
+            let result = sys::ratchet_chain_key_get_message_keys(
+                self.raw.as_ptr(),
+                &mut keys,
+            )
+            .into_result();
+
+            let message_keys = MessageKeys {
+                cipher_key: keys.cipher_key,
+                mac_key: keys.mac_key,
+                iv: keys.iv,
+                counter: keys.counter,
+            };
+            keys.cipher_key.zeroize();
+            keys.mac_key.zeroize();
+            keys.iv.zeroize();
+
+            result?;
+            Ok(message_keys)
+        }
+    }
+
+    /// Move one step along the chain.
+    pub fn next(&self) -> Result<ChainKey, Error> {
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::ratchet_chain_key_create_next(
+                self.raw.as_const_ptr(),
+                &mut raw,
+            )
+            .into_result()?;
+
+            Ok(ChainKey {
+                raw: Raw::from_ptr(raw),
+                _ctx: Rc::clone(&self._ctx),
+            })
+        }
+    }
+}
+
+/// The key used to start a new [`ChainKey`] whenever the other party's
+/// ratchet key changes.
+#[derive(Debug, Clone)]
+pub struct RootKey {
+    pub(crate) raw: Raw<sys::ratchet_root_key>,
+    pub(crate) _ctx: Rc<ContextInner>,
+}
+
+impl RootKey {
+    /// Create a [`RootKey`] from the raw key data.
+    pub fn new(
+        ctx: &Context,
+        kdf: &HMACBasedKeyDerivationFunction,
+        key: &[u8],
+    ) -> Result<RootKey, Error> {
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::ratchet_root_key_create(
+                &mut raw,
+                kdf.raw.as_ptr(),
+                key.as_ptr(),
+                key.len(),
+                ctx.raw(),
+            )
+            .into_result()?;
+
+            Ok(RootKey {
+                raw: Raw::from_ptr(raw),
+                _ctx: Rc::clone(&ctx.0),
+            })
+        }
+    }
+
+    /// Get a copy of the underlying key data.
+    pub fn key(&self) -> Result<SecretBuffer, Error> {
+        unsafe {
+            let mut buffer = ptr::null_mut();
+            sys::ratchet_root_key_get_key(self.raw.as_ptr(), &mut buffer)
+                .into_result()?;
+
+            Ok(SecretBuffer::from(Buffer::from_raw(buffer)))
+        }
+    }
+
+    /// Step the ratchet using the other party's ratchet key and our own,
+    /// returning the next [`RootKey`] and the first [`ChainKey`] of the new
+    /// chain.
+    pub fn create_chain(
+        &self,
+        their_ratchet_key: &PublicKey,
+        our_ratchet_key: &PrivateKey,
+    ) -> Result<(RootKey, ChainKey), Error> {
+        unsafe {
+            let mut root_key = ptr::null_mut();
+            let mut chain_key = ptr::null_mut();
+            sys::ratchet_root_key_create_chain(
+                self.raw.as_ptr(),
+                &mut root_key,
+                &mut chain_key,
+                their_ratchet_key.raw.as_ptr(),
+                our_ratchet_key.raw.as_ptr(),
+            )
+            .into_result()?;
+
+            Ok((
+                RootKey {
+                    raw: Raw::from_ptr(root_key),
+                    _ctx: Rc::clone(&self._ctx),
+                },
+                ChainKey {
+                    raw: Raw::from_ptr(chain_key),
+                    _ctx: Rc::clone(&self._ctx),
+                },
+            ))
+        }
+    }
+}
+
+impl Ord for RootKey {
+    fn cmp(&self, other: &RootKey) -> Ordering {
+        let cmp = unsafe {
+            sys::ratchet_root_key_compare(
+                self.raw.as_const_ptr(),
+                other.raw.as_const_ptr(),
+            )
+        };
+
+        cmp.cmp(&0)
+    }
+}
+
+impl PartialEq for RootKey {
+    fn eq(&self, other: &RootKey) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RootKey {}
+
+impl PartialOrd for RootKey {
+    fn partial_cmp(&self, other: &RootKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The keys used to encrypt and authenticate a single message.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MessageKeys {
+    cipher_key: [u8; MessageKeys::CIPHER_KEY_LENGTH],
+    mac_key: [u8; MessageKeys::MAC_KEY_LENGTH],
+    iv: [u8; MessageKeys::IV_LENGTH],
+    counter: u32,
+}
+
+impl MessageKeys {
+    /// The length of [`MessageKeys::cipher_key()`].
+    pub const CIPHER_KEY_LENGTH: usize = 32;
+    /// The length of [`MessageKeys::mac_key()`].
+    pub const MAC_KEY_LENGTH: usize = 32;
+    /// The length of [`MessageKeys::iv()`].
+    pub const IV_LENGTH: usize = 16;
+
+    /// The AES key the message is encrypted with.
+    pub const fn cipher_key(&self) -> &[u8] { &self.cipher_key }
+
+    /// The key used to calculate the message's MAC.
+    pub const fn mac_key(&self) -> &[u8] { &self.mac_key }
+
+    /// The IV the message is encrypted with.
+    pub const fn iv(&self) -> &[u8] { &self.iv }
+
+    /// The index of the [`ChainKey`] these keys were derived from.
+    pub const fn counter(&self) -> u32 { self.counter }
+}
+
+impl Debug for MessageKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageKeys")
+            .field("cipher_key", &"<elided>")
+            .field("mac_key", &"<elided>")
+            .field("iv", &"<elided>")
+            .field("counter", &self.counter)
+            .finish()
+    }
+}
+
+impl Drop for MessageKeys {
+    fn drop(&mut self) {
+        self.cipher_key.zeroize();
+        self.mac_key.zeroize();
+        self.iv.zeroize();
+    }
+}
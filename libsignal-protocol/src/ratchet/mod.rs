@@ -0,0 +1,25 @@
+//! The double ratchet at the heart of a session.
+//!
+//! Sessions normally take care of all of this for you, but working with it
+//! directly is handy for checking test vectors, figuring out where two
+//! devices' ratchets diverged, or setting up a session from keys which were
+//! exchanged out-of-band (e.g. by scanning a QR code) instead of via a
+//! [`PreKeyBundle`](crate::PreKeyBundle).
+//!
+//! Each time a new ratchet key is received the [`RootKey`] is used to start a
+//! new sending or receiving [`ChainKey`]. Every message then moves the chain
+//! forward one step, and the [`MessageKeys`] for that step are used to
+//! encrypt the message.
+
+mod keys;
+mod parameters;
+
+pub use self::{
+    keys::{ChainKey, MessageKeys, RootKey},
+    parameters::{
+        initialize_alice_session, initialize_bob_session,
+        initialize_symmetric_session, AliceParameters, AliceParametersBuilder,
+        BobParameters, BobParametersBuilder, SymmetricParameters,
+        SymmetricParametersBuilder,
+    },
+};
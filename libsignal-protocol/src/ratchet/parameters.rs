@@ -0,0 +1,405 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    ptr,
+};
+
+use crate::{
+    errors::{Error, FromInternalErrorCode, RequiredField},
+    keys::{IdentityKeyPair, KeyPair, PublicKey},
+    raw_ptr::Raw,
+    Context, SessionState,
+};
+
+/// The keys Alice (the party who starts the session) needs to set it up.
+#[derive(Clone)]
+pub struct AliceParameters {
+    raw: Raw<sys::alice_signal_protocol_parameters>,
+}
+
+impl AliceParameters {
+    /// Get a builder struct for the [`AliceParameters`].
+    pub fn builder() -> AliceParametersBuilder {
+        AliceParametersBuilder::default()
+    }
+}
+
+impl Debug for AliceParameters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AliceParameters").finish()
+    }
+}
+
+/// A builder type for the [`AliceParameters`].
+#[derive(Debug, Default)]
+pub struct AliceParametersBuilder {
+    our_identity_key: Option<IdentityKeyPair>,
+    our_base_key: Option<KeyPair>,
+    their_identity_key: Option<PublicKey>,
+    their_signed_pre_key: Option<PublicKey>,
+    their_one_time_pre_key: Option<PublicKey>,
+    their_ratchet_key: Option<PublicKey>,
+}
+
+impl AliceParametersBuilder {
+    /// Set our identity key pair.
+    pub fn our_identity_key(mut self, identity_key: &IdentityKeyPair) -> Self {
+        self.our_identity_key = Some(identity_key.clone());
+        self
+    }
+
+    /// Set the ephemeral key pair we're starting the session with.
+    pub fn our_base_key(mut self, base_key: &KeyPair) -> Self {
+        self.our_base_key = Some(base_key.clone());
+        self
+    }
+
+    /// Set Bob's identity key.
+    pub fn their_identity_key(mut self, identity_key: &PublicKey) -> Self {
+        self.their_identity_key = Some(identity_key.clone());
+        self
+    }
+
+    /// Set Bob's signed pre-key.
+    pub fn their_signed_pre_key(mut self, signed_pre_key: &PublicKey) -> Self {
+        self.their_signed_pre_key = Some(signed_pre_key.clone());
+        self
+    }
+
+    /// Set Bob's one-time pre-key, if he has one.
+    pub fn their_one_time_pre_key(mut self, pre_key: &PublicKey) -> Self {
+        self.their_one_time_pre_key = Some(pre_key.clone());
+        self
+    }
+
+    /// Set Bob's ratchet key (normally the same as his signed pre-key).
+    pub fn their_ratchet_key(mut self, ratchet_key: &PublicKey) -> Self {
+        self.their_ratchet_key = Some(ratchet_key.clone());
+        self
+    }
+
+    /// Actually build the [`AliceParameters`].
+    pub fn build(self) -> Result<AliceParameters, Error> {
+        let our_identity_key =
+            required(&self.our_identity_key, RequiredField::OurIdentityKey)?;
+        let our_base_key =
+            required(&self.our_base_key, RequiredField::OurBaseKey)?;
+        let their_identity_key = required(
+            &self.their_identity_key,
+            RequiredField::TheirIdentityKey,
+        )?;
+        let their_signed_pre_key = required(
+            &self.their_signed_pre_key,
+            RequiredField::TheirSignedPreKey,
+        )?;
+        let their_ratchet_key =
+            required(&self.their_ratchet_key, RequiredField::TheirRatchetKey)?;
+        let their_one_time_pre_key = match self.their_one_time_pre_key {
+            Some(ref key) => key.raw.as_ptr(),
+            None => ptr::null_mut(),
+        };
+
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::alice_signal_protocol_parameters_create(
+                &mut raw,
+                our_identity_key.raw.as_ptr(),
+                our_base_key.raw.as_ptr(),
+                their_identity_key.raw.as_ptr(),
+                their_signed_pre_key.raw.as_ptr(),
+                their_one_time_pre_key,
+                their_ratchet_key.raw.as_ptr(),
+            )
+            .into_result()?;
+
+            Ok(AliceParameters {
+                raw: Raw::from_ptr(raw),
+            })
+        }
+    }
+}
+
+/// The keys Bob (the party receiving the first message) needs to set up a
+/// session.
+#[derive(Clone)]
+pub struct BobParameters {
+    raw: Raw<sys::bob_signal_protocol_parameters>,
+}
+
+impl BobParameters {
+    /// Get a builder struct for the [`BobParameters`].
+    pub fn builder() -> BobParametersBuilder { BobParametersBuilder::default() }
+}
+
+impl Debug for BobParameters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BobParameters").finish()
+    }
+}
+
+/// A builder type for the [`BobParameters`].
+#[derive(Debug, Default)]
+pub struct BobParametersBuilder {
+    our_identity_key: Option<IdentityKeyPair>,
+    our_signed_pre_key: Option<KeyPair>,
+    our_one_time_pre_key: Option<KeyPair>,
+    our_ratchet_key: Option<KeyPair>,
+    their_identity_key: Option<PublicKey>,
+    their_base_key: Option<PublicKey>,
+}
+
+impl BobParametersBuilder {
+    /// Set our identity key pair.
+    pub fn our_identity_key(mut self, identity_key: &IdentityKeyPair) -> Self {
+        self.our_identity_key = Some(identity_key.clone());
+        self
+    }
+
+    /// Set our signed pre-key.
+    pub fn our_signed_pre_key(mut self, signed_pre_key: &KeyPair) -> Self {
+        self.our_signed_pre_key = Some(signed_pre_key.clone());
+        self
+    }
+
+    /// Set the one-time pre-key Alice used, if there was one.
+    pub fn our_one_time_pre_key(mut self, pre_key: &KeyPair) -> Self {
+        self.our_one_time_pre_key = Some(pre_key.clone());
+        self
+    }
+
+    /// Set our ratchet key (normally the same as our signed pre-key).
+    pub fn our_ratchet_key(mut self, ratchet_key: &KeyPair) -> Self {
+        self.our_ratchet_key = Some(ratchet_key.clone());
+        self
+    }
+
+    /// Set Alice's identity key.
+    pub fn their_identity_key(mut self, identity_key: &PublicKey) -> Self {
+        self.their_identity_key = Some(identity_key.clone());
+        self
+    }
+
+    /// Set the ephemeral key Alice started the session with.
+    pub fn their_base_key(mut self, base_key: &PublicKey) -> Self {
+        self.their_base_key = Some(base_key.clone());
+        self
+    }
+
+    /// Actually build the [`BobParameters`].
+    pub fn build(self) -> Result<BobParameters, Error> {
+        let our_identity_key =
+            required(&self.our_identity_key, RequiredField::OurIdentityKey)?;
+        let our_signed_pre_key =
+            required(&self.our_signed_pre_key, RequiredField::OurSignedPreKey)?;
+        let our_ratchet_key =
+            required(&self.our_ratchet_key, RequiredField::OurRatchetKey)?;
+        let their_identity_key = required(
+            &self.their_identity_key,
+            RequiredField::TheirIdentityKey,
+        )?;
+        let their_base_key =
+            required(&self.their_base_key, RequiredField::TheirBaseKey)?;
+        let our_one_time_pre_key = match self.our_one_time_pre_key {
+            Some(ref key) => key.raw.as_ptr(),
+            None => ptr::null_mut(),
+        };
+
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::bob_signal_protocol_parameters_create(
+                &mut raw,
+                our_identity_key.raw.as_ptr(),
+                our_signed_pre_key.raw.as_ptr(),
+                our_one_time_pre_key,
+                our_ratchet_key.raw.as_ptr(),
+                their_identity_key.raw.as_ptr(),
+                their_base_key.raw.as_ptr(),
+            )
+            .into_result()?;
+
+            Ok(BobParameters {
+                raw: Raw::from_ptr(raw),
+            })
+        }
+    }
+}
+
+/// The keys needed when both parties exchange a base key and ratchet key at
+/// the same time (e.g. in person), so neither of them is "Alice" or "Bob".
+///
+/// Whoever has the lower base key ends up playing the part of Alice.
+#[derive(Clone)]
+pub struct SymmetricParameters {
+    raw: Raw<sys::symmetric_signal_protocol_parameters>,
+}
+
+impl SymmetricParameters {
+    /// Get a builder struct for the [`SymmetricParameters`].
+    pub fn builder() -> SymmetricParametersBuilder {
+        SymmetricParametersBuilder::default()
+    }
+}
+
+impl Debug for SymmetricParameters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SymmetricParameters").finish()
+    }
+}
+
+/// A builder type for the [`SymmetricParameters`].
+#[derive(Debug, Default)]
+pub struct SymmetricParametersBuilder {
+    our_identity_key: Option<IdentityKeyPair>,
+    our_base_key: Option<KeyPair>,
+    our_ratchet_key: Option<KeyPair>,
+    their_identity_key: Option<PublicKey>,
+    their_base_key: Option<PublicKey>,
+    their_ratchet_key: Option<PublicKey>,
+}
+
+impl SymmetricParametersBuilder {
+    /// Set our identity key pair.
+    pub fn our_identity_key(mut self, identity_key: &IdentityKeyPair) -> Self {
+        self.our_identity_key = Some(identity_key.clone());
+        self
+    }
+
+    /// Set our base key.
+    pub fn our_base_key(mut self, base_key: &KeyPair) -> Self {
+        self.our_base_key = Some(base_key.clone());
+        self
+    }
+
+    /// Set our ratchet key.
+    pub fn our_ratchet_key(mut self, ratchet_key: &KeyPair) -> Self {
+        self.our_ratchet_key = Some(ratchet_key.clone());
+        self
+    }
+
+    /// Set the other party's identity key.
+    pub fn their_identity_key(mut self, identity_key: &PublicKey) -> Self {
+        self.their_identity_key = Some(identity_key.clone());
+        self
+    }
+
+    /// Set the other party's base key.
+    pub fn their_base_key(mut self, base_key: &PublicKey) -> Self {
+        self.their_base_key = Some(base_key.clone());
+        self
+    }
+
+    /// Set the other party's ratchet key.
+    pub fn their_ratchet_key(mut self, ratchet_key: &PublicKey) -> Self {
+        self.their_ratchet_key = Some(ratchet_key.clone());
+        self
+    }
+
+    /// Actually build the [`SymmetricParameters`].
+    pub fn build(self) -> Result<SymmetricParameters, Error> {
+        let our_identity_key =
+            required(&self.our_identity_key, RequiredField::OurIdentityKey)?;
+        let our_base_key =
+            required(&self.our_base_key, RequiredField::OurBaseKey)?;
+        let our_ratchet_key =
+            required(&self.our_ratchet_key, RequiredField::OurRatchetKey)?;
+        let their_identity_key = required(
+            &self.their_identity_key,
+            RequiredField::TheirIdentityKey,
+        )?;
+        let their_base_key =
+            required(&self.their_base_key, RequiredField::TheirBaseKey)?;
+        let their_ratchet_key =
+            required(&self.their_ratchet_key, RequiredField::TheirRatchetKey)?;
+
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::symmetric_signal_protocol_parameters_create(
+                &mut raw,
+                our_identity_key.raw.as_ptr(),
+                our_base_key.raw.as_ptr(),
+                our_ratchet_key.raw.as_ptr(),
+                their_base_key.raw.as_ptr(),
+                their_ratchet_key.raw.as_ptr(),
+                their_identity_key.raw.as_ptr(),
+            )
+            .into_result()?;
+
+            Ok(SymmetricParameters {
+                raw: Raw::from_ptr(raw),
+            })
+        }
+    }
+}
+
+/// Create a new [`SessionState`] for the party starting a session.
+///
+/// The state can be saved with [`SessionRecord::new()`] and
+/// [`StoreContext::store_session()`] so a [`SessionCipher`] can use it.
+///
+/// [`SessionRecord::new()`]: crate::SessionRecord::new
+/// [`StoreContext::store_session()`]: crate::StoreContext::store_session
+/// [`SessionCipher`]: crate::SessionCipher
+pub fn initialize_alice_session(
+    ctx: &Context,
+    parameters: &AliceParameters,
+) -> Result<SessionState, Error> {
+    let state = SessionState::new(ctx)?;
+
+    unsafe {
+        sys::ratcheting_session_alice_initialize(
+            state.raw.as_ptr(),
+            parameters.raw.as_ptr(),
+            ctx.raw(),
+        )
+        .into_result()?;
+    }
+
+    Ok(state)
+}
+
+/// Create a new [`SessionState`] for the party receiving the first message
+/// in a session.
+///
+/// See [`initialize_alice_session()`] for how to use the state.
+pub fn initialize_bob_session(
+    ctx: &Context,
+    parameters: &BobParameters,
+) -> Result<SessionState, Error> {
+    let state = SessionState::new(ctx)?;
+
+    unsafe {
+        sys::ratcheting_session_bob_initialize(
+            state.raw.as_ptr(),
+            parameters.raw.as_ptr(),
+            ctx.raw(),
+        )
+        .into_result()?;
+    }
+
+    Ok(state)
+}
+
+/// Create a new [`SessionState`] from keys both parties exchanged at the
+/// same time.
+///
+/// See [`initialize_alice_session()`] for how to use the state.
+pub fn initialize_symmetric_session(
+    ctx: &Context,
+    parameters: &SymmetricParameters,
+) -> Result<SessionState, Error> {
+    let state = SessionState::new(ctx)?;
+
+    unsafe {
+        sys::ratcheting_session_symmetric_initialize(
+            state.raw.as_ptr(),
+            parameters.raw.as_ptr(),
+            ctx.raw(),
+        )
+        .into_result()?;
+    }
+
+    Ok(state)
+}
+
+fn required<T>(value: &Option<T>, field: RequiredField) -> Result<&T, Error> {
+    value.as_ref().ok_or(Error::MissingRequiredField(field))
+}
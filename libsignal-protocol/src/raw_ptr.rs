@@ -87,17 +87,22 @@ pub unsafe trait IsA<Parent> {
 }
 
 impl_is_a! {
+    sys::alice_signal_protocol_parameters => sys::signal_type_base,
+    sys::bob_signal_protocol_parameters => sys::signal_type_base,
     sys::ciphertext_message => sys::signal_type_base,
     sys::ec_key_pair => sys::signal_type_base,
     sys::ec_private_key => sys::signal_type_base,
     sys::ec_public_key => sys::signal_type_base,
     sys::hkdf_context => sys::signal_type_base,
     sys::pre_key_signal_message => sys::signal_type_base,
+    sys::ratchet_chain_key => sys::signal_type_base,
     sys::ratchet_identity_key_pair => sys::signal_type_base,
+    sys::ratchet_root_key => sys::signal_type_base,
     sys::session_pre_key => sys::signal_type_base,
     sys::session_pre_key_bundle => sys::signal_type_base,
     sys::session_record => sys::signal_type_base,
     sys::session_signed_pre_key => sys::signal_type_base,
     sys::session_state => sys::signal_type_base,
     sys::signal_message => sys::signal_type_base,
+    sys::symmetric_signal_protocol_parameters => sys::signal_type_base,
 }
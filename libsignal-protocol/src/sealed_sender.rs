@@ -0,0 +1,699 @@
+//! Sealed sender (a.k.a. "unidentified delivery"), where the server relaying
+//! a message doesn't learn who sent it.
+//!
+//! Instead of authenticating to the server, the sender proves who they are
+//! to the recipient with a [`SenderCertificate`]. This is issued (and
+//! signed) by the server using a key from a [`ServerCertificate`], which is in
+//! turn signed by a trust root every client knows the public half of.
+//!
+//! The [`CiphertextMessage`] and [`SenderCertificate`] are then encrypted to
+//! the recipient's identity key in two layers:
+//!
+//! 1. An ephemeral key agreement with the recipient's identity key is used
+//!    to encrypt the sender's identity key
+//! 2. A key agreement between the sender's and recipient's identity keys is
+//!    used to encrypt the certificate and message
+//!
+//! When the recipient [`unseal()`]s the message they check the certificate
+//! chain back to the trust root, that the certificate hasn't expired, and
+//! that the certificate was issued to the identity key which encrypted the
+//! message. The [`UnsealedMessage::message()`] can then be decrypted with a
+//! [`SessionCipher`] like normal.
+//!
+//! [`SessionCipher`]: crate::SessionCipher
+
+use std::{
+    convert::TryInto,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    crypto::{constant_time_eq, Crypto, SignalCipherType},
+    errors::{Error, InternalError},
+    keys::{IdentityKeyPair, PrivateKey, PublicKey},
+    messages::{CiphertextMessage, CiphertextType},
+    Address, Buffer, Context, Deserializable, SecretBuffer, Serializable,
+};
+
+const VERSION: u8 = 0x11;
+const SALT_PREFIX: &[u8] = b"UnidentifiedDelivery";
+const HKDF_VERSION: i32 = 3;
+const KEY_LENGTH: usize = 32;
+/// Each layer derives a chain key, a cipher key and a MAC key.
+const DERIVED_KEYS_LENGTH: usize = 3 * KEY_LENGTH;
+const MAC_LENGTH: usize = 10;
+/// Every layer uses a fresh key, so there's no need for a random IV.
+const IV: [u8; 16] = [0; 16];
+
+/// A key the server uses to issue [`SenderCertificate`]s, signed by the trust
+/// root.
+#[derive(Debug, Clone)]
+pub struct ServerCertificate {
+    key_id: u32,
+    key: PublicKey,
+    /// The bytes covered by the signature.
+    certificate: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl ServerCertificate {
+    /// Sign a server key using the trust root's private key.
+    pub fn new(
+        ctx: &Context,
+        key_id: u32,
+        key: &PublicKey,
+        trust_root: &PrivateKey,
+    ) -> Result<ServerCertificate, Error> {
+        let mut certificate = Vec::new();
+        certificate.extend_from_slice(&key_id.to_be_bytes());
+        put_bytes(&mut certificate, key.to_bytes()?.as_slice());
+        let signature =
+            crate::calculate_signature(ctx, trust_root, &certificate)?;
+
+        Ok(ServerCertificate {
+            key_id,
+            key: key.clone(),
+            certificate,
+            signature: signature.as_slice().to_vec(),
+        })
+    }
+
+    /// The ID of the server key, so it can be rotated.
+    pub const fn key_id(&self) -> u32 { self.key_id }
+
+    /// The key [`SenderCertificate`]s are signed with.
+    pub const fn key(&self) -> &PublicKey { &self.key }
+
+    /// Check that this certificate was signed by the trust root.
+    pub fn validate(&self, trust_root: &PublicKey) -> Result<(), Error> {
+        trust_root.verify_signature(&self.certificate, &self.signature)
+    }
+}
+
+impl Serializable for ServerCertificate {
+    fn serialize(&self) -> Result<Buffer, Error> {
+        let mut buffer = Vec::new();
+        put_bytes(&mut buffer, &self.certificate);
+        put_bytes(&mut buffer, &self.signature);
+
+        Ok(Buffer::from(buffer))
+    }
+}
+
+impl Deserializable for ServerCertificate {
+    fn deserialize(
+        ctx: &Context,
+        data: &[u8],
+    ) -> Result<ServerCertificate, Error> {
+        let mut reader = Reader::new(data);
+        let certificate = reader.bytes()?;
+        let signature = reader.bytes()?;
+        reader.finish()?;
+
+        let mut fields = Reader::new(certificate);
+        let key_id = fields.u32()?;
+        let key = PublicKey::decode_point(ctx, fields.bytes()?)?;
+        fields.finish()?;
+
+        Ok(ServerCertificate {
+            key_id,
+            key,
+            certificate: certificate.to_vec(),
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+/// A short-lived certificate, issued by the server, vouching for the
+/// sender's [`Address`] and identity key.
+#[derive(Debug, Clone)]
+pub struct SenderCertificate {
+    sender: Address,
+    identity_key: PublicKey,
+    expires: SystemTime,
+    signer: ServerCertificate,
+    /// The bytes covered by the signature.
+    certificate: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl SenderCertificate {
+    /// Issue a new [`SenderCertificate`], signing it with the private half of
+    /// the [`ServerCertificate`]'s key.
+    ///
+    /// The expiry time is stored with millisecond precision.
+    pub fn new(
+        ctx: &Context,
+        sender: Address,
+        identity_key: &PublicKey,
+        expires: SystemTime,
+        signer: ServerCertificate,
+        server_key: &PrivateKey,
+    ) -> Result<SenderCertificate, Error> {
+        let millis = expires
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_millis() as u64;
+
+        let mut certificate = Vec::new();
+        put_bytes(&mut certificate, sender.bytes());
+        certificate.extend_from_slice(&sender.device_id().to_be_bytes());
+        certificate.extend_from_slice(&millis.to_be_bytes());
+        put_bytes(&mut certificate, identity_key.to_bytes()?.as_slice());
+        put_bytes(&mut certificate, signer.serialize()?.as_slice());
+        let signature =
+            crate::calculate_signature(ctx, server_key, &certificate)?;
+
+        Ok(SenderCertificate {
+            sender,
+            identity_key: identity_key.clone(),
+            expires: SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+            signer,
+            certificate,
+            signature: signature.as_slice().to_vec(),
+        })
+    }
+
+    /// Who the certificate was issued to.
+    pub const fn sender(&self) -> &Address { &self.sender }
+
+    /// The sender's identity key.
+    pub const fn identity_key(&self) -> &PublicKey { &self.identity_key }
+
+    /// When the certificate stops being valid.
+    pub const fn expires(&self) -> SystemTime { self.expires }
+
+    /// The [`ServerCertificate`] whose key signed this certificate.
+    pub const fn signer(&self) -> &ServerCertificate { &self.signer }
+
+    /// Check that the certificate chains back to the trust root and hasn't
+    /// expired as of `now`.
+    pub fn validate(
+        &self,
+        trust_root: &PublicKey,
+        now: SystemTime,
+    ) -> Result<(), Error> {
+        self.signer.validate(trust_root)?;
+        self.signer
+            .key()
+            .verify_signature(&self.certificate, &self.signature)?;
+
+        if now > self.expires {
+            return Err(Error::ExpiredCertificate);
+        }
+
+        Ok(())
+    }
+}
+
+impl Serializable for SenderCertificate {
+    fn serialize(&self) -> Result<Buffer, Error> {
+        let mut buffer = Vec::new();
+        put_bytes(&mut buffer, &self.certificate);
+        put_bytes(&mut buffer, &self.signature);
+
+        Ok(Buffer::from(buffer))
+    }
+}
+
+impl Deserializable for SenderCertificate {
+    fn deserialize(
+        ctx: &Context,
+        data: &[u8],
+    ) -> Result<SenderCertificate, Error> {
+        let mut reader = Reader::new(data);
+        let certificate = reader.bytes()?;
+        let signature = reader.bytes()?;
+        reader.finish()?;
+
+        let mut fields = Reader::new(certificate);
+        let name = fields.bytes()?;
+        let device_id = fields.u32()? as i32;
+        let millis = fields.u64()?;
+        let identity_key = PublicKey::decode_point(ctx, fields.bytes()?)?;
+        let signer = ServerCertificate::deserialize(ctx, fields.bytes()?)?;
+        fields.finish()?;
+
+        Ok(SenderCertificate {
+            sender: Address::new(name, device_id),
+            identity_key,
+            expires: SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+            signer,
+            certificate: certificate.to_vec(),
+            signature: signature.to_vec(),
+        })
+    }
+}
+
+/// A message which has been [`unseal()`]ed, along with the (validated)
+/// certificate identifying who sent it.
+#[derive(Debug, Clone)]
+pub struct UnsealedMessage {
+    certificate: SenderCertificate,
+    message_type: CiphertextType,
+    message: Buffer,
+}
+
+impl UnsealedMessage {
+    /// Who sent the message.
+    pub const fn sender(&self) -> &Address { self.certificate.sender() }
+
+    /// The certificate the sender identified themselves with.
+    pub const fn certificate(&self) -> &SenderCertificate { &self.certificate }
+
+    /// What type of message is this?
+    pub const fn message_type(&self) -> CiphertextType { self.message_type }
+
+    /// The serialized message, ready to be deserialized as a
+    /// [`SignalMessage`](crate::messages::SignalMessage) or
+    /// [`PreKeySignalMessage`](crate::messages::PreKeySignalMessage)
+    /// (depending on its [`UnsealedMessage::message_type()`]).
+    pub fn message(&self) -> &[u8] { self.message.as_slice() }
+}
+
+/// Encrypt a message so only the recipient knows who sent it.
+pub fn seal(
+    ctx: &Context,
+    message: &CiphertextMessage,
+    certificate: &SenderCertificate,
+    sender: &IdentityKeyPair,
+    recipient: &PublicKey,
+) -> Result<Buffer, Error> {
+    seal_content(
+        ctx,
+        message.get_type()?,
+        message.serialize()?.as_slice(),
+        certificate,
+        sender,
+        recipient,
+    )
+}
+
+fn seal_content(
+    ctx: &Context,
+    message_type: CiphertextType,
+    message: &[u8],
+    certificate: &SenderCertificate,
+    sender: &IdentityKeyPair,
+    recipient: &PublicKey,
+) -> Result<Buffer, Error> {
+    let crypto = ctx.crypto();
+
+    let mut content = vec![message_type as u8];
+    put_bytes(&mut content, certificate.serialize()?.as_slice());
+    content.extend_from_slice(message);
+
+    let ephemeral = crate::generate_key_pair(ctx)?;
+    let ephemeral_public = ephemeral.public().to_bytes()?;
+    let salt = ephemeral_salt(recipient, ephemeral_public.as_slice())?;
+    let ephemeral_keys = DerivedKeys::new(
+        ctx,
+        &recipient.calculate_agreement(&ephemeral.private())?,
+        &salt,
+    )?;
+    let static_key = ephemeral_keys
+        .encrypt(crypto, sender.public().to_bytes()?.as_slice())?;
+
+    let salt = [ephemeral_keys.chain_key(), &static_key].concat();
+    let static_keys = DerivedKeys::new(
+        ctx,
+        &recipient.calculate_agreement(&sender.private())?,
+        &salt,
+    )?;
+    let encrypted_content = static_keys.encrypt(crypto, &content)?;
+
+    let mut sealed = vec![VERSION];
+    put_bytes(&mut sealed, ephemeral_public.as_slice());
+    put_bytes(&mut sealed, &static_key);
+    sealed.extend_from_slice(&encrypted_content);
+
+    Ok(Buffer::from(sealed))
+}
+
+/// Decrypt a sealed message and validate the sender's certificate.
+///
+/// # Note
+///
+/// This doesn't check whether the message was sent by the local client
+/// (e.g. when it is echoed back by the server).
+pub fn unseal(
+    ctx: &Context,
+    sealed: &[u8],
+    recipient: &IdentityKeyPair,
+    trust_root: &PublicKey,
+    now: SystemTime,
+) -> Result<UnsealedMessage, Error> {
+    let crypto = ctx.crypto();
+
+    let mut reader = Reader::new(sealed);
+    if reader.u8()? != VERSION {
+        return Err(InternalError::InvalidVersion.into());
+    }
+    let ephemeral_public = reader.bytes()?;
+    let static_key = reader.bytes()?;
+    let encrypted_content = reader.rest();
+
+    let ephemeral = PublicKey::decode_point(ctx, ephemeral_public)?;
+    let salt = ephemeral_salt(&recipient.public(), ephemeral_public)?;
+    let ephemeral_keys = DerivedKeys::new(
+        ctx,
+        &ephemeral.calculate_agreement(&recipient.private())?,
+        &salt,
+    )?;
+    let sender_identity = ephemeral_keys.decrypt(crypto, static_key)?;
+    let sender_identity =
+        PublicKey::decode_point(ctx, sender_identity.as_slice())?;
+
+    let salt = [ephemeral_keys.chain_key(), static_key].concat();
+    let static_keys = DerivedKeys::new(
+        ctx,
+        &sender_identity.calculate_agreement(&recipient.private())?,
+        &salt,
+    )?;
+    let content = static_keys.decrypt(crypto, encrypted_content)?;
+
+    let mut reader = Reader::new(content.as_slice());
+    let message_type = CiphertextType::from_raw(u32::from(reader.u8()?))?;
+    let certificate = SenderCertificate::deserialize(ctx, reader.bytes()?)?;
+    let message = Buffer::from(reader.rest());
+
+    certificate.validate(trust_root, now)?;
+    if *certificate.identity_key() != sender_identity {
+        // someone is trying to pass off another user's certificate as their
+        // own
+        return Err(Error::InvalidCertificate);
+    }
+
+    Ok(UnsealedMessage {
+        certificate,
+        message_type,
+        message,
+    })
+}
+
+fn ephemeral_salt(
+    recipient: &PublicKey,
+    ephemeral_public: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let recipient = recipient.to_bytes()?;
+
+    Ok([SALT_PREFIX, recipient.as_slice(), ephemeral_public].concat())
+}
+
+/// The keys used to encrypt one layer of a sealed message.
+struct DerivedKeys(SecretBuffer);
+
+impl DerivedKeys {
+    fn new(
+        ctx: &Context,
+        shared_secret: &SecretBuffer,
+        salt: &[u8],
+    ) -> Result<DerivedKeys, Error> {
+        let keys = crate::create_hkdf(ctx, HKDF_VERSION)?.derive_secrets(
+            DERIVED_KEYS_LENGTH,
+            shared_secret.as_slice(),
+            salt,
+            &[],
+        )?;
+
+        if keys.len() != DERIVED_KEYS_LENGTH {
+            return Err(Error::SecretsCalculationError);
+        }
+
+        Ok(DerivedKeys(keys))
+    }
+
+    fn chain_key(&self) -> &[u8] { &self.0.as_slice()[..KEY_LENGTH] }
+
+    fn cipher_key(&self) -> &[u8] {
+        &self.0.as_slice()[KEY_LENGTH..2 * KEY_LENGTH]
+    }
+
+    fn mac_key(&self) -> &[u8] { &self.0.as_slice()[2 * KEY_LENGTH..] }
+
+    /// Encrypt with AES-256-CTR, appending a truncated HMAC-SHA256.
+    fn encrypt(
+        &self,
+        crypto: &dyn Crypto,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut ciphertext = crypto.encrypt(
+            SignalCipherType::AesCtrNoPadding,
+            self.cipher_key(),
+            &IV,
+            plaintext,
+        )?;
+        let mac = self.mac(crypto, &ciphertext)?;
+        ciphertext.extend_from_slice(&mac);
+
+        Ok(ciphertext)
+    }
+
+    fn decrypt(
+        &self,
+        crypto: &dyn Crypto,
+        data: &[u8],
+    ) -> Result<SecretBuffer, Error> {
+        if data.len() < MAC_LENGTH {
+            return Err(InternalError::InvalidMessage.into());
+        }
+
+        let (ciphertext, their_mac) = data.split_at(data.len() - MAC_LENGTH);
+        let our_mac = self.mac(crypto, ciphertext)?;
+        if !constant_time_eq(&our_mac, their_mac) {
+            return Err(InternalError::InvalidMAC.into());
+        }
+
+        let plaintext = crypto.decrypt(
+            SignalCipherType::AesCtrNoPadding,
+            self.cipher_key(),
+            &IV,
+            ciphertext,
+        )?;
+
+        Ok(SecretBuffer::from(plaintext))
+    }
+
+    fn mac(
+        &self,
+        crypto: &dyn Crypto,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let mut hmac = crypto.hmac_sha256(self.mac_key())?;
+        hmac.update(ciphertext)?;
+        let mut mac = hmac.finalize()?;
+        mac.truncate(MAC_LENGTH);
+
+        Ok(mac)
+    }
+}
+
+/// Append a length-prefixed field.
+fn put_bytes(buffer: &mut Vec<u8>, data: &[u8]) {
+    buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buffer.extend_from_slice(data);
+}
+
+/// Pulls the fields written by [`put_bytes()`] (and friends) back out of a
+/// buffer.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    const fn new(data: &'a [u8]) -> Reader<'a> { Reader { data } }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.data.len() < len {
+            return Err(InternalError::InvalidMessage.into());
+        }
+
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> { Ok(self.take(1)?[0]) }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    const fn rest(self) -> &'a [u8] { self.data }
+
+    /// Make sure there's nothing left over.
+    fn finish(&self) -> Result<(), Error> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(InternalError::InvalidMessage.into())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "crypto-native"))]
+mod tests {
+    use super::*;
+
+    struct Setup {
+        ctx: Context,
+        trust_root: PublicKey,
+        certificate: SenderCertificate,
+        alice: IdentityKeyPair,
+        bob: IdentityKeyPair,
+        now: SystemTime,
+    }
+
+    fn setup() -> Setup {
+        let ctx = Context::default();
+        let trust_root = crate::generate_key_pair(&ctx).unwrap();
+        let server_key = crate::generate_key_pair(&ctx).unwrap();
+        let alice = crate::generate_identity_key_pair(&ctx).unwrap();
+        let bob = crate::generate_identity_key_pair(&ctx).unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+        let server_certificate = ServerCertificate::new(
+            &ctx,
+            1,
+            &server_key.public(),
+            &trust_root.private(),
+        )
+        .unwrap();
+        let certificate = SenderCertificate::new(
+            &ctx,
+            Address::new("alice", 1),
+            &alice.public(),
+            now + Duration::from_secs(60 * 60),
+            server_certificate,
+            &server_key.private(),
+        )
+        .unwrap();
+
+        Setup {
+            ctx,
+            trust_root: trust_root.public(),
+            certificate,
+            alice,
+            bob,
+            now,
+        }
+    }
+
+    fn seal_hello(s: &Setup, certificate: &SenderCertificate) -> Buffer {
+        seal_content(
+            &s.ctx,
+            CiphertextType::Signal,
+            b"Hello, Bob!",
+            certificate,
+            &s.alice,
+            &s.bob.public(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip_a_sealed_message() {
+        let s = setup();
+        let sealed = seal_hello(&s, &s.certificate);
+
+        let got =
+            unseal(&s.ctx, sealed.as_slice(), &s.bob, &s.trust_root, s.now)
+                .unwrap();
+
+        assert_eq!(got.sender(), &Address::new("alice", 1));
+        assert_eq!(got.message_type(), CiphertextType::Signal);
+        assert_eq!(got.message(), b"Hello, Bob!");
+        assert_eq!(got.certificate().identity_key(), &s.alice.public());
+    }
+
+    #[test]
+    fn certificates_survive_serialization() {
+        let s = setup();
+
+        let serialized = s.certificate.serialize().unwrap();
+        let got =
+            SenderCertificate::deserialize(&s.ctx, serialized.as_slice())
+                .unwrap();
+
+        assert_eq!(got.sender(), s.certificate.sender());
+        assert_eq!(got.expires(), s.certificate.expires());
+        assert_eq!(got.signer().key_id(), 1);
+        got.validate(&s.trust_root, s.now).unwrap();
+    }
+
+    #[test]
+    fn expired_certificates_are_rejected() {
+        let s = setup();
+        let sealed = seal_hello(&s, &s.certificate);
+        let later = s.now + Duration::from_secs(2 * 60 * 60);
+
+        let got =
+            unseal(&s.ctx, sealed.as_slice(), &s.bob, &s.trust_root, later);
+
+        match got {
+            Err(Error::ExpiredCertificate) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn certificates_must_chain_to_the_trust_root() {
+        let s = setup();
+        let sealed = seal_hello(&s, &s.certificate);
+        let impostor = crate::generate_key_pair(&s.ctx).unwrap().public();
+
+        let got = unseal(&s.ctx, sealed.as_slice(), &s.bob, &impostor, s.now);
+
+        match got {
+            Err(Error::InvalidSignature) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_certificate_must_belong_to_the_sender() {
+        let s = setup();
+        let mallory = crate::generate_identity_key_pair(&s.ctx).unwrap();
+        // mallory tries to send a message using alice's certificate
+        let sealed = seal_content(
+            &s.ctx,
+            CiphertextType::Signal,
+            b"Hello, Bob!",
+            &s.certificate,
+            &mallory,
+            &s.bob.public(),
+        )
+        .unwrap();
+
+        let got =
+            unseal(&s.ctx, sealed.as_slice(), &s.bob, &s.trust_root, s.now);
+
+        match got {
+            Err(Error::InvalidCertificate) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let s = setup();
+        let mut sealed = seal_hello(&s, &s.certificate).as_slice().to_vec();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 0x01;
+
+        let got = unseal(&s.ctx, &sealed, &s.bob, &s.trust_root, s.now);
+
+        match got {
+            Err(Error::InternalError(InternalError::InvalidMAC)) => {},
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    io::{self, Write},
+    ops::Deref,
+};
+
+use zeroize::Zeroize;
+
+use crate::{crypto::constant_time_eq, Buffer};
+
+// For rustdoc link resolution
+#[allow(unused_imports)]
+use crate::{
+    keys::{PrivateKey, PublicKey},
+    HMACBasedKeyDerivationFunction, SessionCipher,
+};
+
+/// A [`Buffer`] holding secret material (private keys, derived secrets,
+/// decrypted plaintext, etc.).
+///
+/// The contents are zeroed when the buffer is dropped and never show up in
+/// [`Debug`] output, and comparisons are done in constant time.
+///
+/// # Migrating
+///
+/// [`HMACBasedKeyDerivationFunction::derive_secrets()`],
+/// [`PublicKey::calculate_agreement()`], [`PrivateKey::to_bytes()`],
+/// [`SessionCipher::decrypt_message()`] and
+/// [`SessionCipher::decrypt_pre_key_message()`] used to return a
+/// `Vec<u8>` or [`Buffer`] and now return a [`SecretBuffer`]. It derefs to
+/// `[u8]` and can be compared with byte slices and arrays, so most code keeps
+/// compiling. Otherwise use `.into()` (or [`SecretBuffer::into_buffer()`]) to
+/// get a [`Buffer`], or `.to_vec()` to get a `Vec<u8>`.
+#[derive(Clone, Default)]
+pub struct SecretBuffer(Buffer);
+
+impl SecretBuffer {
+    /// Create a new empty buffer.
+    pub fn new() -> SecretBuffer { SecretBuffer(Buffer::new()) }
+
+    /// How many bytes are in this buffer?
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Is the buffer empty?
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Get an immutable reference to the underlying data.
+    pub fn as_slice(&self) -> &[u8] { self.0.as_slice() }
+
+    /// Get a mutable reference to the underlying data.
+    pub fn as_slice_mut(&mut self) -> &mut [u8] { self.0.as_slice_mut() }
+
+    /// Unwrap the underlying [`Buffer`].
+    ///
+    /// The [`Buffer`] will still be zeroed when it is dropped, but its
+    /// contents are no longer hidden from [`Debug`].
+    pub fn into_buffer(self) -> Buffer { self.0 }
+
+    /// Copy some secret bytes out of memory owned by `libsignal-protocol-c`,
+    /// wiping the original before it is freed.
+    pub(crate) unsafe fn copy_and_free(data: *mut u8, len: usize) -> Self {
+        let original = std::slice::from_raw_parts_mut(data, len);
+        let secret = SecretBuffer::from(&original[..]);
+
+        original.zeroize();
+        libc::free(data as *mut libc::c_void);
+
+        secret
+    }
+}
+
+impl Zeroize for SecretBuffer {
+    fn zeroize(&mut self) { self.0.zeroize() }
+}
+
+impl Debug for SecretBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBuffer([REDACTED; {}])", self.len())
+    }
+}
+
+impl PartialEq for SecretBuffer {
+    fn eq(&self, other: &SecretBuffer) -> bool {
+        constant_time_eq(self.as_slice(), other.as_slice())
+    }
+}
+
+impl Eq for SecretBuffer {}
+
+impl PartialEq<[u8]> for SecretBuffer {
+    fn eq(&self, other: &[u8]) -> bool {
+        constant_time_eq(self.as_slice(), other)
+    }
+}
+
+impl<'a> PartialEq<&'a [u8]> for SecretBuffer {
+    fn eq(&self, other: &&'a [u8]) -> bool {
+        constant_time_eq(self.as_slice(), other)
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for SecretBuffer {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        constant_time_eq(self.as_slice(), other)
+    }
+}
+
+impl Deref for SecretBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] { self.as_slice() }
+}
+
+impl From<SecretBuffer> for Buffer {
+    fn from(other: SecretBuffer) -> Buffer { other.into_buffer() }
+}
+
+impl From<Buffer> for SecretBuffer {
+    fn from(other: Buffer) -> SecretBuffer { SecretBuffer(other) }
+}
+
+impl From<Vec<u8>> for SecretBuffer {
+    fn from(other: Vec<u8>) -> SecretBuffer {
+        SecretBuffer(Buffer::from(other))
+    }
+}
+
+impl<'a> From<&'a [u8]> for SecretBuffer {
+    fn from(other: &'a [u8]) -> SecretBuffer {
+        SecretBuffer(Buffer::from(other))
+    }
+}
+
+impl AsRef<[u8]> for SecretBuffer {
+    fn as_ref(&self) -> &[u8] { self.as_slice() }
+}
+
+impl AsMut<[u8]> for SecretBuffer {
+    fn as_mut(&mut self) -> &mut [u8] { self.as_slice_mut() }
+}
+
+impl Write for SecretBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_output_is_redacted() {
+        let secret = SecretBuffer::from(&b"hunter2"[..]);
+
+        let got = format!("{:?}", secret);
+
+        assert_eq!(got, "SecretBuffer([REDACTED; 7])");
+    }
+
+    #[test]
+    fn writes_are_appended() {
+        let mut secret = SecretBuffer::new();
+
+        secret.write_all(b"Hello, ").unwrap();
+        secret.write_all(b"World!").unwrap();
+
+        assert_eq!(secret.as_slice(), b"Hello, World!");
+    }
+
+    #[test]
+    fn compare_with_plain_bytes() {
+        let secret = SecretBuffer::from(&b"hunter2"[..]);
+
+        assert_eq!(secret, SecretBuffer::from(&b"hunter2"[..]));
+        assert_eq!(secret, *b"hunter2");
+        assert_eq!(secret, &b"hunter2"[..]);
+        assert_ne!(secret, &b"hunter3"[..]);
+        assert_ne!(secret, &b"hunter"[..]);
+    }
+
+    #[test]
+    fn copy_secrets_out_of_c_memory() {
+        unsafe {
+            let data = libc::malloc(3) as *mut u8;
+            std::ptr::copy_nonoverlapping([1_u8, 2, 3].as_ptr(), data, 3);
+
+            let secret = SecretBuffer::copy_and_free(data, 3);
+
+            assert_eq!(secret.as_slice(), &[1, 2, 3]);
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! [`serde`] support for the types most often sent between clients and
+//! servers.
+//!
+//! Keys and byte buffers are written as base64 strings in human-readable
+//! formats (e.g. JSON) and as plain bytes everywhere else, while messages and
+//! pre-key bundles are written using their protobuf serialization.
+//!
+//! Anything backed by a `libsignal-protocol-c` object needs a [`Context`] to
+//! be deserialized, so it implements [`DeserializeWithContext`] instead of
+//! [`Deserialize`] and is deserialized with a [`ContextSeed`].
+
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+};
+
+use serde::{
+    de::{DeserializeSeed, Error as _},
+    ser::Error as _,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{
+    keys::PublicKey,
+    messages::{
+        CiphertextMessage, CiphertextType, PreKeySignalMessage, SignalMessage,
+    },
+    Address, Buffer, Context, Deserializable, PreKeyBundle, Serializable,
+};
+
+/// Something which needs a [`Context`] before it can be deserialized.
+///
+/// This is the [`Context`]-aware equivalent of [`Deserialize`], use a
+/// [`ContextSeed`] to call it.
+pub trait DeserializeWithContext<'de>: Sized {
+    /// Deserialize a value, creating any `libsignal-protocol-c` objects it
+    /// needs using `ctx`.
+    fn deserialize_with_context<D>(
+        ctx: &Context,
+        deserializer: D,
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// A [`DeserializeSeed`] for types which implement
+/// [`DeserializeWithContext`].
+///
+/// ```rust,no_run
+/// # use libsignal_protocol::{keys::PublicKey, Context, ContextSeed};
+/// use serde::de::DeserializeSeed;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let ctx = Context::default();
+/// let json = r#""BXu6QIKVz5MA8gstzfOgRQGqyLqOwNKHL6INkv3IHWMF""#;
+///
+/// let mut deserializer = serde_json::Deserializer::from_str(json);
+/// let key: PublicKey =
+///     ContextSeed::new(&ctx).deserialize(&mut deserializer)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ContextSeed<'ctx, T> {
+    ctx: &'ctx Context,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<'ctx, T> ContextSeed<'ctx, T> {
+    /// Create a new [`ContextSeed`].
+    pub const fn new(ctx: &'ctx Context) -> Self {
+        ContextSeed {
+            ctx,
+            _type: PhantomData,
+        }
+    }
+}
+
+impl<'de, 'ctx, T> DeserializeSeed<'de> for ContextSeed<'ctx, T>
+where
+    T: DeserializeWithContext<'de>,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_with_context(self.ctx, deserializer)
+    }
+}
+
+impl<'ctx, T> Debug for ContextSeed<'ctx, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContextSeed")
+            .field("type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+impl<'ctx, T> Clone for ContextSeed<'ctx, T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<'ctx, T> Copy for ContextSeed<'ctx, T> {}
+
+/// Bytes which are base64-encoded when the format is human-readable.
+mod base64_bytes {
+    use super::*;
+
+    pub fn serialize<B, S>(bytes: &B, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        B: AsRef<[u8]>,
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes.as_ref())
+        }
+    }
+
+    pub fn deserialize<'de, B, D>(deserializer: D) -> Result<B, D::Error>
+    where
+        B: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let encoded = Cow::<'de, str>::deserialize(deserializer)?;
+            base64::decode(encoded.as_bytes())
+                .map(B::from)
+                .map_err(D::Error::custom)
+        } else {
+            serde_bytes(deserializer).map(B::from)
+        }
+    }
+}
+
+/// Bytes which are written as a UTF-8 string when the format is
+/// human-readable.
+mod utf8_bytes {
+    use super::*;
+
+    pub fn serialize<B, S>(bytes: &B, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        B: AsRef<[u8]>,
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            let text = std::str::from_utf8(bytes.as_ref())
+                .map_err(S::Error::custom)?;
+            serializer.serialize_str(text)
+        } else {
+            serializer.serialize_bytes(bytes.as_ref())
+        }
+    }
+
+    pub fn deserialize<'de, B, D>(deserializer: D) -> Result<B, D::Error>
+    where
+        B: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer).map(|s| B::from(s.into_bytes()))
+        } else {
+            serde_bytes(deserializer).map(B::from)
+        }
+    }
+}
+
+/// Read a byte buffer, accepting anything a binary format might reasonably
+/// use for one.
+fn serde_bytes<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct BytesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("a byte buffer")
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> { Ok(v) }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Vec<u8>, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            let mut bytes =
+                Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+
+    deserializer.deserialize_byte_buf(BytesVisitor)
+}
+
+impl Serialize for Buffer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        base64_bytes::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Buffer {
+    fn deserialize<D>(deserializer: D) -> Result<Buffer, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        base64_bytes::deserialize(deserializer)
+    }
+}
+
+/// How an [`Address`] is represented, names are usually written as strings.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Address")]
+struct AddressRepr<'a> {
+    #[serde(with = "utf8_bytes")]
+    name: Cow<'a, [u8]>,
+    device_id: i32,
+}
+
+impl Serialize for Address {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        AddressRepr {
+            name: Cow::Borrowed(self.bytes()),
+            device_id: self.device_id(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = AddressRepr::deserialize(deserializer)?;
+        Ok(Address::new(repr.name, repr.device_id))
+    }
+}
+
+impl Serialize for PublicKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            let encoded = self.to_base64().map_err(S::Error::custom)?;
+            serializer.serialize_str(&encoded)
+        } else {
+            let bytes = self.to_bytes().map_err(S::Error::custom)?;
+            serializer.serialize_bytes(bytes.as_slice())
+        }
+    }
+}
+
+impl<'de> DeserializeWithContext<'de> for PublicKey {
+    fn deserialize_with_context<D>(
+        ctx: &Context,
+        deserializer: D,
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes: Vec<u8> = base64_bytes::deserialize(deserializer)?;
+        PublicKey::decode_point(ctx, &bytes).map_err(D::Error::custom)
+    }
+}
+
+/// Implement [`Serialize`] and [`DeserializeWithContext`] for something
+/// which is sent around as a protobuf.
+macro_rules! impl_serde_via_protobuf {
+    ($name:ty, |$this:ident| $serialized:expr) => {
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let $this = self;
+                let buffer = $serialized.map_err(S::Error::custom)?;
+                buffer.serialize(serializer)
+            }
+        }
+
+        impl<'de> DeserializeWithContext<'de> for $name {
+            fn deserialize_with_context<D>(
+                ctx: &Context,
+                deserializer: D,
+            ) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let buffer = Buffer::deserialize(deserializer)?;
+                <$name as Deserializable>::deserialize(ctx, buffer.as_slice())
+                    .map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+impl_serde_via_protobuf!(PreKeyBundle, |bundle| {
+    Serializable::serialize(bundle)
+});
+impl_serde_via_protobuf!(SignalMessage, |message| {
+    Serializable::serialize(&CiphertextMessage::from(message.clone()))
+});
+impl_serde_via_protobuf!(PreKeySignalMessage, |message| {
+    Serializable::serialize(&CiphertextMessage::from(message.clone()))
+});
+
+/// A [`CiphertextMessage`] can't be decoded without knowing what type of
+/// message it contains, so we need to keep track of that too.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "CiphertextMessage")]
+struct CiphertextMessageRepr {
+    #[serde(rename = "type")]
+    ty: CiphertextType,
+    body: Buffer,
+}
+
+impl Serialize for CiphertextMessage {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        CiphertextMessageRepr {
+            ty: self.get_type().map_err(S::Error::custom)?,
+            body: Serializable::serialize(self).map_err(S::Error::custom)?,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> DeserializeWithContext<'de> for CiphertextMessage {
+    fn deserialize_with_context<D>(
+        ctx: &Context,
+        deserializer: D,
+    ) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = CiphertextMessageRepr::deserialize(deserializer)?;
+        let body = repr.body.as_slice();
+
+        let message = match repr.ty {
+            CiphertextType::Signal => {
+                SignalMessage::deserialize(ctx, body).map(Into::into)
+            },
+            CiphertextType::PreKey => {
+                PreKeySignalMessage::deserialize(ctx, body).map(Into::into)
+            },
+            other => {
+                return Err(D::Error::custom(format_args!(
+                    "Unable to deserialize {:?} messages",
+                    other
+                )))
+            },
+        };
+
+        message.map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_base64_in_json() {
+        let buffer = Buffer::from(vec![0xde, 0xad, 0xbe, 0xef]);
+
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(json, r#""3q2+7w==""#);
+
+        let round_tripped: Buffer = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, buffer);
+    }
+
+    #[test]
+    fn addresses_use_their_name_as_a_string() {
+        let address = Address::new("+14159998888", 42);
+
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(json, r#"{"name":"+14159998888","device_id":42}"#);
+
+        let round_tripped: Address = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_tripped, address);
+    }
+
+    #[test]
+    fn non_utf8_names_cant_be_written_as_json() {
+        let address = Address::new([0xff, 0xfe], 1);
+
+        assert!(serde_json::to_string(&address).is_err());
+    }
+}
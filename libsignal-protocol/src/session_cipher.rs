@@ -1,13 +1,18 @@
 use crate::{
     context::{Context, ContextInner},
-    errors::FromInternalErrorCode,
-    messages::{CiphertextMessage, PreKeySignalMessage, SignalMessage},
+    errors::{FromInternalErrorCode, InternalError},
+    messages::{
+        CiphertextMessage, CiphertextType, PreKeySignalMessage, SignalMessage,
+    },
+    padding::Padding,
     raw_ptr::Raw,
+    session_limits::SessionLimits,
     store_context::{StoreContext, StoreContextInner},
-    Address, Buffer, Error,
+    Address, Buffer, Deserializable, Error, SecretBuffer,
 };
 
 use std::{
+    convert::TryFrom,
     fmt::{self, Debug, Formatter},
     ptr,
     rc::Rc,
@@ -19,6 +24,8 @@ pub struct SessionCipher {
     _ctx: Rc<ContextInner>,
     _store_ctx: Rc<StoreContextInner>,
     _addr: Address,
+    padding: Padding,
+    limits: SessionLimits,
 }
 
 impl SessionCipher {
@@ -43,12 +50,51 @@ impl SessionCipher {
                 _store_ctx: Rc::clone(&store_ctx.0),
                 _ctx: Rc::clone(&ctx.0),
                 _addr: address.clone(),
+                padding: Padding::None,
+                limits: SessionLimits::default(),
             })
         }
     }
 
-    /// Encrypt a message.
+    /// How messages are padded before they're encrypted.
+    pub const fn padding(&self) -> &Padding { &self.padding }
+
+    /// Start padding messages before they're encrypted (and stripping the
+    /// padding after they're decrypted).
+    ///
+    /// Padding is off by default. The recipient must use padding too.
+    pub fn set_padding(&mut self, padding: Padding) { self.padding = padding; }
+
+    /// How much old key material is kept for the session.
+    pub const fn limits(&self) -> SessionLimits { self.limits }
+
+    /// Keep less old key material than `libsignal-protocol-c` normally would.
+    ///
+    /// The session is trimmed down to these limits after each message is
+    /// successfully decrypted. Limits are *not* enforced when encrypting or
+    /// when the session is loaded, so a session which was saved before the
+    /// limits were set can exceed them until it next receives a message.
+    // a const fn can't take `&mut self` until Rust 1.83
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn set_limits(&mut self, limits: SessionLimits) {
+        self.limits = limits;
+    }
+
+    /// Encrypt a message, padding it first if
+    /// [padding](SessionCipher::set_padding) is enabled.
     pub fn encrypt(&self, message: &[u8]) -> Result<CiphertextMessage, Error> {
+        if self.padding == Padding::None {
+            self.encrypt_unpadded(message)
+        } else {
+            let padded = self.padding.pad(message)?;
+            self.encrypt_unpadded(&padded)
+        }
+    }
+
+    fn encrypt_unpadded(
+        &self,
+        message: &[u8],
+    ) -> Result<CiphertextMessage, Error> {
         unsafe {
             let mut raw = ptr::null_mut();
             sys::session_cipher_encrypt(
@@ -66,11 +112,13 @@ impl SessionCipher {
         }
     }
 
-    /// Decrypt a pre key message
+    /// Decrypt a pre key message, stripping any padding.
+    ///
+    /// Malformed padding is reported as [`Error::InvalidPadding`].
     pub fn decrypt_pre_key_message(
         &self,
         message: &PreKeySignalMessage,
-    ) -> Result<Buffer, Error> {
+    ) -> Result<SecretBuffer, Error> {
         unsafe {
             let mut buffer = ptr::null_mut();
             sys::session_cipher_decrypt_pre_key_signal_message(
@@ -81,15 +129,19 @@ impl SessionCipher {
             )
             .into_result()?;
 
-            Ok(Buffer::from_raw(buffer))
+            let plaintext = SecretBuffer::from(Buffer::from_raw(buffer));
+            self.enforce_limits();
+            self.padding.unpad(plaintext)
         }
     }
 
-    /// Decrypt a message
+    /// Decrypt a message, stripping any padding.
+    ///
+    /// Malformed padding is reported as [`Error::InvalidPadding`].
     pub fn decrypt_message(
         &self,
         message: &SignalMessage,
-    ) -> Result<Buffer, Error> {
+    ) -> Result<SecretBuffer, Error> {
         unsafe {
             let mut buffer = ptr::null_mut();
             sys::session_cipher_decrypt_signal_message(
@@ -100,7 +152,125 @@ impl SessionCipher {
             )
             .into_result()?;
 
-            Ok(Buffer::from_raw(buffer))
+            let plaintext = SecretBuffer::from(Buffer::from_raw(buffer));
+            self.enforce_limits();
+            self.padding.unpad(plaintext)
+        }
+    }
+
+    /// Decrypt a message of any type, using
+    /// [`SessionCipher::decrypt_pre_key_message()`] or
+    /// [`SessionCipher::decrypt_message()`] as appropriate.
+    ///
+    /// Sender key messages aren't encrypted with a [`SessionCipher`], so they
+    /// are rejected with [`Error::UnsupportedMessageType`].
+    pub fn decrypt(
+        &self,
+        message: &CiphertextMessage,
+    ) -> Result<DecryptedMessage, Error> {
+        match message.get_type()? {
+            CiphertextType::PreKey => {
+                let message = PreKeySignalMessage::try_from(message.clone())?;
+                self.decrypt_any_pre_key_message(&message)
+            },
+            CiphertextType::Signal => {
+                let message = SignalMessage::try_from(message.clone())?;
+                self.decrypt_any_signal_message(&message)
+            },
+            other => Err(Error::UnsupportedMessageType(other)),
+        }
+    }
+
+    /// Decrypt a serialized [`PreKeySignalMessage`] or [`SignalMessage`]
+    /// when you don't know which one it is.
+    ///
+    /// Messages from clients too old to support the current protocol version
+    /// are rejected with [`InternalError::LegacyMessage`]. Anything which
+    /// can't be parsed as a [`PreKeySignalMessage`] is assumed to be a
+    /// [`SignalMessage`], and if it isn't either the error from parsing it
+    /// as a [`PreKeySignalMessage`] is returned.
+    pub fn decrypt_serialized(
+        &self,
+        data: &[u8],
+    ) -> Result<DecryptedMessage, Error> {
+        if SignalMessage::is_legacy(data) {
+            return Err(InternalError::LegacyMessage.into());
+        }
+
+        let ctx = Context(Rc::clone(&self._ctx));
+
+        match PreKeySignalMessage::deserialize(&ctx, data) {
+            Ok(message) => self.decrypt_any_pre_key_message(&message),
+            Err(pre_key_error) => {
+                match SignalMessage::deserialize(&ctx, data) {
+                    Ok(message) => self.decrypt_any_signal_message(&message),
+                    Err(_) => Err(pre_key_error),
+                }
+            },
+        }
+    }
+
+    fn decrypt_any_pre_key_message(
+        &self,
+        message: &PreKeySignalMessage,
+    ) -> Result<DecryptedMessage, Error> {
+        // a pre-key message is only used to set up a session (and consume
+        // its one-time pre-key) the first time we see it
+        let store_ctx = StoreContext(Rc::clone(&self._store_ctx));
+        let record = store_ctx.load_session(&self._addr)?;
+        let new_session = !record.has_session_state(
+            u32::from(message.message_version()),
+            &message.base_key(),
+        );
+
+        let plaintext = self.decrypt_pre_key_message(message)?;
+
+        Ok(DecryptedMessage {
+            plaintext,
+            new_session,
+            pre_key_id: if new_session {
+                message.pre_key_id()
+            } else {
+                None
+            },
+        })
+    }
+
+    fn decrypt_any_signal_message(
+        &self,
+        message: &SignalMessage,
+    ) -> Result<DecryptedMessage, Error> {
+        Ok(DecryptedMessage {
+            plaintext: self.decrypt_message(message)?,
+            new_session: false,
+            pre_key_id: None,
+        })
+    }
+
+    /// Trim the stored session down to our [`SessionLimits`].
+    ///
+    /// By now the message has been decrypted and the session saved, so
+    /// failing here would lose the message. Log it and try again next time.
+    fn enforce_limits(&self) {
+        if self.limits == SessionLimits::default() {
+            // libsignal-protocol-c already takes care of these
+            return;
+        }
+
+        let store_ctx = StoreContext(Rc::clone(&self._store_ctx));
+        let result = store_ctx.load_session(&self._addr).and_then(|record| {
+            if self.limits.apply(&record)? {
+                store_ctx.store_session(&self._addr, &record)?;
+            }
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            log::warn!(
+                "Unable to apply the session limits for {:?}: {}",
+                self._addr,
+                e
+            );
         }
     }
 
@@ -128,6 +298,33 @@ impl SessionCipher {
     }
 }
 
+/// The result of [`SessionCipher::decrypt()`].
+#[derive(Debug)]
+pub struct DecryptedMessage {
+    plaintext: SecretBuffer,
+    new_session: bool,
+    pre_key_id: Option<u32>,
+}
+
+impl DecryptedMessage {
+    /// The decrypted message, with any padding stripped.
+    pub const fn plaintext(&self) -> &SecretBuffer { &self.plaintext }
+
+    /// Get the decrypted message, with any padding stripped.
+    pub fn into_plaintext(self) -> SecretBuffer { self.plaintext }
+
+    /// Did this message set up a new session with the sender?
+    pub const fn new_session(&self) -> bool { self.new_session }
+
+    /// The ID of the one-time pre-key used to set up the new session, if
+    /// there was one.
+    ///
+    /// The pre-key has been removed from the
+    /// [`PreKeyStore`](crate::stores::PreKeyStore), so the server should be
+    /// given a replacement.
+    pub const fn pre_key_id(&self) -> Option<u32> { self.pre_key_id }
+}
+
 impl Drop for SessionCipher {
     fn drop(&mut self) {
         unsafe {
@@ -0,0 +1,170 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{Error, ReceiverChain, Serializable, SessionRecord};
+
+/// A summary of how big a [`SessionRecord`] is and what it contains, for
+/// figuring out why stored sessions keep growing.
+///
+/// The [`Display`] impl writes everything on a single line, which is handy
+/// for logging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDiagnostics {
+    /// The version of the current session.
+    pub version: u32,
+    /// How many bytes the record takes up once serialized.
+    pub serialized_size: usize,
+    /// How many previous sessions are being kept around.
+    pub archived_states: usize,
+    /// The current session's receiver chains, oldest first.
+    pub receiver_chains: Vec<ChainDiagnostics>,
+    /// The total number of skipped message keys held by archived states.
+    pub archived_message_keys: usize,
+    /// Are we still waiting for the other party to reply to our
+    /// pre-key message?
+    pub unacknowledged_pre_key_message: bool,
+    /// Has the current session been flagged as needing to be set up again?
+    pub needs_refresh: bool,
+}
+
+impl SessionDiagnostics {
+    pub(crate) fn for_record(
+        record: &SessionRecord,
+    ) -> Result<SessionDiagnostics, Error> {
+        let state = record.state();
+        let archived = record.archived_states();
+
+        let receiver_chains = state
+            .receiver_chains()?
+            .iter()
+            .map(ChainDiagnostics::from)
+            .collect();
+        let archived_message_keys = archived
+            .iter()
+            .map(|state| state.skipped_message_key_count())
+            .sum::<Result<usize, Error>>()?;
+
+        Ok(SessionDiagnostics {
+            version: state.version(),
+            serialized_size: record.serialize()?.as_slice().len(),
+            archived_states: archived.len(),
+            receiver_chains,
+            archived_message_keys,
+            unacknowledged_pre_key_message: state
+                .has_unacknowledged_pre_key_message(),
+            needs_refresh: state.needs_refresh(),
+        })
+    }
+
+    /// The total number of skipped message keys held by the current session.
+    pub fn message_keys(&self) -> usize {
+        self.receiver_chains
+            .iter()
+            .map(|chain| chain.skipped_message_keys)
+            .sum()
+    }
+}
+
+impl Display for SessionDiagnostics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "v{} session, {} bytes, {} archived states ({} message keys), \
+             {} receiver chains (",
+            self.version,
+            self.serialized_size,
+            self.archived_states,
+            self.archived_message_keys,
+            self.receiver_chains.len(),
+        )?;
+
+        for (i, chain) in self.receiver_chains.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", chain.skipped_message_keys)?;
+        }
+        f.write_str(" message keys)")?;
+
+        if self.unacknowledged_pre_key_message {
+            f.write_str(", unacknowledged pre-key message")?;
+        }
+        if self.needs_refresh {
+            f.write_str(", needs refresh")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Diagnostics for a single [`ReceiverChain`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ChainDiagnostics {
+    /// The counter of the next message expected on this chain.
+    pub index: u32,
+    /// How many keys for skipped messages are being kept.
+    pub skipped_message_keys: usize,
+}
+
+impl From<&ReceiverChain> for ChainDiagnostics {
+    fn from(chain: &ReceiverChain) -> ChainDiagnostics {
+        ChainDiagnostics {
+            index: chain.index(),
+            skipped_message_keys: chain.skipped_message_keys().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostics() -> SessionDiagnostics {
+        SessionDiagnostics {
+            version: 3,
+            serialized_size: 1234,
+            archived_states: 2,
+            receiver_chains: vec![
+                ChainDiagnostics {
+                    index: 7,
+                    skipped_message_keys: 0,
+                },
+                ChainDiagnostics {
+                    index: 12,
+                    skipped_message_keys: 5,
+                },
+            ],
+            archived_message_keys: 3,
+            unacknowledged_pre_key_message: false,
+            needs_refresh: false,
+        }
+    }
+
+    #[test]
+    fn display_fits_on_one_line() {
+        let got = diagnostics().to_string();
+
+        assert_eq!(
+            got,
+            "v3 session, 1234 bytes, 2 archived states (3 message keys), 2 \
+             receiver chains (0, 5 message keys)"
+        );
+    }
+
+    #[test]
+    fn flags_are_only_shown_when_set() {
+        let mut diagnostics = diagnostics();
+        diagnostics.unacknowledged_pre_key_message = true;
+        diagnostics.needs_refresh = true;
+
+        let got = diagnostics.to_string();
+
+        assert!(got.ends_with(
+            "message keys), unacknowledged pre-key message, needs refresh"
+        ));
+    }
+
+    #[test]
+    fn message_keys_are_summed_across_chains() {
+        assert_eq!(diagnostics().message_keys(), 5);
+    }
+}
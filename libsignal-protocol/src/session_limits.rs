@@ -0,0 +1,126 @@
+use std::iter;
+
+use crate::{Error, SessionRecord};
+
+/// Limits on how much old key material is kept for a session.
+///
+/// Keeping keys for skipped messages lets them be decrypted if they arrive
+/// out of order, and archived states let messages from before a session was
+/// re-established be decrypted. Both make the session's [`SessionRecord`]
+/// bigger.
+///
+/// `libsignal-protocol-c` enforces its own (fairly generous) limits, so these
+/// can only be used to make them stricter. Raising them would need a change
+/// to `libsignal-protocol-c` itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SessionLimits {
+    max_skipped_message_keys: usize,
+    max_archived_states: usize,
+}
+
+impl SessionLimits {
+    /// The number of skipped message keys `libsignal-protocol-c` keeps per
+    /// receiver chain.
+    pub const MAX_SKIPPED_MESSAGE_KEYS: usize = 2000;
+    /// The number of archived states `libsignal-protocol-c` keeps.
+    pub const MAX_ARCHIVED_STATES: usize = 40;
+
+    /// Create a new set of [`SessionLimits`].
+    ///
+    /// Returns [`Error::SessionLimitTooHigh`] if either limit is above the
+    /// one `libsignal-protocol-c` already enforces
+    /// ([`SessionLimits::MAX_SKIPPED_MESSAGE_KEYS`] and
+    /// [`SessionLimits::MAX_ARCHIVED_STATES`]), because it wouldn't have any
+    /// effect.
+    pub const fn new(
+        max_skipped_message_keys: usize,
+        max_archived_states: usize,
+    ) -> Result<SessionLimits, Error> {
+        if max_skipped_message_keys > SessionLimits::MAX_SKIPPED_MESSAGE_KEYS
+            || max_archived_states > SessionLimits::MAX_ARCHIVED_STATES
+        {
+            return Err(Error::SessionLimitTooHigh);
+        }
+
+        Ok(SessionLimits {
+            max_skipped_message_keys,
+            max_archived_states,
+        })
+    }
+
+    /// How many keys for skipped messages to keep for each receiver chain.
+    pub const fn max_skipped_message_keys(&self) -> usize {
+        self.max_skipped_message_keys
+    }
+
+    /// How many previous session states to keep.
+    pub const fn max_archived_states(&self) -> usize {
+        self.max_archived_states
+    }
+
+    /// Trim `record` down to these limits, dropping the oldest keys and
+    /// states first.
+    ///
+    /// Returns `true` if anything was removed.
+    pub(crate) fn apply(&self, record: &SessionRecord) -> Result<bool, Error> {
+        let mut changed =
+            record.truncate_archived_states(self.max_archived_states) > 0;
+
+        let states = iter::once(record.state()).chain(record.archived_states());
+
+        for state in states {
+            for chain in state.receiver_chains()? {
+                let skipped = chain.skipped_message_keys();
+                let excess =
+                    skipped.len().saturating_sub(self.max_skipped_message_keys);
+
+                for &counter in &skipped[..excess] {
+                    changed |= state.remove_message_keys(
+                        chain.sender_ratchet_key(),
+                        counter,
+                    )?;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+impl Default for SessionLimits {
+    fn default() -> SessionLimits {
+        SessionLimits {
+            max_skipped_message_keys: SessionLimits::MAX_SKIPPED_MESSAGE_KEYS,
+            max_archived_states: SessionLimits::MAX_ARCHIVED_STATES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_can_only_be_lowered() {
+        let limits = SessionLimits::new(2, 0).unwrap();
+        assert_eq!(limits.max_skipped_message_keys(), 2);
+        assert_eq!(limits.max_archived_states(), 0);
+
+        let default = SessionLimits::new(
+            SessionLimits::MAX_SKIPPED_MESSAGE_KEYS,
+            SessionLimits::MAX_ARCHIVED_STATES,
+        )
+        .unwrap();
+        assert_eq!(default, SessionLimits::default());
+
+        assert!(SessionLimits::new(
+            SessionLimits::MAX_SKIPPED_MESSAGE_KEYS + 1,
+            0
+        )
+        .is_err());
+        assert!(
+            SessionLimits::new(0, SessionLimits::MAX_ARCHIVED_STATES + 1)
+                .is_err()
+        );
+    }
+}
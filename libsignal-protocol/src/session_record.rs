@@ -1,5 +1,10 @@
-use crate::{raw_ptr::Raw, ContextInner, SessionState};
-use std::rc::Rc;
+use crate::{
+    errors::{Error, FromInternalErrorCode},
+    keys::PublicKey,
+    raw_ptr::Raw,
+    Context, ContextInner, SessionDiagnostics, SessionState,
+};
+use std::{ptr, rc::Rc};
 
 /// The serialized state of a session.
 #[derive(Debug, Clone)]
@@ -9,6 +14,23 @@ pub struct SessionRecord {
 }
 
 impl SessionRecord {
+    /// Create a new record whose current session is `state`.
+    pub fn new(
+        ctx: &Context,
+        state: &SessionState,
+    ) -> Result<SessionRecord, Error> {
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::session_record_create(&mut raw, state.raw.as_ptr(), ctx.raw())
+                .into_result()?;
+
+            Ok(SessionRecord {
+                raw: Raw::from_ptr(raw),
+                ctx: Rc::clone(&ctx.0),
+            })
+        }
+    }
+
     /// Get the state.
     pub fn state(&self) -> SessionState {
         unsafe {
@@ -20,6 +42,86 @@ impl SessionRecord {
             }
         }
     }
+
+    /// Previous sessions with the same recipient, most recent first.
+    ///
+    /// These are kept so messages which were sent before the current session
+    /// was set up can still be decrypted.
+    pub fn archived_states(&self) -> Vec<SessionState> {
+        let mut states = Vec::new();
+
+        unsafe {
+            let mut node =
+                sys::session_record_get_previous_states_head(self.raw.as_ptr());
+
+            while !node.is_null() {
+                let raw = sys::session_record_get_previous_states_element(node);
+                assert!(!raw.is_null());
+                states.push(SessionState {
+                    raw: Raw::copied_from(raw),
+                    _ctx: Rc::clone(&self.ctx),
+                });
+
+                node = sys::session_record_get_previous_states_next(node);
+            }
+        }
+
+        states
+    }
+
+    /// Throw away all but the `max` most recent archived states, returning
+    /// how many were removed.
+    pub(crate) fn truncate_archived_states(&self, max: usize) -> usize {
+        let mut removed = 0;
+
+        unsafe {
+            let mut node =
+                sys::session_record_get_previous_states_head(self.raw.as_ptr());
+
+            for _ in 0..max {
+                if node.is_null() {
+                    return 0;
+                }
+                node = sys::session_record_get_previous_states_next(node);
+            }
+
+            while !node.is_null() {
+                node = sys::session_record_get_previous_states_remove(
+                    self.raw.as_ptr(),
+                    node,
+                );
+                removed += 1;
+            }
+        }
+
+        removed
+    }
+
+    /// Does this record contain a session (either current or archived) set
+    /// up with the given version and base key?
+    pub fn has_session_state(
+        &self,
+        version: u32,
+        base_key: &PublicKey,
+    ) -> bool {
+        unsafe {
+            sys::session_record_has_session_state(
+                self.raw.as_ptr(),
+                version,
+                base_key.raw.as_const_ptr(),
+            ) != 0
+        }
+    }
+
+    /// Is this a new record which hasn't been used for a session yet?
+    pub fn is_fresh(&self) -> bool {
+        unsafe { sys::session_record_is_fresh(self.raw.as_ptr()) != 0 }
+    }
+
+    /// Summarise how big this record is and what's taking up the space.
+    pub fn diagnostics(&self) -> Result<SessionDiagnostics, Error> {
+        SessionDiagnostics::for_record(self)
+    }
 }
 
 impl_serializable!(SessionRecord, session_record_serialize);
@@ -1,5 +1,12 @@
-use crate::{raw_ptr::Raw, ContextInner};
-use std::rc::Rc;
+use crate::{
+    errors::{Error, FromInternalErrorCode, InternalError},
+    keys::PublicKey,
+    raw_ptr::Raw,
+    Buffer, Context, ContextInner,
+};
+use prost::Message;
+use std::{ptr, rc::Rc};
+use zeroize::Zeroize;
 
 /// The internal state associated with a session.
 #[derive(Debug, Clone)]
@@ -9,8 +16,230 @@ pub struct SessionState {
 }
 
 impl SessionState {
+    /// Create a new, empty session state.
+    pub(crate) fn new(ctx: &Context) -> Result<SessionState, Error> {
+        unsafe {
+            let mut raw = ptr::null_mut();
+            sys::session_state_create(&mut raw, ctx.raw()).into_result()?;
+
+            Ok(SessionState {
+                raw: Raw::from_ptr(raw),
+                _ctx: Rc::clone(&ctx.0),
+            })
+        }
+    }
+
     /// Get the session version.
     pub fn version(&self) -> u32 {
         unsafe { sys::session_state_get_session_version(self.raw.as_ptr()) }
     }
+
+    /// Our registration ID.
+    pub fn local_registration_id(&self) -> u32 {
+        unsafe {
+            sys::session_state_get_local_registration_id(
+                self.raw.as_const_ptr(),
+            )
+        }
+    }
+
+    /// Set our registration ID.
+    pub fn set_local_registration_id(&mut self, id: u32) {
+        unsafe {
+            sys::session_state_set_local_registration_id(self.raw.as_ptr(), id);
+        }
+    }
+
+    /// The other party's registration ID.
+    pub fn remote_registration_id(&self) -> u32 {
+        unsafe {
+            sys::session_state_get_remote_registration_id(
+                self.raw.as_const_ptr(),
+            )
+        }
+    }
+
+    /// Set the other party's registration ID.
+    pub fn set_remote_registration_id(&mut self, id: u32) {
+        unsafe {
+            sys::session_state_set_remote_registration_id(
+                self.raw.as_ptr(),
+                id,
+            );
+        }
+    }
+
+    /// Are we still sending [`PreKeySignalMessage`]s because the other party
+    /// hasn't replied yet?
+    ///
+    /// [`PreKeySignalMessage`]: crate::messages::PreKeySignalMessage
+    pub fn has_unacknowledged_pre_key_message(&self) -> bool {
+        unsafe {
+            sys::session_state_has_unacknowledged_pre_key_message(
+                self.raw.as_const_ptr(),
+            ) != 0
+        }
+    }
+
+    /// Has this session been flagged as needing to be set up again?
+    pub fn needs_refresh(&self) -> bool {
+        unsafe {
+            sys::session_state_get_needs_refresh(self.raw.as_const_ptr()) != 0
+        }
+    }
+
+    /// Do we still have the keys for a message we skipped over?
+    pub fn has_message_keys(
+        &self,
+        sender_ratchet_key: &PublicKey,
+        counter: u32,
+    ) -> bool {
+        unsafe {
+            sys::session_state_has_message_keys(
+                self.raw.as_ptr(),
+                sender_ratchet_key.raw.as_ptr(),
+                counter,
+            ) != 0
+        }
+    }
+
+    /// Throw away the keys for a skipped message.
+    ///
+    /// Returns `false` if there were no keys to remove.
+    pub(crate) fn remove_message_keys(
+        &self,
+        sender_ratchet_key: &PublicKey,
+        counter: u32,
+    ) -> Result<bool, Error> {
+        unsafe {
+            let mut keys: sys::ratchet_message_keys = std::mem::zeroed();
+            // like session_state_has_message_keys(), this returns 1 if the
+            // keys were found and 0 if they weren't
+            let result = sys::session_state_remove_message_keys(
+                self.raw.as_ptr(),
+                &mut keys,
+                sender_ratchet_key.raw.as_ptr(),
+                counter,
+            );
+            keys.cipher_key.zeroize();
+            keys.mac_key.zeroize();
+            keys.iv.zeroize();
+
+            if result < 0 {
+                result.into_result()?;
+            }
+
+            Ok(result != 0)
+        }
+    }
+
+    /// The chains used to receive messages from the other party, oldest
+    /// first.
+    pub fn receiver_chains(&self) -> Result<Vec<ReceiverChain>, Error> {
+        let ctx = Context(Rc::clone(&self._ctx));
+
+        self.structure()?
+            .receiver_chains
+            .into_iter()
+            .map(|chain| {
+                Ok(ReceiverChain {
+                    sender_ratchet_key: PublicKey::decode_point(
+                        &ctx,
+                        &chain.sender_ratchet_key.unwrap_or_default(),
+                    )?,
+                    index: chain
+                        .chain_key
+                        .and_then(|key| key.index)
+                        .unwrap_or_default(),
+                    skipped_message_keys: chain
+                        .message_keys
+                        .into_iter()
+                        .map(|key| key.index.unwrap_or_default())
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// How many keys for skipped messages are being kept, across all
+    /// [receiver chains](SessionState::receiver_chains)?
+    pub fn skipped_message_key_count(&self) -> Result<usize, Error> {
+        Ok(self
+            .structure()?
+            .receiver_chains
+            .iter()
+            .map(|chain| chain.message_keys.len())
+            .sum())
+    }
+
+    /// Parse the serialized state so we can look at the bits
+    /// `libsignal-protocol-c` doesn't give us accessors for.
+    fn structure(&self) -> Result<proto::SessionStructure, Error> {
+        let serialized = unsafe {
+            let mut buffer = ptr::null_mut();
+            sys::session_state_serialize(&mut buffer, self.raw.as_ptr())
+                .into_result()?;
+            Buffer::from_raw(buffer)
+        };
+
+        proto::SessionStructure::decode(serialized.as_slice())
+            .map_err(|_| InternalError::InvalidProtoBuf.into())
+    }
+}
+
+/// A chain of message keys for receiving messages, started whenever the other
+/// party sends us a new ratchet key.
+#[derive(Debug, Clone)]
+pub struct ReceiverChain {
+    sender_ratchet_key: PublicKey,
+    index: u32,
+    skipped_message_keys: Vec<u32>,
+}
+
+impl ReceiverChain {
+    /// The ratchet key the other party is sending messages with.
+    pub const fn sender_ratchet_key(&self) -> &PublicKey {
+        &self.sender_ratchet_key
+    }
+
+    /// The counter of the next message we expect on this chain.
+    pub const fn index(&self) -> u32 { self.index }
+
+    /// The counters of messages we skipped over, and are keeping the keys for
+    /// in case they turn up later.
+    pub fn skipped_message_keys(&self) -> &[u32] { &self.skipped_message_keys }
+}
+
+/// Just enough of `LocalStorageProtocol.proto` to inspect a session.
+///
+/// Everything we don't read is skipped, so these must never be used to write
+/// a session back out.
+mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct SessionStructure {
+        #[prost(message, repeated, tag = "7")]
+        pub receiver_chains: Vec<Chain>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct Chain {
+        #[prost(bytes, optional, tag = "1")]
+        pub sender_ratchet_key: Option<Vec<u8>>,
+        #[prost(message, optional, tag = "3")]
+        pub chain_key: Option<ChainKey>,
+        #[prost(message, repeated, tag = "4")]
+        pub message_keys: Vec<MessageKey>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct ChainKey {
+        #[prost(uint32, optional, tag = "1")]
+        pub index: Option<u32>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub(super) struct MessageKey {
+        #[prost(uint32, optional, tag = "1")]
+        pub index: Option<u32>,
+    }
 }
@@ -106,6 +106,25 @@ impl StoreContext {
         }
     }
 
+    /// Save the session for the provided recipient, replacing any existing
+    /// one.
+    pub fn store_session(
+        &self,
+        addr: &Address,
+        record: &SessionRecord,
+    ) -> Result<(), Error> {
+        unsafe {
+            sys::signal_protocol_session_store_session(
+                self.raw(),
+                addr.raw(),
+                record.raw.as_ptr(),
+            )
+            .into_result()?;
+        }
+
+        Ok(())
+    }
+
     /// Load the sub-device sessions corresponding to the provided recipient
     /// identifier.
     pub fn get_sub_device_sessions(
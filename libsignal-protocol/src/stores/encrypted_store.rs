@@ -0,0 +1,639 @@
+use crate::{
+    crypto::{constant_time_eq, Crypto, SignalCipherType},
+    stores::{
+        IdentityKeyStore, IdentityRecord, PreKeyStore, SerializedSession,
+        SessionStore, SignedPreKeyStore, VerifiedStatus,
+    },
+    Address, Buffer, Error, InternalError,
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fmt::{self, Debug, Formatter},
+    io::{self, Write},
+    sync::Mutex,
+};
+use zeroize::Zeroizing;
+
+const FORMAT_VERSION: u8 = 1;
+const KEY_LENGTH: usize = 32;
+const IV_LENGTH: usize = 16;
+const MAC_LENGTH: usize = 32;
+const HEADER_LENGTH: usize = 1 + 4 + IV_LENGTH;
+
+const ENCRYPTION_KEY_INFO: &[u8] = b"libsignal-protocol-rs storage encryption";
+const MAC_KEY_INFO: &[u8] = b"libsignal-protocol-rs storage authentication";
+
+/// A caller-supplied key used by an [`EncryptedStore`] to seal records.
+///
+/// Each key has an ID which is written alongside every record it seals, so
+/// records sealed with an older key can still be opened after
+/// [`EncryptedStore::rotate_key`].
+#[derive(Clone)]
+pub struct StorageKey {
+    id: u32,
+    key: Zeroizing<Vec<u8>>,
+}
+
+impl StorageKey {
+    /// Create a new [`StorageKey`] from 32 bytes of key material.
+    pub fn new(id: u32, key: &[u8]) -> Result<StorageKey, Error> {
+        if key.len() != KEY_LENGTH {
+            return Err(InternalError::InvalidKey.into());
+        }
+
+        Ok(StorageKey {
+            id,
+            key: Zeroizing::new(key.to_vec()),
+        })
+    }
+
+    /// Generate a new random [`StorageKey`].
+    pub fn generate(crypto: &dyn Crypto, id: u32) -> Result<StorageKey, Error> {
+        let mut key = Zeroizing::new(vec![0; KEY_LENGTH]);
+        crypto.fill_random(&mut key)?;
+
+        Ok(StorageKey { id, key })
+    }
+
+    /// The ID written alongside records sealed with this key.
+    pub const fn id(&self) -> u32 { self.id }
+}
+
+impl Debug for StorageKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageKey")
+            .field("id", &self.id)
+            .field("key", &"<elided>")
+            .finish()
+    }
+}
+
+/// A wrapper around any of the store traits which encrypts records before
+/// they reach the underlying store.
+///
+/// Records are encrypted using AES-256-CBC and authenticated with
+/// HMAC-SHA256 (using the provided [`Crypto`] implementation), under keys
+/// derived from a [`StorageKey`]. The MAC also covers the [`Address`] or ID
+/// the record is stored under, so a record can't be swapped with another
+/// one by someone with access to the underlying storage.
+///
+/// When a record sealed with an older key is loaded it is transparently
+/// re-sealed with the current key and written back to the inner store.
+///
+/// # What Isn't Encrypted
+///
+/// Pre-keys, signed pre-keys and sessions (including their extra data) are
+/// always sealed. The [`IdentityKeyStore`] impl is much more limited:
+///
+/// - The local private identity key is only opened on the way out. The inner
+///   store must already hold it in its sealed form (see
+///   [`EncryptedStore::seal_private_identity_key`]), otherwise loading it
+///   fails.
+/// - The local public identity key and registration ID are stored in the
+///   clear.
+/// - Remote clients' [`IdentityRecord`]s are passed through untouched. That
+///   includes the identity key, its [`VerifiedStatus`], any nonblocking
+///   approval and when the key was first seen and last changed. The inner
+///   store makes trust decisions by comparing stored keys and reading this
+///   metadata, which it can't do if it only ever sees ciphertext.
+///
+/// The IDs and [`Address`]es records are stored under, and the length of
+/// each record, are also visible to the inner store.
+pub struct EncryptedStore<S> {
+    inner: S,
+    crypto: Box<dyn Crypto>,
+    keys: Mutex<KeyRing>,
+}
+
+impl<S> EncryptedStore<S> {
+    /// Wrap an existing store, sealing new records with `key`.
+    pub fn new<C: Crypto + 'static>(
+        inner: S,
+        crypto: C,
+        key: StorageKey,
+    ) -> Result<EncryptedStore<S>, Error> {
+        let crypto: Box<dyn Crypto> = Box::new(crypto);
+        let mut keys = KeyRing {
+            current: key.id,
+            keys: HashMap::new(),
+        };
+        keys.keys.insert(key.id, DerivedKeys::new(&*crypto, &key)?);
+
+        Ok(EncryptedStore {
+            inner,
+            crypto,
+            keys: Mutex::new(keys),
+        })
+    }
+
+    /// Start sealing records with a new key.
+    ///
+    /// Previous keys are kept around so existing records can still be read
+    /// until they are [retired](EncryptedStore::retire_key).
+    pub fn rotate_key(&self, key: StorageKey) -> Result<(), Error> {
+        let derived = DerivedKeys::new(&*self.crypto, &key)?;
+        let mut keys = self.keys.lock().unwrap();
+        keys.keys.insert(key.id, derived);
+        keys.current = key.id;
+
+        Ok(())
+    }
+
+    /// Forget an old key, returning `false` if it isn't known. Any records
+    /// still sealed with it will fail to load.
+    ///
+    /// The current key can't be retired.
+    pub fn retire_key(&self, id: u32) -> bool {
+        let mut keys = self.keys.lock().unwrap();
+
+        if keys.current == id {
+            return false;
+        }
+
+        keys.keys.remove(&id).is_some()
+    }
+
+    /// The ID of the key new records are sealed with.
+    pub fn current_key_id(&self) -> u32 { self.keys.lock().unwrap().current }
+
+    /// Get a reference to the underlying store.
+    pub const fn inner(&self) -> &S { &self.inner }
+
+    /// Unwrap the underlying store.
+    pub fn into_inner(self) -> S { self.inner }
+
+    /// Seal the local client's private identity key so it can be kept by the
+    /// inner [`IdentityKeyStore`].
+    pub fn seal_private_identity_key(
+        &self,
+        private_key: &[u8],
+    ) -> Result<Buffer, Error> {
+        let sealed = self.seal(&Binding::IdentityKeyPair, private_key)?;
+        Ok(Buffer::from(sealed))
+    }
+
+    fn seal(
+        &self,
+        binding: &Binding<'_>,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        let (key_id, keys) = {
+            let ring = self.keys.lock().unwrap();
+            let keys = ring.keys.get(&ring.current).cloned();
+            (ring.current, keys.ok_or(InternalError::InvalidKeyId)?)
+        };
+
+        let mut iv = [0; IV_LENGTH];
+        self.crypto.fill_random(&mut iv)?;
+        let ciphertext = self.crypto.encrypt(
+            SignalCipherType::AesCbcPkcs5,
+            &keys.encryption,
+            &iv,
+            plaintext,
+        )?;
+
+        let mut sealed =
+            Vec::with_capacity(HEADER_LENGTH + ciphertext.len() + MAC_LENGTH);
+        sealed.push(FORMAT_VERSION);
+        sealed.extend_from_slice(&key_id.to_be_bytes());
+        sealed.extend_from_slice(&iv);
+        sealed.extend_from_slice(&ciphertext);
+
+        let mac = self.mac(&keys, binding, &sealed)?;
+        sealed.extend_from_slice(&mac);
+
+        Ok(sealed)
+    }
+
+    /// Open a sealed record, returning the plaintext and whether it was
+    /// sealed with the current key.
+    fn open(
+        &self,
+        binding: &Binding<'_>,
+        sealed: &[u8],
+    ) -> Result<(Zeroizing<Vec<u8>>, bool), InternalError> {
+        if sealed.len() < HEADER_LENGTH + MAC_LENGTH
+            || sealed[0] != FORMAT_VERSION
+        {
+            return Err(InternalError::InvalidMessage);
+        }
+
+        let key_id = u32::from_be_bytes(sealed[1..5].try_into().unwrap());
+        let (keys, is_current) = {
+            let ring = self.keys.lock().unwrap();
+            let keys = ring.keys.get(&key_id).cloned();
+            (
+                keys.ok_or(InternalError::InvalidKeyId)?,
+                ring.current == key_id,
+            )
+        };
+
+        let (body, mac) = sealed.split_at(sealed.len() - MAC_LENGTH);
+        let expected = self.mac(&keys, binding, body)?;
+        if !constant_time_eq(&expected, mac) {
+            return Err(InternalError::InvalidMAC);
+        }
+
+        let iv = &body[5..HEADER_LENGTH];
+        let ciphertext = &body[HEADER_LENGTH..];
+        let plaintext = self.crypto.decrypt(
+            SignalCipherType::AesCbcPkcs5,
+            &keys.encryption,
+            iv,
+            ciphertext,
+        )?;
+
+        Ok((Zeroizing::new(plaintext), is_current))
+    }
+
+    fn mac(
+        &self,
+        keys: &DerivedKeys,
+        binding: &Binding<'_>,
+        body: &[u8],
+    ) -> Result<Vec<u8>, InternalError> {
+        let mut hmac = self.crypto.hmac_sha256(&keys.mac)?;
+        hmac.update(&binding.to_bytes())?;
+        hmac.update(body)?;
+        hmac.finalize()
+    }
+}
+
+impl<S> Debug for EncryptedStore<S>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedStore")
+            .field("inner", &self.inner)
+            .field("current_key_id", &self.current_key_id())
+            .finish()
+    }
+}
+
+impl<S: PreKeyStore> PreKeyStore for EncryptedStore<S> {
+    fn load(&self, id: u32, writer: &mut dyn Write) -> io::Result<()> {
+        let mut sealed = Vec::new();
+        self.inner.load(id, &mut sealed)?;
+
+        let binding = Binding::PreKey(id);
+        let (plaintext, is_current) = self
+            .open(&binding, &sealed)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if !is_current {
+            if let Err(e) = self.store(id, &plaintext) {
+                log::warn!("Unable to re-seal pre-key {}: {}", id, e);
+            }
+        }
+
+        writer.write_all(&plaintext)
+    }
+
+    fn store(&self, id: u32, body: &[u8]) -> Result<(), Error> {
+        let sealed = self.seal(&Binding::PreKey(id), body)?;
+        self.inner.store(id, &sealed)
+    }
+
+    fn contains(&self, id: u32) -> bool { self.inner.contains(id) }
+
+    fn remove(&self, id: u32) -> Result<(), Error> { self.inner.remove(id) }
+}
+
+impl<S: SignedPreKeyStore> SignedPreKeyStore for EncryptedStore<S> {
+    fn load(&self, id: u32, writer: &mut dyn Write) -> io::Result<()> {
+        let mut sealed = Vec::new();
+        self.inner.load(id, &mut sealed)?;
+
+        let binding = Binding::SignedPreKey(id);
+        let (plaintext, is_current) = self
+            .open(&binding, &sealed)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if !is_current {
+            if let Err(e) = self.store(id, &plaintext) {
+                log::warn!("Unable to re-seal signed pre-key {}: {}", id, e);
+            }
+        }
+
+        writer.write_all(&plaintext)
+    }
+
+    fn store(&self, id: u32, body: &[u8]) -> Result<(), Error> {
+        let sealed = self.seal(&Binding::SignedPreKey(id), body)?;
+        self.inner.store(id, &sealed)
+    }
+
+    fn contains(&self, id: u32) -> bool { self.inner.contains(id) }
+
+    fn remove(&self, id: u32) -> Result<(), Error> { self.inner.remove(id) }
+}
+
+impl<S: SessionStore> SessionStore for EncryptedStore<S> {
+    fn load_session(
+        &self,
+        address: Address,
+    ) -> Result<Option<SerializedSession>, Error> {
+        let sealed = match self.inner.load_session(address.clone())? {
+            Some(sealed) => sealed,
+            None => return Ok(None),
+        };
+
+        let (session, session_is_current) = self
+            .open(&Binding::Session(&address), sealed.session.as_slice())?;
+        let extra_data = match sealed.extra_data {
+            Some(ref extra) => Some(
+                self.open(&Binding::SessionExtra(&address), extra.as_slice())?,
+            ),
+            None => None,
+        };
+        let is_current = match extra_data {
+            Some((_, extra_is_current)) => {
+                session_is_current && extra_is_current
+            },
+            None => session_is_current,
+        };
+
+        let session = SerializedSession {
+            session: Buffer::from(session.as_slice()),
+            extra_data: extra_data
+                .map(|(extra, _)| Buffer::from(extra.as_slice())),
+        };
+
+        if !is_current {
+            if let Err(e) = self.store_session(address.clone(), session.clone())
+            {
+                log::warn!(
+                    "Unable to re-seal the session for {:?}: {}",
+                    address,
+                    e
+                );
+            }
+        }
+
+        Ok(Some(session))
+    }
+
+    fn get_sub_device_sessions(
+        &self,
+        name: &[u8],
+    ) -> Result<Vec<i32>, InternalError> {
+        self.inner.get_sub_device_sessions(name)
+    }
+
+    fn contains_session(&self, addr: Address) -> Result<bool, Error> {
+        self.inner.contains_session(addr)
+    }
+
+    fn store_session(
+        &self,
+        addr: Address,
+        session: SerializedSession,
+    ) -> Result<(), InternalError> {
+        let sealed_session =
+            self.seal(&Binding::Session(&addr), session.session.as_slice())?;
+        let sealed_extra = match session.extra_data {
+            Some(ref extra) => Some(
+                self.seal(&Binding::SessionExtra(&addr), extra.as_slice())?,
+            ),
+            None => None,
+        };
+
+        let sealed = SerializedSession {
+            session: Buffer::from(sealed_session),
+            extra_data: sealed_extra.map(Buffer::from),
+        };
+
+        self.inner.store_session(addr, sealed)
+    }
+
+    fn delete_session(&self, addr: Address) -> Result<(), Error> {
+        self.inner.delete_session(addr)
+    }
+
+    fn delete_all_sessions(&self, name: &[u8]) -> Result<usize, Error> {
+        self.inner.delete_all_sessions(name)
+    }
+}
+
+impl<S: IdentityKeyStore> IdentityKeyStore for EncryptedStore<S> {
+    fn identity_key_pair(&self) -> Result<(Buffer, Buffer), Error> {
+        let (public, sealed_private) = self.inner.identity_key_pair()?;
+        let (private, _) =
+            self.open(&Binding::IdentityKeyPair, sealed_private.as_slice())?;
+
+        Ok((public, Buffer::from(private.as_slice())))
+    }
+
+    fn local_registration_id(&self) -> Result<u32, Error> {
+        self.inner.local_registration_id()
+    }
+
+    fn is_trusted_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<bool, Error> {
+        self.inner.is_trusted_identity(address, identity_key)
+    }
+
+    fn save_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<(), Error> {
+        self.inner.save_identity(address, identity_key)
+    }
+
+    fn identity_record(
+        &self,
+        address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        self.inner.identity_record(address)
+    }
+
+    fn save_identity_record(
+        &self,
+        address: Address,
+        record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        self.inner.save_identity_record(address, record)
+    }
+
+    fn get_identity(&self, address: Address) -> Result<Option<Buffer>, Error> {
+        self.inner.get_identity(address)
+    }
+
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        self.inner.verified_status(address)
+    }
+
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        self.inner.set_verified_status(address, status)
+    }
+}
+
+struct KeyRing {
+    current: u32,
+    keys: HashMap<u32, DerivedKeys>,
+}
+
+/// The actual encryption and MAC keys, derived from a [`StorageKey`].
+#[derive(Clone)]
+struct DerivedKeys {
+    encryption: Zeroizing<Vec<u8>>,
+    mac: Zeroizing<Vec<u8>>,
+}
+
+impl DerivedKeys {
+    fn new(
+        crypto: &dyn Crypto,
+        key: &StorageKey,
+    ) -> Result<DerivedKeys, InternalError> {
+        let derive = |info: &[u8]| {
+            let mut hmac = crypto.hmac_sha256(&key.key)?;
+            hmac.update(info)?;
+            hmac.finalize()
+        };
+
+        Ok(DerivedKeys {
+            encryption: Zeroizing::new(derive(ENCRYPTION_KEY_INFO)?),
+            mac: Zeroizing::new(derive(MAC_KEY_INFO)?),
+        })
+    }
+}
+
+/// Whatever a record is stored under, mixed into its MAC so it can't be moved
+/// to another slot.
+enum Binding<'a> {
+    PreKey(u32),
+    SignedPreKey(u32),
+    Session(&'a Address),
+    SessionExtra(&'a Address),
+    IdentityKeyPair,
+}
+
+impl<'a> Binding<'a> {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        match *self {
+            Binding::PreKey(id) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&id.to_be_bytes());
+            },
+            Binding::SignedPreKey(id) => {
+                bytes.push(2);
+                bytes.extend_from_slice(&id.to_be_bytes());
+            },
+            Binding::Session(addr) => {
+                bytes.push(3);
+                push_address(&mut bytes, addr);
+            },
+            Binding::SessionExtra(addr) => {
+                bytes.push(4);
+                push_address(&mut bytes, addr);
+            },
+            Binding::IdentityKeyPair => bytes.push(5),
+        }
+
+        bytes
+    }
+}
+
+fn push_address(bytes: &mut Vec<u8>, addr: &Address) {
+    let name = addr.bytes();
+    bytes.extend_from_slice(&(name.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(name);
+    bytes.extend_from_slice(&addr.device_id().to_be_bytes());
+}
+
+#[cfg(all(test, feature = "crypto-native"))]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::DefaultCrypto,
+        stores::{InMemoryPreKeyStore, InMemorySessionStore},
+    };
+
+    fn key(id: u32) -> StorageKey {
+        StorageKey::generate(&DefaultCrypto, id).unwrap()
+    }
+
+    #[test]
+    fn round_trip_a_pre_key() {
+        let store = EncryptedStore::new(
+            InMemoryPreKeyStore::default(),
+            DefaultCrypto,
+            key(1),
+        )
+        .unwrap();
+        let body = b"super secret pre-key";
+
+        PreKeyStore::store(&store, 42, body).unwrap();
+
+        let mut raw = Vec::new();
+        store.inner().load(42, &mut raw).unwrap();
+        assert_ne!(raw.as_slice(), &body[..]);
+
+        let mut got = Vec::new();
+        PreKeyStore::load(&store, 42, &mut got).unwrap();
+        assert_eq!(got.as_slice(), &body[..]);
+    }
+
+    #[test]
+    fn records_are_bound_to_their_address() {
+        let store = EncryptedStore::new(
+            InMemorySessionStore::default(),
+            DefaultCrypto,
+            key(1),
+        )
+        .unwrap();
+        let alice = Address::new("alice", 1);
+        let mallory = Address::new("mallory", 1);
+        let session = SerializedSession {
+            session: Buffer::from(&b"session"[..]),
+            extra_data: None,
+        };
+
+        store.store_session(alice.clone(), session).unwrap();
+        let sealed = store.inner().load_session(alice).unwrap().unwrap();
+        store.inner().store_session(mallory.clone(), sealed).unwrap();
+
+        let got = store.load_session(mallory);
+        assert!(matches!(
+            got,
+            Err(Error::InternalError(InternalError::InvalidMAC))
+        ));
+    }
+
+    #[test]
+    fn old_records_are_resealed_after_rotation() {
+        let store = EncryptedStore::new(
+            InMemoryPreKeyStore::default(),
+            DefaultCrypto,
+            key(1),
+        )
+        .unwrap();
+        PreKeyStore::store(&store, 7, b"pre-key").unwrap();
+
+        store.rotate_key(key(2)).unwrap();
+        let mut got = Vec::new();
+        PreKeyStore::load(&store, 7, &mut got).unwrap();
+        assert_eq!(got.as_slice(), b"pre-key");
+
+        // the record was re-sealed on load, so the old key can go
+        assert!(store.retire_key(1));
+        let mut got = Vec::new();
+        PreKeyStore::load(&store, 7, &mut got).unwrap();
+        assert_eq!(got.as_slice(), b"pre-key");
+    }
+}
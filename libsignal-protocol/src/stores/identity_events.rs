@@ -0,0 +1,296 @@
+use crate::{
+    keys::PublicKey,
+    stores::{IdentityKeyStore, IdentityRecord, VerifiedStatus},
+    Address, Buffer, Context, Error,
+};
+use std::{
+    fmt::{self, Debug, Formatter},
+    panic::RefUnwindSafe,
+    rc::Rc,
+    sync::Mutex,
+};
+
+/// Something which happened to a remote client's identity.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdentityEvent {
+    /// An identity key was saved for a recipient we knew nothing about.
+    FirstSeen {
+        /// The recipient.
+        address: Address,
+        /// Their identity key.
+        identity_key: PublicKey,
+    },
+    /// A recipient's identity key was replaced with a different one.
+    Changed {
+        /// The recipient.
+        address: Address,
+        /// The identity key which used to be saved.
+        old: PublicKey,
+        /// The new identity key.
+        new: PublicKey,
+    },
+    /// A recipient's identity key was replaced, but the key which used to be
+    /// saved couldn't be decoded (e.g. because the store is corrupt).
+    ///
+    /// This should be treated the same as [`IdentityEvent::Changed`].
+    InvalidKeyReplaced {
+        /// The recipient.
+        address: Address,
+        /// The raw bytes which used to be saved.
+        old: Buffer,
+        /// The new identity key.
+        new: PublicKey,
+    },
+    /// A recipient's identity key was removed from the store.
+    Removed {
+        /// The recipient.
+        address: Address,
+    },
+    /// The user changed whether a recipient's identity key is verified.
+    Verified {
+        /// The recipient.
+        address: Address,
+        /// The new [`VerifiedStatus`].
+        status: VerifiedStatus,
+    },
+}
+
+type Observer = Rc<dyn Fn(&IdentityEvent) + RefUnwindSafe>;
+
+/// A shared list of callbacks which are notified of [`IdentityEvent`]s.
+///
+/// Cloning an [`IdentityObservers`] gives you another handle to the same
+/// list, so you can keep subscribing after the store has been handed to a
+/// [`StoreContext`](crate::StoreContext).
+#[derive(Clone, Default)]
+pub struct IdentityObservers(Rc<Mutex<Vec<Observer>>>);
+
+impl IdentityObservers {
+    /// Register a callback to be invoked for every [`IdentityEvent`].
+    pub fn subscribe<F>(&self, observer: F)
+    where
+        F: Fn(&IdentityEvent) + RefUnwindSafe + 'static,
+    {
+        self.0.lock().unwrap().push(Rc::new(observer));
+    }
+
+    fn emit(&self, event: &IdentityEvent) {
+        // take a snapshot so observers are free to subscribe while we're
+        // notifying them
+        let observers = self.0.lock().unwrap().clone();
+
+        for observer in observers {
+            observer(event);
+        }
+    }
+}
+
+impl Debug for IdentityObservers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdentityObservers")
+            .field("len", &self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// An [`IdentityKeyStore`] wrapper which notifies [`IdentityObservers`]
+/// whenever a remote client's identity is saved, changed, removed, or
+/// verified.
+///
+/// Changes are detected using [`IdentityKeyStore::identity_record()`] (or
+/// [`IdentityKeyStore::get_identity()`] and
+/// [`IdentityKeyStore::verified_status()`]), so the wrapped store needs to
+/// implement them.
+#[derive(Debug)]
+pub struct ObservedIdentityKeyStore<S> {
+    inner: S,
+    ctx: Context,
+    observers: IdentityObservers,
+}
+
+impl<S: IdentityKeyStore> ObservedIdentityKeyStore<S> {
+    /// Wrap an existing [`IdentityKeyStore`], using the [`Context`] to decode
+    /// identity keys.
+    pub fn new(ctx: &Context, inner: S) -> ObservedIdentityKeyStore<S> {
+        ObservedIdentityKeyStore {
+            inner,
+            ctx: ctx.clone(),
+            observers: IdentityObservers::default(),
+        }
+    }
+
+    /// Register a callback to be invoked for every [`IdentityEvent`].
+    pub fn subscribe<F>(&self, observer: F)
+    where
+        F: Fn(&IdentityEvent) + RefUnwindSafe + 'static,
+    {
+        self.observers.subscribe(observer);
+    }
+
+    /// Get a handle to this store's observers.
+    pub fn observers(&self) -> IdentityObservers { self.observers.clone() }
+
+    /// Get a reference to the wrapped store.
+    pub const fn inner(&self) -> &S { &self.inner }
+
+    /// Unwrap the underlying store.
+    pub fn into_inner(self) -> S { self.inner }
+
+    /// Work out which event (if any) saving `identity_key` will trigger.
+    ///
+    /// The new key is decoded up front so a bad key is reported before the
+    /// inner store is touched. A bad key which is already in the store
+    /// mustn't stop it from being replaced, though.
+    fn key_event(
+        &self,
+        address: &Address,
+        previous: Option<Buffer>,
+        identity_key: &[u8],
+    ) -> Result<Option<IdentityEvent>, Error> {
+        let event = match previous {
+            Some(_) if identity_key.is_empty() => {
+                Some(IdentityEvent::Removed {
+                    address: address.clone(),
+                })
+            },
+            Some(old) if old.as_slice() != identity_key => {
+                let new = PublicKey::decode_point(&self.ctx, identity_key)?;
+
+                match PublicKey::decode_point(&self.ctx, old.as_slice()) {
+                    Ok(old) => Some(IdentityEvent::Changed {
+                        address: address.clone(),
+                        old,
+                        new,
+                    }),
+                    Err(e) => {
+                        log::warn!(
+                            "Unable to decode the identity key saved for \
+                             {:?}: {}",
+                            address,
+                            e
+                        );
+                        Some(IdentityEvent::InvalidKeyReplaced {
+                            address: address.clone(),
+                            old,
+                            new,
+                        })
+                    },
+                }
+            },
+            None if !identity_key.is_empty() => {
+                Some(IdentityEvent::FirstSeen {
+                    address: address.clone(),
+                    identity_key: PublicKey::decode_point(
+                        &self.ctx,
+                        identity_key,
+                    )?,
+                })
+            },
+            _ => None,
+        };
+
+        Ok(event)
+    }
+
+    fn emit_status_change(
+        &self,
+        address: Address,
+        previous: VerifiedStatus,
+    ) -> Result<(), Error> {
+        let current = self.inner.verified_status(address.clone())?;
+
+        if previous != current {
+            self.observers.emit(&IdentityEvent::Verified {
+                address,
+                status: current,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: IdentityKeyStore> IdentityKeyStore for ObservedIdentityKeyStore<S> {
+    fn identity_key_pair(&self) -> Result<(Buffer, Buffer), Error> {
+        self.inner.identity_key_pair()
+    }
+
+    fn local_registration_id(&self) -> Result<u32, Error> {
+        self.inner.local_registration_id()
+    }
+
+    fn is_trusted_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<bool, Error> {
+        self.inner.is_trusted_identity(address, identity_key)
+    }
+
+    fn save_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<(), Error> {
+        let previous_key = self.inner.get_identity(address.clone())?;
+        let previous_status = self.inner.verified_status(address.clone())?;
+        let event = self.key_event(&address, previous_key, identity_key)?;
+
+        self.inner.save_identity(address.clone(), identity_key)?;
+
+        if let Some(event) = event {
+            self.observers.emit(&event);
+        }
+
+        // some stores revoke verification when the key changes
+        self.emit_status_change(address, previous_status)
+    }
+
+    fn identity_record(
+        &self,
+        address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        self.inner.identity_record(address)
+    }
+
+    fn save_identity_record(
+        &self,
+        address: Address,
+        record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        let previous_key = self.inner.get_identity(address.clone())?;
+        let previous_status = self.inner.verified_status(address.clone())?;
+        let event =
+            self.key_event(&address, previous_key, &record.identity_key)?;
+
+        self.inner.save_identity_record(address.clone(), record)?;
+
+        if let Some(event) = event {
+            self.observers.emit(&event);
+        }
+
+        self.emit_status_change(address, previous_status)
+    }
+
+    fn get_identity(&self, address: Address) -> Result<Option<Buffer>, Error> {
+        self.inner.get_identity(address)
+    }
+
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        self.inner.verified_status(address)
+    }
+
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        let previous = self.inner.verified_status(address.clone())?;
+        self.inner.set_verified_status(address.clone(), status)?;
+
+        self.emit_status_change(address, previous)
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{Address, Buffer, Error};
+use crate::{
+    stores::{IdentityRecord, VerifiedStatus},
+    Address, Buffer, Error,
+};
 use std::{
     os::raw::{c_int, c_void},
     panic::RefUnwindSafe,
@@ -24,6 +27,9 @@ pub trait IdentityKeyStore: RefUnwindSafe {
     /// recipient in the local store, or if it matches the saved key for a
     /// recipient in the local store.  Only if it mismatches an entry in the
     /// local store is it considered *untrusted*.
+    ///
+    /// Stores which need something stricter (or looser) can delegate this
+    /// decision to a [`TrustPolicy`](crate::stores::TrustPolicy).
     fn is_trusted_identity(
         &self,
         address: Address,
@@ -40,6 +46,67 @@ pub trait IdentityKeyStore: RefUnwindSafe {
         address: Address,
         identity_key: &[u8],
     ) -> Result<(), Error>;
+
+    /// Get everything known about a remote client's identity.
+    ///
+    /// Stores which only keep raw identity keys can leave this as the
+    /// default, which always returns `None`.
+    fn identity_record(
+        &self,
+        _address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        Ok(None)
+    }
+
+    /// Save a remote client's [`IdentityRecord`], replacing any existing one.
+    ///
+    /// By default only the record's identity key is saved (using
+    /// [`IdentityKeyStore::save_identity()`]).
+    fn save_identity_record(
+        &self,
+        address: Address,
+        record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        self.save_identity(address, &record.identity_key)
+    }
+
+    /// Get the identity key currently saved for a remote client, if any.
+    fn get_identity(&self, address: Address) -> Result<Option<Buffer>, Error> {
+        let record = self.identity_record(address)?;
+
+        Ok(record
+            .as_ref()
+            .and_then(IdentityRecord::saved_key)
+            .map(Buffer::from))
+    }
+
+    /// Get the [`VerifiedStatus`] of a remote client's identity key.
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        let record = self.identity_record(address)?;
+
+        Ok(record.map_or(VerifiedStatus::Default, |r| r.verified))
+    }
+
+    /// Record whether the user has verified a remote client's identity key.
+    ///
+    /// By default this updates the recipient's [`IdentityRecord`], doing
+    /// nothing if there isn't one.
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        match self.identity_record(address.clone())? {
+            Some(mut record) => {
+                record.verified = status;
+                self.save_identity_record(address, &record)
+            },
+            None => Ok(()),
+        }
+    }
 }
 
 pub(crate) fn new_vtable<I: IdentityKeyStore + 'static>(
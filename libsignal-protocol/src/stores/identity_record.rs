@@ -0,0 +1,120 @@
+use crate::stores::VerifiedStatus;
+use std::time::SystemTime;
+
+/// Everything an [`IdentityKeyStore`](crate::stores::IdentityKeyStore) knows
+/// about a remote client's identity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentityRecord {
+    /// The serialized public identity key.
+    ///
+    /// This will be empty if the key has been removed but the rest of the
+    /// record was kept.
+    pub identity_key: Vec<u8>,
+    /// When an identity key was first saved for the recipient.
+    pub first_seen: SystemTime,
+    /// When the identity key last changed.
+    pub last_changed: SystemTime,
+    /// Whether the user has verified the identity key.
+    pub verified: VerifiedStatus,
+    /// Has the user approved the current identity key without verifying it?
+    pub nonblocking_approval: bool,
+}
+
+impl IdentityRecord {
+    /// Create a record for an identity key seen for the first time.
+    pub fn new(identity_key: &[u8]) -> IdentityRecord {
+        let now = SystemTime::now();
+
+        IdentityRecord {
+            identity_key: identity_key.to_vec(),
+            first_seen: now,
+            last_changed: now,
+            verified: VerifiedStatus::Default,
+            nonblocking_approval: false,
+        }
+    }
+
+    /// Get the saved identity key, if it hasn't been removed.
+    pub fn saved_key(&self) -> Option<&[u8]> {
+        if self.identity_key.is_empty() {
+            None
+        } else {
+            Some(&self.identity_key)
+        }
+    }
+
+    /// Replace the identity key, updating the record's metadata.
+    ///
+    /// Verification and approval only apply to the key they were given for,
+    /// so switching to a different key marks a verified record as
+    /// [`VerifiedStatus::Unverified`] and clears any nonblocking approval.
+    ///
+    /// Once a key has been removed there is no way to tell whether a key
+    /// saved later is the same one, so it is always treated as a different
+    /// key.
+    ///
+    /// Returns `true` if the saved key was changed to a different one.
+    pub fn update_identity_key(&mut self, identity_key: &[u8]) -> bool {
+        if identity_key.is_empty() {
+            self.identity_key.clear();
+            return false;
+        }
+
+        let changed = self.saved_key() != Some(identity_key);
+
+        if changed {
+            self.last_changed = SystemTime::now();
+            self.nonblocking_approval = false;
+            if self.verified == VerifiedStatus::Verified {
+                self.verified = VerifiedStatus::Unverified;
+            }
+        }
+
+        self.identity_key = identity_key.to_vec();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changing_the_key_revokes_verification_and_approval() {
+        let mut record = IdentityRecord::new(&[1, 2, 3]);
+        record.verified = VerifiedStatus::Verified;
+        record.nonblocking_approval = true;
+
+        assert!(!record.update_identity_key(&[1, 2, 3]));
+        assert_eq!(record.verified, VerifiedStatus::Verified);
+
+        assert!(record.update_identity_key(&[4, 5, 6]));
+        assert_eq!(record.identity_key, &[4, 5, 6]);
+        assert_eq!(record.verified, VerifiedStatus::Unverified);
+        assert!(!record.nonblocking_approval);
+        assert!(record.last_changed >= record.first_seen);
+    }
+
+    #[test]
+    fn removing_the_key_keeps_the_metadata() {
+        let mut record = IdentityRecord::new(&[1, 2, 3]);
+        record.verified = VerifiedStatus::Verified;
+
+        assert!(!record.update_identity_key(&[]));
+        assert_eq!(record.saved_key(), None);
+        assert_eq!(record.verified, VerifiedStatus::Verified);
+    }
+
+    #[test]
+    fn saving_a_key_after_removal_revokes_verification_and_approval() {
+        let mut record = IdentityRecord::new(&[1, 2, 3]);
+        record.verified = VerifiedStatus::Verified;
+        record.nonblocking_approval = true;
+        record.update_identity_key(&[]);
+
+        assert!(record.update_identity_key(&[4, 5, 6]));
+        assert_eq!(record.saved_key(), Some(&[4, 5, 6][..]));
+        assert_eq!(record.verified, VerifiedStatus::Unverified);
+        assert!(!record.nonblocking_approval);
+    }
+}
@@ -1,16 +1,32 @@
 use crate::{
-    keys::IdentityKeyPair, stores::IdentityKeyStore, Address, Buffer, Error,
-    Serializable,
+    keys::IdentityKeyPair,
+    stores::{
+        IdentityChange, IdentityChangeKind, IdentityKeyStore, IdentityRecord,
+        TrustOnFirstUse, TrustPolicy, VerifiedStatus,
+    },
+    Address, Buffer, Error, Serializable,
 };
 use std::{collections::HashMap, sync::Mutex};
 
 /// An in-memory [`IdentityKeyStore`].
+///
+/// Whether a remote identity is trusted is decided by a [`TrustPolicy`]
+/// (by default [`TrustOnFirstUse`]). Key changes and changes to a
+/// recipient's [`VerifiedStatus`] are recorded and can be retrieved with
+/// [`InMemoryIdentityKeyStore::take_changes()`].
 #[derive(Debug)]
 pub struct InMemoryIdentityKeyStore {
     registration_id: u32,
     identity: IdentityKeyPair,
-    trusted_identities: Mutex<HashMap<Address, Vec<u8>>>,
+    trusted_identities: Mutex<HashMap<Address, IdentityRecord>>,
+    changes: Mutex<Vec<IdentityChange>>,
+    trust_policy: Option<Box<dyn TrustPolicy>>,
     /// Should recipients be trusted the first time they are contacted?
+    ///
+    /// Setting this to `false` means only keys which have already been saved
+    /// for the recipient are trusted. It is ignored when a policy has been
+    /// set with [`InMemoryIdentityKeyStore::with_trust_policy()`].
+    #[deprecated(note = "Use InMemoryIdentityKeyStore::with_trust_policy()")]
     pub trust_on_first_use: bool,
 }
 
@@ -20,15 +36,78 @@ impl InMemoryIdentityKeyStore {
         registration_id: u32,
         identity: &IdentityKeyPair,
     ) -> InMemoryIdentityKeyStore {
+        #[allow(deprecated)]
         InMemoryIdentityKeyStore {
             registration_id,
-            trust_on_first_use: true,
             identity: identity.clone(),
             trusted_identities: Default::default(),
+            changes: Default::default(),
+            trust_policy: None,
+            trust_on_first_use: true,
+        }
+    }
+
+    /// Use a different [`TrustPolicy`] when deciding whether to trust a
+    /// remote client's identity key.
+    pub fn with_trust_policy<P>(mut self, policy: P) -> InMemoryIdentityKeyStore
+    where
+        P: TrustPolicy + 'static,
+    {
+        self.trust_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Remove and return all identity changes recorded since the last call.
+    pub fn take_changes(&self) -> Vec<IdentityChange> {
+        std::mem::take(&mut *self.changes.lock().unwrap())
+    }
+
+    fn trust_policy(&self) -> &dyn TrustPolicy {
+        match self.trust_policy {
+            Some(ref policy) => policy.as_ref(),
+            #[allow(deprecated)]
+            None if self.trust_on_first_use => &TrustOnFirstUse,
+            None => &TrustSavedKeys,
+        }
+    }
+
+    fn record_key_change(&self, address: Address) {
+        self.changes.lock().unwrap().push(IdentityChange {
+            address,
+            kind: IdentityChangeKind::KeyChanged,
+        });
+    }
+
+    fn record_status_change(
+        &self,
+        address: Address,
+        previous: VerifiedStatus,
+        current: VerifiedStatus,
+    ) {
+        if previous != current {
+            self.changes.lock().unwrap().push(IdentityChange {
+                address,
+                kind: IdentityChangeKind::StatusChanged { previous, current },
+            });
         }
     }
 }
 
+/// What `trust_on_first_use = false` means, only trust a key if it
+/// matches the one saved for the recipient.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct TrustSavedKeys;
+
+impl TrustPolicy for TrustSavedKeys {
+    fn is_trusted(
+        &self,
+        record: Option<&IdentityRecord>,
+        identity_key: &[u8],
+    ) -> bool {
+        record.and_then(IdentityRecord::saved_key) == Some(identity_key)
+    }
+}
+
 impl IdentityKeyStore for InMemoryIdentityKeyStore {
     fn local_registration_id(&self) -> Result<u32, Error> {
         Ok(self.registration_id)
@@ -48,11 +127,9 @@ impl IdentityKeyStore for InMemoryIdentityKeyStore {
     ) -> Result<bool, Error> {
         let identities = self.trusted_identities.lock().unwrap();
 
-        if let Some(identity) = identities.get(&address) {
-            Ok(identity_key == identity.as_slice())
-        } else {
-            Ok(self.trust_on_first_use)
-        }
+        Ok(self
+            .trust_policy()
+            .is_trusted(identities.get(&address), identity_key))
     }
 
     fn save_identity(
@@ -60,10 +137,59 @@ impl IdentityKeyStore for InMemoryIdentityKeyStore {
         addr: Address,
         identity_key: &[u8],
     ) -> Result<(), Error> {
-        self.trusted_identities
-            .lock()
-            .unwrap()
-            .insert(addr, identity_key.to_vec());
+        let mut identities = self.trusted_identities.lock().unwrap();
+
+        match identities.get_mut(&addr) {
+            Some(record) => {
+                let previous = record.verified;
+                if record.update_identity_key(identity_key) {
+                    self.record_key_change(addr.clone());
+                }
+                self.record_status_change(addr, previous, record.verified);
+            },
+            // there's nothing to remove
+            None if identity_key.is_empty() => {},
+            None => {
+                identities.insert(addr, IdentityRecord::new(identity_key));
+            },
+        }
+
+        Ok(())
+    }
+
+    fn identity_record(
+        &self,
+        address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        let identities = self.trusted_identities.lock().unwrap();
+
+        Ok(identities.get(&address).cloned())
+    }
+
+    fn save_identity_record(
+        &self,
+        address: Address,
+        record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        let mut identities = self.trusted_identities.lock().unwrap();
+
+        if let Some(previous) = identities.get(&address) {
+            // a key saved after the old one was removed counts as a change
+            // because we can't tell whether it's the same key
+            match (previous.saved_key(), record.saved_key()) {
+                (old, Some(new)) if old != Some(new) => {
+                    self.record_key_change(address.clone())
+                },
+                _ => {},
+            }
+            self.record_status_change(
+                address.clone(),
+                previous.verified,
+                record.verified,
+            );
+        }
+
+        identities.insert(address, record.clone());
 
         Ok(())
     }
@@ -1,6 +1,6 @@
 use crate::{
     stores::{PreKeyStore, SignedPreKeyStore},
-    Error,
+    Error, SecretBuffer,
 };
 use std::{
     collections::HashMap,
@@ -54,19 +54,19 @@ impl SignedPreKeyStore for InMemorySignedPreKeyStore {
 
 #[derive(Debug, Default)]
 struct Inner {
-    keys: Mutex<HashMap<u32, Vec<u8>>>,
+    keys: Mutex<HashMap<u32, SecretBuffer>>,
 }
 
 impl Inner {
     fn load(&self, id: u32, writer: &mut dyn Write) -> io::Result<()> {
         match self.keys.lock().unwrap().get(&id) {
-            Some(bytes) => writer.write_all(bytes),
+            Some(bytes) => writer.write_all(bytes.as_slice()),
             None => unimplemented!(),
         }
     }
 
     fn store(&self, id: u32, body: &[u8]) -> Result<(), Error> {
-        self.keys.lock().unwrap().insert(id, body.to_vec());
+        self.keys.lock().unwrap().insert(id, SecretBuffer::from(body));
         Ok(())
     }
 
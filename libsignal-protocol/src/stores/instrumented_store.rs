@@ -0,0 +1,350 @@
+use crate::{
+    metrics::{CountingWriter, MetricsSink, Operation, Timer},
+    stores::{
+        IdentityKeyStore, IdentityRecord, PreKeyStore, SerializedSession,
+        SessionStore, SignedPreKeyStore, VerifiedStatus,
+    },
+    Address, Buffer, Error, InternalError,
+};
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+/// A wrapper around any of the store traits which reports every call to a
+/// [`MetricsSink`].
+///
+/// See the [`metrics`](crate::metrics) module for more.
+#[derive(Debug, Clone)]
+pub struct InstrumentedStore<S> {
+    inner: S,
+    sink: Arc<dyn MetricsSink>,
+}
+
+impl<S> InstrumentedStore<S> {
+    /// Wrap an existing store.
+    pub fn new(inner: S, sink: Arc<dyn MetricsSink>) -> InstrumentedStore<S> {
+        InstrumentedStore { inner, sink }
+    }
+
+    /// Get a reference to the underlying store.
+    pub const fn inner(&self) -> &S { &self.inner }
+
+    /// Unwrap the underlying store.
+    pub fn into_inner(self) -> S { self.inner }
+
+    fn measure<T, E>(
+        &self,
+        operation: Operation,
+        f: impl FnOnce() -> Result<T, E>,
+        bytes: impl FnOnce(&T) -> usize,
+    ) -> Result<T, E> {
+        let timer = Timer::start(operation);
+        let result = f();
+        let processed = match result {
+            Ok(ref value) => bytes(value),
+            Err(_) => 0,
+        };
+        timer.finish(&*self.sink, processed, result.is_ok());
+
+        result
+    }
+
+    fn load_record(
+        &self,
+        operation: Operation,
+        writer: &mut dyn Write,
+        load: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let timer = Timer::start(operation);
+        let mut writer = CountingWriter::new(writer);
+        let result = load(&mut writer);
+        timer.finish(&*self.sink, writer.written, result.is_ok());
+
+        result
+    }
+
+    fn count(&self, operation: Operation, f: impl FnOnce() -> bool) -> bool {
+        let timer = Timer::start(operation);
+        let result = f();
+        timer.finish(&*self.sink, 0, true);
+
+        result
+    }
+}
+
+fn session_len(session: &SerializedSession) -> usize {
+    let extra = match session.extra_data {
+        Some(ref extra) => extra.len(),
+        None => 0,
+    };
+
+    session.session.len() + extra
+}
+
+impl<S: PreKeyStore> PreKeyStore for InstrumentedStore<S> {
+    fn load(&self, id: u32, writer: &mut dyn Write) -> io::Result<()> {
+        self.load_record(Operation::LoadPreKey, writer, |w| {
+            self.inner.load(id, w)
+        })
+    }
+
+    fn store(&self, id: u32, body: &[u8]) -> Result<(), Error> {
+        self.measure(
+            Operation::StorePreKey,
+            || self.inner.store(id, body),
+            |_| body.len(),
+        )
+    }
+
+    fn contains(&self, id: u32) -> bool {
+        self.count(Operation::ContainsPreKey, || self.inner.contains(id))
+    }
+
+    fn remove(&self, id: u32) -> Result<(), Error> {
+        self.measure(Operation::RemovePreKey, || self.inner.remove(id), |_| 0)
+    }
+}
+
+impl<S: SignedPreKeyStore> SignedPreKeyStore for InstrumentedStore<S> {
+    fn load(&self, id: u32, writer: &mut dyn Write) -> io::Result<()> {
+        self.load_record(Operation::LoadSignedPreKey, writer, |w| {
+            self.inner.load(id, w)
+        })
+    }
+
+    fn store(&self, id: u32, body: &[u8]) -> Result<(), Error> {
+        self.measure(
+            Operation::StoreSignedPreKey,
+            || self.inner.store(id, body),
+            |_| body.len(),
+        )
+    }
+
+    fn contains(&self, id: u32) -> bool {
+        self.count(Operation::ContainsSignedPreKey, || self.inner.contains(id))
+    }
+
+    fn remove(&self, id: u32) -> Result<(), Error> {
+        self.measure(
+            Operation::RemoveSignedPreKey,
+            || self.inner.remove(id),
+            |_| 0,
+        )
+    }
+}
+
+impl<S: SessionStore> SessionStore for InstrumentedStore<S> {
+    fn load_session(
+        &self,
+        address: Address,
+    ) -> Result<Option<SerializedSession>, Error> {
+        self.measure(
+            Operation::LoadSession,
+            || self.inner.load_session(address),
+            |session| session.as_ref().map_or(0, session_len),
+        )
+    }
+
+    fn get_sub_device_sessions(
+        &self,
+        name: &[u8],
+    ) -> Result<Vec<i32>, InternalError> {
+        self.measure(
+            Operation::GetSubDeviceSessions,
+            || self.inner.get_sub_device_sessions(name),
+            |_| 0,
+        )
+    }
+
+    fn contains_session(&self, addr: Address) -> Result<bool, Error> {
+        self.measure(
+            Operation::ContainsSession,
+            || self.inner.contains_session(addr),
+            |_| 0,
+        )
+    }
+
+    fn store_session(
+        &self,
+        addr: Address,
+        session: SerializedSession,
+    ) -> Result<(), InternalError> {
+        let len = session_len(&session);
+
+        self.measure(
+            Operation::StoreSession,
+            || self.inner.store_session(addr, session),
+            |_| len,
+        )
+    }
+
+    fn delete_session(&self, addr: Address) -> Result<(), Error> {
+        self.measure(
+            Operation::DeleteSession,
+            || self.inner.delete_session(addr),
+            |_| 0,
+        )
+    }
+
+    fn delete_all_sessions(&self, name: &[u8]) -> Result<usize, Error> {
+        self.measure(
+            Operation::DeleteAllSessions,
+            || self.inner.delete_all_sessions(name),
+            |_| 0,
+        )
+    }
+}
+
+impl<S: IdentityKeyStore> IdentityKeyStore for InstrumentedStore<S> {
+    fn identity_key_pair(&self) -> Result<(Buffer, Buffer), Error> {
+        self.measure(
+            Operation::GetIdentityKeyPair,
+            || self.inner.identity_key_pair(),
+            |(public, private)| public.len() + private.len(),
+        )
+    }
+
+    fn local_registration_id(&self) -> Result<u32, Error> {
+        self.measure(
+            Operation::GetLocalRegistrationId,
+            || self.inner.local_registration_id(),
+            |_| 0,
+        )
+    }
+
+    fn is_trusted_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<bool, Error> {
+        self.measure(
+            Operation::IsTrustedIdentity,
+            || self.inner.is_trusted_identity(address, identity_key),
+            |_| identity_key.len(),
+        )
+    }
+
+    fn save_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<(), Error> {
+        self.measure(
+            Operation::SaveIdentity,
+            || self.inner.save_identity(address, identity_key),
+            |_| identity_key.len(),
+        )
+    }
+
+    fn identity_record(
+        &self,
+        address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        self.measure(
+            Operation::GetIdentityRecord,
+            || self.inner.identity_record(address),
+            |record| match *record {
+                Some(ref record) => record.identity_key.len(),
+                None => 0,
+            },
+        )
+    }
+
+    fn save_identity_record(
+        &self,
+        address: Address,
+        record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        self.measure(
+            Operation::SaveIdentityRecord,
+            || self.inner.save_identity_record(address, record),
+            |_| record.identity_key.len(),
+        )
+    }
+
+    fn get_identity(&self, address: Address) -> Result<Option<Buffer>, Error> {
+        self.measure(
+            Operation::GetIdentity,
+            || self.inner.get_identity(address),
+            |key| key.as_ref().map_or(0, Buffer::len),
+        )
+    }
+
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        self.measure(
+            Operation::GetVerifiedStatus,
+            || self.inner.verified_status(address),
+            |_| 0,
+        )
+    }
+
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        self.measure(
+            Operation::SetVerifiedStatus,
+            || self.inner.set_verified_status(address, status),
+            |_| 0,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        metrics::InMemoryMetrics,
+        stores::{InMemoryPreKeyStore, InMemorySessionStore},
+    };
+
+    #[test]
+    fn pre_key_calls_are_recorded() {
+        let metrics = Arc::new(InMemoryMetrics::default());
+        let store = InstrumentedStore::new(
+            InMemoryPreKeyStore::default(),
+            metrics.clone(),
+        );
+
+        PreKeyStore::store(&store, 42, b"pre-key").unwrap();
+        let mut got = Vec::new();
+        PreKeyStore::load(&store, 42, &mut got).unwrap();
+        assert!(PreKeyStore::contains(&store, 42));
+
+        assert_eq!(metrics.stats(Operation::StorePreKey).bytes, 7);
+        assert_eq!(metrics.stats(Operation::LoadPreKey).bytes, 7);
+        assert_eq!(metrics.stats(Operation::ContainsPreKey).calls, 1);
+        assert_eq!(metrics.stats(Operation::RemovePreKey).calls, 0);
+    }
+
+    #[test]
+    fn session_calls_are_recorded() {
+        let metrics = Arc::new(InMemoryMetrics::default());
+        let store = InstrumentedStore::new(
+            InMemorySessionStore::default(),
+            metrics.clone(),
+        );
+        let alice = Address::new("alice", 1);
+        let session = SerializedSession {
+            session: Buffer::from(&b"session"[..]),
+            extra_data: Some(Buffer::from(&b"extra"[..])),
+        };
+
+        store.store_session(alice.clone(), session).unwrap();
+        store.load_session(alice.clone()).unwrap();
+        store.load_session(Address::new("bob", 1)).unwrap();
+
+        let stored = metrics.stats(Operation::StoreSession);
+        assert_eq!(stored.calls, 1);
+        assert_eq!(stored.bytes, 12);
+        let loaded = metrics.stats(Operation::LoadSession);
+        assert_eq!(loaded.calls, 2);
+        assert_eq!(loaded.bytes, 12);
+        assert_eq!(loaded.failures, 0);
+    }
+}
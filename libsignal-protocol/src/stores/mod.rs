@@ -1,21 +1,40 @@
 //! Places to store Signal Protocol state.
 
+mod encrypted_store;
+mod identity_events;
 pub(crate) mod identity_key_store;
+mod identity_record;
 mod in_memory_identity_key_store;
 mod in_memory_pre_key_stores;
 mod in_memory_session_store;
+mod instrumented_store;
 pub(crate) mod pre_key_store;
 pub(crate) mod session_store;
 pub(crate) mod signed_pre_key_store;
+mod trust;
+mod typed_identity_key_store;
 
 pub use self::{
+    encrypted_store::{EncryptedStore, StorageKey},
+    identity_events::{
+        IdentityEvent, IdentityObservers, ObservedIdentityKeyStore,
+    },
     identity_key_store::IdentityKeyStore,
+    identity_record::IdentityRecord,
     in_memory_identity_key_store::InMemoryIdentityKeyStore,
     in_memory_pre_key_stores::{
         InMemoryPreKeyStore, InMemorySignedPreKeyStore,
     },
     in_memory_session_store::InMemorySessionStore,
+    instrumented_store::InstrumentedStore,
     pre_key_store::PreKeyStore,
     session_store::{SerializedSession, SessionStore},
     signed_pre_key_store::SignedPreKeyStore,
+    trust::{
+        AllowKeyChanges, IdentityChange, IdentityChangeKind,
+        RequireVerification, TrustOnFirstUse, TrustPolicy, VerifiedStatus,
+    },
+    typed_identity_key_store::{
+        IdentityKeyStoreAdapter, TypedIdentityKeyStore,
+    },
 };
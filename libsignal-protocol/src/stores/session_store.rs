@@ -6,6 +6,7 @@ use std::{
 
 /// A serialized session.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SerializedSession {
     /// The session itself.
     pub session: Buffer,
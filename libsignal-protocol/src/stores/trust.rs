@@ -0,0 +1,153 @@
+use crate::{stores::IdentityRecord, Address};
+use std::{fmt::Debug, panic::RefUnwindSafe};
+
+/// Whether the user has verified a remote client's identity key (e.g. by
+/// comparing safety numbers).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum VerifiedStatus {
+    /// The identity key has never been verified.
+    Default,
+    /// The user has verified the identity key.
+    Verified,
+    /// The identity key was verified in the past, but has since been
+    /// un-verified or replaced with a different key.
+    Unverified,
+}
+
+/// Decides whether a remote client's identity key should be trusted.
+pub trait TrustPolicy: Debug + RefUnwindSafe {
+    /// Is `identity_key` trusted, given what is currently known about the
+    /// recipient (if anything)?
+    fn is_trusted(
+        &self,
+        record: Option<&IdentityRecord>,
+        identity_key: &[u8],
+    ) -> bool;
+}
+
+/// Trust a recipient's identity key the first time it is seen, and block
+/// any key which doesn't match it afterwards.
+///
+/// This is the convention used by the TextSecure protocol.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TrustOnFirstUse;
+
+impl TrustPolicy for TrustOnFirstUse {
+    fn is_trusted(
+        &self,
+        record: Option<&IdentityRecord>,
+        identity_key: &[u8],
+    ) -> bool {
+        match record.and_then(IdentityRecord::saved_key) {
+            Some(saved) => saved == identity_key,
+            None => true,
+        }
+    }
+}
+
+/// Only trust identity keys which the user has explicitly verified.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct RequireVerification;
+
+impl TrustPolicy for RequireVerification {
+    fn is_trusted(
+        &self,
+        record: Option<&IdentityRecord>,
+        identity_key: &[u8],
+    ) -> bool {
+        match record {
+            Some(record) => {
+                record.verified == VerifiedStatus::Verified
+                    && record.saved_key() == Some(identity_key)
+            },
+            None => false,
+        }
+    }
+}
+
+/// Always trust identity keys, even when they change.
+///
+/// Changes are still recorded by the store so the user can be told their
+/// safety number has changed.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct AllowKeyChanges;
+
+impl TrustPolicy for AllowKeyChanges {
+    fn is_trusted(
+        &self,
+        _record: Option<&IdentityRecord>,
+        _identity_key: &[u8],
+    ) -> bool {
+        true
+    }
+}
+
+/// A noteworthy change to a remote client's identity, recorded so it can be
+/// shown to the user (e.g. "your safety number with Bob has changed").
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdentityChange {
+    /// The recipient whose identity changed.
+    pub address: Address,
+    /// What happened.
+    pub kind: IdentityChangeKind,
+}
+
+/// The different types of [`IdentityChange`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IdentityChangeKind {
+    /// The recipient's identity key was replaced with a different one.
+    KeyChanged,
+    /// The recipient's [`VerifiedStatus`] changed.
+    StatusChanged {
+        /// The status before the change.
+        previous: VerifiedStatus,
+        /// The new status.
+        current: VerifiedStatus,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = &[1, 2, 3];
+    const OTHER_KEY: &[u8] = &[4, 5, 6];
+
+    fn record(status: VerifiedStatus) -> IdentityRecord {
+        IdentityRecord {
+            verified: status,
+            ..IdentityRecord::new(KEY)
+        }
+    }
+
+    #[test]
+    fn trust_on_first_use_blocks_changed_keys() {
+        let policy = TrustOnFirstUse;
+        let record = record(VerifiedStatus::Verified);
+
+        assert!(policy.is_trusted(None, KEY));
+        assert!(policy.is_trusted(Some(&record), KEY));
+        assert!(!policy.is_trusted(Some(&record), OTHER_KEY));
+        assert!(policy.is_trusted(Some(&IdentityRecord::new(&[])), OTHER_KEY));
+    }
+
+    #[test]
+    fn only_verified_keys_are_trusted_when_verification_is_required() {
+        let policy = RequireVerification;
+        let unverified = record(VerifiedStatus::Default);
+        let verified = record(VerifiedStatus::Verified);
+
+        assert!(!policy.is_trusted(None, KEY));
+        assert!(!policy.is_trusted(Some(&unverified), KEY));
+        assert!(!policy.is_trusted(Some(&verified), OTHER_KEY));
+        assert!(policy.is_trusted(Some(&verified), KEY));
+    }
+
+    #[test]
+    fn key_changes_can_be_allowed() {
+        let policy = AllowKeyChanges;
+        let record = record(VerifiedStatus::Unverified);
+
+        assert!(policy.is_trusted(Some(&record), OTHER_KEY));
+    }
+}
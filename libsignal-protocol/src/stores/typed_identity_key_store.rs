@@ -0,0 +1,215 @@
+use crate::{
+    keys::{IdentityKeyPair, PublicKey},
+    stores::{IdentityKeyStore, IdentityRecord, VerifiedStatus},
+    Address, Buffer, Context, Error, Serializable,
+};
+use std::panic::RefUnwindSafe;
+
+/// A variant of [`IdentityKeyStore`] which works with decoded keys instead
+/// of raw bytes.
+///
+/// Use an [`IdentityKeyStoreAdapter`] to turn it into an
+/// [`IdentityKeyStore`].
+pub trait TypedIdentityKeyStore: RefUnwindSafe {
+    /// Get the local client's identity key pair.
+    fn identity_key_pair(&self) -> Result<IdentityKeyPair, Error>;
+
+    /// Get the local client's registration ID.
+    ///
+    /// Clients should maintain a registration ID, a random number
+    /// between 1 and 16380 that's generated once at install time.
+    fn local_registration_id(&self) -> Result<u32, Error>;
+
+    /// Verify a remote client's identity key.
+    ///
+    /// See [`IdentityKeyStore::is_trusted_identity()`] for the conventions
+    /// that should be followed.
+    fn is_trusted_identity(
+        &self,
+        address: Address,
+        identity_key: &PublicKey,
+    ) -> Result<bool, Error>;
+
+    /// Save a remote client's identity key as trusted.
+    fn save_identity(
+        &self,
+        address: Address,
+        identity_key: &PublicKey,
+    ) -> Result<(), Error>;
+
+    /// Remove a remote client's identity key from the store, retaining any
+    /// metadata that may be kept alongside it.
+    fn remove_identity(&self, address: Address) -> Result<(), Error>;
+
+    /// Get the identity key currently saved for a remote client, if any.
+    fn get_identity(
+        &self,
+        address: Address,
+    ) -> Result<Option<PublicKey>, Error>;
+
+    /// Get everything known about a remote client's identity.
+    ///
+    /// See [`IdentityKeyStore::identity_record()`].
+    fn identity_record(
+        &self,
+        _address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        Ok(None)
+    }
+
+    /// Save a remote client's [`IdentityRecord`], replacing any existing one.
+    ///
+    /// `identity_key` is the record's identity key after it has been
+    /// decoded, or `None` if the key was removed. By default only the key is
+    /// saved.
+    fn save_identity_record(
+        &self,
+        address: Address,
+        identity_key: Option<&PublicKey>,
+        _record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        match identity_key {
+            Some(identity_key) => self.save_identity(address, identity_key),
+            None => self.remove_identity(address),
+        }
+    }
+
+    /// Get the [`VerifiedStatus`] of a remote client's identity key.
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        let record = self.identity_record(address)?;
+
+        Ok(record.map_or(VerifiedStatus::Default, |r| r.verified))
+    }
+
+    /// Record whether the user has verified a remote client's identity key.
+    ///
+    /// By default this updates the recipient's [`IdentityRecord`], doing
+    /// nothing if there isn't one.
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        match self.identity_record(address.clone())? {
+            Some(mut record) => {
+                record.verified = status;
+                let identity_key = self.get_identity(address.clone())?;
+                self.save_identity_record(
+                    address,
+                    identity_key.as_ref(),
+                    &record,
+                )
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Lets a [`TypedIdentityKeyStore`] be used anywhere an [`IdentityKeyStore`]
+/// is expected, decoding keys on the way in and serializing them on the way
+/// out.
+#[derive(Debug)]
+pub struct IdentityKeyStoreAdapter<S> {
+    inner: S,
+    ctx: Context,
+}
+
+impl<S: TypedIdentityKeyStore> IdentityKeyStoreAdapter<S> {
+    /// Wrap a [`TypedIdentityKeyStore`], using the [`Context`] to decode
+    /// identity keys.
+    pub fn new(ctx: &Context, inner: S) -> IdentityKeyStoreAdapter<S> {
+        IdentityKeyStoreAdapter {
+            inner,
+            ctx: ctx.clone(),
+        }
+    }
+
+    /// Get a reference to the wrapped store.
+    pub const fn inner(&self) -> &S { &self.inner }
+
+    /// Unwrap the underlying store.
+    pub fn into_inner(self) -> S { self.inner }
+}
+
+impl<S: TypedIdentityKeyStore> IdentityKeyStore for IdentityKeyStoreAdapter<S> {
+    fn identity_key_pair(&self) -> Result<(Buffer, Buffer), Error> {
+        let identity = self.inner.identity_key_pair()?;
+        let public = identity.public().serialize()?;
+        let private = identity.private().serialize()?;
+
+        Ok((public, private))
+    }
+
+    fn local_registration_id(&self) -> Result<u32, Error> {
+        self.inner.local_registration_id()
+    }
+
+    fn is_trusted_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<bool, Error> {
+        let identity_key = PublicKey::decode_point(&self.ctx, identity_key)?;
+
+        self.inner.is_trusted_identity(address, &identity_key)
+    }
+
+    fn save_identity(
+        &self,
+        address: Address,
+        identity_key: &[u8],
+    ) -> Result<(), Error> {
+        if identity_key.is_empty() {
+            return self.inner.remove_identity(address);
+        }
+
+        let identity_key = PublicKey::decode_point(&self.ctx, identity_key)?;
+        self.inner.save_identity(address, &identity_key)
+    }
+
+    fn get_identity(&self, address: Address) -> Result<Option<Buffer>, Error> {
+        match self.inner.get_identity(address)? {
+            Some(identity_key) => identity_key.serialize().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn identity_record(
+        &self,
+        address: Address,
+    ) -> Result<Option<IdentityRecord>, Error> {
+        self.inner.identity_record(address)
+    }
+
+    fn save_identity_record(
+        &self,
+        address: Address,
+        record: &IdentityRecord,
+    ) -> Result<(), Error> {
+        let identity_key = match record.saved_key() {
+            Some(key) => Some(PublicKey::decode_point(&self.ctx, key)?),
+            None => None,
+        };
+
+        self.inner
+            .save_identity_record(address, identity_key.as_ref(), record)
+    }
+
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        self.inner.verified_status(address)
+    }
+
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        self.inner.set_verified_status(address, status)
+    }
+}
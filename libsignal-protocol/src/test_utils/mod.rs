@@ -0,0 +1,186 @@
+//! Helpers for testing code built on top of this crate.
+//!
+//! Everything here uses a [`DeterministicCrypto`], so keys, registration IDs
+//! and messages come out the same every time a test is run.
+//!
+//! ```rust,no_run
+//! # use libsignal_protocol::test_utils;
+//! # fn main() -> Result<(), libsignal_protocol::Error> {
+//! let (alice, bob) = test_utils::alice_and_bob(42)?;
+//! alice.start_session_with(&bob)?;
+//!
+//! let message = alice.session_cipher(&bob.address)?.encrypt(b"Hello")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A [`Simulator`] can be used to pass messages between several parties over
+//! an unreliable network.
+//!
+//! # Security
+//!
+//! **Never** use these outside of tests.
+
+mod simulator;
+
+pub use self::simulator::{Channel, Delivery, Simulator};
+
+use std::time::UNIX_EPOCH;
+
+use crate::{
+    crypto::DeterministicCrypto,
+    keys::IdentityKeyPair,
+    stores::{
+        InMemoryIdentityKeyStore, InMemoryPreKeyStore, InMemorySessionStore,
+        InMemorySignedPreKeyStore,
+    },
+    Address, Context, Error, InternalError, PreKeyBundle, SessionBuilder,
+    SessionCipher, StoreContext,
+};
+
+#[cfg(feature = "crypto-native")]
+type Provider = crate::crypto::DefaultCrypto;
+#[cfg(all(not(feature = "crypto-native"), feature = "crypto-openssl"))]
+type Provider = crate::crypto::OpenSSLCrypto;
+#[cfg(all(
+    not(feature = "crypto-native"),
+    not(feature = "crypto-openssl"),
+    feature = "crypto-ring"
+))]
+type Provider = crate::crypto::RingCrypto;
+
+/// The ID used for each [`TestParty`]'s one-time pre-key.
+pub const PRE_KEY_ID: u32 = 1;
+/// The ID used for each [`TestParty`]'s signed pre-key.
+pub const SIGNED_PRE_KEY_ID: u32 = 1;
+
+/// Create a [`Context`] whose random numbers are generated from `seed`.
+pub fn deterministic_context(seed: u64) -> Result<Context, Error> {
+    Context::new(DeterministicCrypto::new(Provider::default(), seed))
+}
+
+/// Create a pair of [`TestParty`]s called `"alice"` and `"bob"`, each with
+/// their own deterministic [`Context`].
+pub fn alice_and_bob(seed: u64) -> Result<(TestParty, TestParty), Error> {
+    let alice = TestParty::new(&deterministic_context(seed)?, "alice", 1)?;
+    let bob = TestParty::new(
+        &deterministic_context(seed.wrapping_add(1))?,
+        "bob",
+        1,
+    )?;
+
+    Ok((alice, bob))
+}
+
+/// Someone taking part in a conversation, with in-memory stores and a
+/// published [`PreKeyBundle`].
+#[derive(Debug, Clone)]
+pub struct TestParty {
+    /// The [`Context`] this party's keys were generated with.
+    pub ctx: Context,
+    /// The party's address.
+    pub address: Address,
+    /// The party's long-term identity.
+    pub identity: IdentityKeyPair,
+    /// The party's registration ID.
+    pub registration_id: u32,
+    /// The party's stores.
+    pub store_context: StoreContext,
+    /// The bundle other parties can use to start a session with this one.
+    pub pre_key_bundle: PreKeyBundle,
+}
+
+impl TestParty {
+    /// Generate a new identity, a pre-key and a signed pre-key, saving them
+    /// in a fresh set of in-memory stores.
+    pub fn new(
+        ctx: &Context,
+        name: &str,
+        device_id: i32,
+    ) -> Result<TestParty, Error> {
+        let address = Address::new(name, device_id);
+        let identity = crate::generate_identity_key_pair(ctx)?;
+        let registration_id = crate::generate_registration_id(ctx, 0)?;
+
+        let store_context = crate::store_context(
+            ctx,
+            InMemoryPreKeyStore::default(),
+            InMemorySignedPreKeyStore::default(),
+            InMemorySessionStore::default(),
+            InMemoryIdentityKeyStore::new(registration_id, &identity),
+        )?;
+
+        let pre_key = crate::generate_pre_keys(ctx, PRE_KEY_ID, 1)?
+            .next()
+            .ok_or(InternalError::Unknown)?;
+        // a fixed timestamp keeps the signed pre-key deterministic
+        let signed_pre_key = crate::generate_signed_pre_key(
+            ctx,
+            &identity,
+            SIGNED_PRE_KEY_ID,
+            UNIX_EPOCH,
+        )?;
+        store_context.store_pre_key(&pre_key)?;
+        store_context.store_signed_pre_key(&signed_pre_key)?;
+
+        let pre_key_bundle = PreKeyBundle::builder()
+            .registration_id(registration_id)
+            .device_id(device_id)
+            .pre_key(pre_key.id(), &pre_key.key_pair().public())
+            .signed_pre_key(
+                signed_pre_key.id(),
+                &signed_pre_key.key_pair().public(),
+            )
+            .signature(signed_pre_key.signature())
+            .identity_key(&identity.public())
+            .build()?;
+
+        Ok(TestParty {
+            ctx: ctx.clone(),
+            address,
+            identity,
+            registration_id,
+            store_context,
+            pre_key_bundle,
+        })
+    }
+
+    /// Generate another one-time pre-key and a [`PreKeyBundle`] which uses
+    /// it, so more than one party can start a session with this one.
+    pub fn new_pre_key_bundle(
+        &self,
+        pre_key_id: u32,
+    ) -> Result<PreKeyBundle, Error> {
+        let pre_key = crate::generate_pre_keys(&self.ctx, pre_key_id, 1)?
+            .next()
+            .ok_or(InternalError::Unknown)?;
+        self.store_context.store_pre_key(&pre_key)?;
+
+        let bundle = &self.pre_key_bundle;
+        PreKeyBundle::builder()
+            .registration_id(bundle.registration_id())
+            .device_id(bundle.device_id())
+            .pre_key(pre_key.id(), &pre_key.key_pair().public())
+            .signed_pre_key(
+                bundle.signed_pre_key_id(),
+                &bundle.signed_pre_key()?,
+            )
+            .signature(bundle.signed_pre_key_signature())
+            .identity_key(&bundle.identity_key()?)
+            .build()
+    }
+
+    /// Create a [`SessionCipher`] for talking to `remote`.
+    pub fn session_cipher(
+        &self,
+        remote: &Address,
+    ) -> Result<SessionCipher, Error> {
+        SessionCipher::new(&self.ctx, &self.store_context, remote)
+    }
+
+    /// Start a session with `other` using their [`PreKeyBundle`].
+    pub fn start_session_with(&self, other: &TestParty) -> Result<(), Error> {
+        SessionBuilder::new(&self.ctx, &self.store_context, &other.address)
+            .process_pre_key_bundle(&other.pre_key_bundle)
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashSet;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    messages::CiphertextMessage,
+    test_utils::{deterministic_context, TestParty, PRE_KEY_ID},
+    Address, DecryptedMessage, Error, InternalError, SessionBuilder,
+};
+
+/// How unreliable the network between parties in a [`Simulator`] is.
+///
+/// Each field is the probability (between `0.0` and `1.0`) of something
+/// happening to a message as it is sent.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Channel {
+    /// The message is dropped.
+    pub loss: f64,
+    /// The message is delivered twice.
+    pub duplication: f64,
+    /// The message overtakes some of the messages sent before it.
+    pub reordering: f64,
+}
+
+impl Channel {
+    /// A channel which delivers every message exactly once, in order.
+    pub const fn reliable() -> Channel {
+        Channel {
+            loss: 0.0,
+            duplication: 0.0,
+            reordering: 0.0,
+        }
+    }
+}
+
+/// A message in transit.
+#[derive(Debug, Clone)]
+struct Packet {
+    id: u64,
+    from: usize,
+    to: usize,
+    plaintext: Vec<u8>,
+    message: CiphertextMessage,
+}
+
+/// The outcome of delivering a message.
+#[derive(Debug)]
+pub struct Delivery {
+    /// Identifies the message, as returned by [`Simulator::send()`].
+    pub id: u64,
+    /// Who sent the message.
+    pub from: Address,
+    /// Who received the message.
+    pub to: Address,
+    /// What the message should decrypt to.
+    pub plaintext: Vec<u8>,
+    /// Was another copy of this message delivered before?
+    pub duplicate: bool,
+    /// What happened when the recipient tried to decrypt the message.
+    pub result: Result<DecryptedMessage, Error>,
+}
+
+impl Delivery {
+    /// Did the recipient reject this message because it had already seen it?
+    pub const fn is_duplicate_error(&self) -> bool {
+        matches!(
+            self.result,
+            Err(Error::InternalError(InternalError::DuplicateMessage))
+        )
+    }
+
+    /// Check the message was decrypted correctly the first time it was
+    /// delivered and rejected with [`InternalError::DuplicateMessage`] every
+    /// time after that.
+    ///
+    /// # Panics
+    ///
+    /// If it wasn't.
+    pub fn assert_expected(&self) {
+        if self.duplicate {
+            assert!(
+                self.is_duplicate_error(),
+                "Message {} from {:?} to {:?} was delivered twice but didn't \
+                 fail with DuplicateMessage: {:?}",
+                self.id,
+                self.from,
+                self.to,
+                self.result,
+            );
+            return;
+        }
+
+        match self.result {
+            Ok(ref decrypted) => assert_eq!(
+                decrypted.plaintext().as_slice(),
+                self.plaintext.as_slice(),
+                "Message {} from {:?} to {:?} decrypted incorrectly",
+                self.id,
+                self.from,
+                self.to,
+            ),
+            Err(ref e) => panic!(
+                "Unable to decrypt message {} from {:?} to {:?}: {}",
+                self.id, self.from, self.to, e
+            ),
+        }
+    }
+}
+
+/// Pass messages between several [`TestParty`]s over a [`Channel`] which
+/// may lose, duplicate or reorder them.
+///
+/// Everything, including the channel, is deterministic so a failing test
+/// can be replayed with the same seed.
+///
+/// ```rust,no_run
+/// # use libsignal_protocol::test_utils::{Channel, Simulator};
+/// # fn main() -> Result<(), libsignal_protocol::Error> {
+/// let channel = Channel {
+///     loss: 0.1,
+///     duplication: 0.1,
+///     reordering: 0.5,
+/// };
+/// let mut sim = Simulator::new(42, &["alice", "bob", "carol"], channel)?;
+///
+/// for i in 0..10_u8 {
+///     sim.send("alice", "bob", &[i])?;
+///     sim.send("carol", "alice", &[i])?;
+/// }
+///
+/// for delivery in sim.deliver_all() {
+///     delivery.assert_expected();
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Simulator {
+    parties: Vec<TestParty>,
+    channel: Channel,
+    rng: StdRng,
+    in_flight: Vec<Packet>,
+    delivered: HashSet<u64>,
+    next_id: u64,
+    next_pre_key_id: u32,
+    dropped: usize,
+}
+
+impl Simulator {
+    /// Create a [`TestParty`] for each name, all connected by `channel`.
+    pub fn new(
+        seed: u64,
+        names: &[&str],
+        channel: Channel,
+    ) -> Result<Simulator, Error> {
+        let parties = names
+            .iter()
+            .zip(seed..)
+            .map(|(name, seed)| {
+                TestParty::new(&deterministic_context(seed)?, name, 1)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Simulator {
+            parties,
+            channel,
+            rng: StdRng::seed_from_u64(seed),
+            in_flight: Vec::new(),
+            delivered: HashSet::new(),
+            next_id: 0,
+            next_pre_key_id: PRE_KEY_ID + 1,
+            dropped: 0,
+        })
+    }
+
+    /// Everyone taking part.
+    pub fn parties(&self) -> &[TestParty] { &self.parties }
+
+    /// Look up a party by name.
+    ///
+    /// # Panics
+    ///
+    /// If there is nobody with that name.
+    pub fn party(&self, name: &str) -> &TestParty {
+        &self.parties[self.index_of(name)]
+    }
+
+    /// How many messages are waiting to be delivered.
+    pub const fn in_flight(&self) -> usize { self.in_flight.len() }
+
+    /// How many messages the channel has lost.
+    pub const fn dropped(&self) -> usize { self.dropped }
+
+    /// Encrypt a message and hand it to the channel, starting a session
+    /// first if `from` doesn't have one with `to` yet.
+    ///
+    /// Returns an ID identifying the message in [`Delivery::id`].
+    pub fn send(
+        &mut self,
+        from: &str,
+        to: &str,
+        plaintext: &[u8],
+    ) -> Result<u64, Error> {
+        let from = self.index_of(from);
+        let to = self.index_of(to);
+        let sender = &self.parties[from];
+        let recipient = &self.parties[to];
+
+        if !sender.store_context.contains_session(&recipient.address)? {
+            // every session needs its own one-time pre-key
+            let bundle = recipient.new_pre_key_bundle(self.next_pre_key_id)?;
+            self.next_pre_key_id += 1;
+            SessionBuilder::new(
+                &sender.ctx,
+                &sender.store_context,
+                &recipient.address,
+            )
+            .process_pre_key_bundle(&bundle)?;
+        }
+
+        let message = sender
+            .session_cipher(&recipient.address)?
+            .encrypt(plaintext)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        let packet = Packet {
+            id,
+            from,
+            to,
+            plaintext: plaintext.to_vec(),
+            message,
+        };
+
+        if self.rng.gen_bool(self.channel.loss) {
+            self.dropped += 1;
+        } else {
+            if self.rng.gen_bool(self.channel.duplication) {
+                self.transmit(packet.clone());
+            }
+            self.transmit(packet);
+        }
+
+        Ok(id)
+    }
+
+    /// Deliver every message that is in flight, in the order the channel
+    /// decided on.
+    pub fn deliver_all(&mut self) -> Vec<Delivery> {
+        let packets = std::mem::take(&mut self.in_flight);
+
+        packets
+            .into_iter()
+            .map(|packet| self.deliver(packet))
+            .collect()
+    }
+
+    fn deliver(&mut self, packet: Packet) -> Delivery {
+        let sender = &self.parties[packet.from];
+        let recipient = &self.parties[packet.to];
+
+        let result = recipient
+            .session_cipher(&sender.address)
+            .and_then(|cipher| cipher.decrypt(&packet.message));
+
+        Delivery {
+            id: packet.id,
+            from: sender.address.clone(),
+            to: recipient.address.clone(),
+            plaintext: packet.plaintext,
+            duplicate: !self.delivered.insert(packet.id),
+            result,
+        }
+    }
+
+    fn transmit(&mut self, packet: Packet) {
+        if self.rng.gen_bool(self.channel.reordering) {
+            let position = self.rng.gen_range(0, self.in_flight.len() + 1);
+            self.in_flight.insert(position, packet);
+        } else {
+            self.in_flight.push(packet);
+        }
+    }
+
+    fn index_of(&self, name: &str) -> usize {
+        self.parties
+            .iter()
+            .position(|party| party.address.bytes() == name.as_bytes())
+            .unwrap_or_else(|| panic!("There is nobody called \"{}\"", name))
+    }
+}
@@ -1,19 +1,26 @@
 extern crate libsignal_protocol as sig;
 
 use std::{
+    collections::HashMap,
     convert::TryFrom,
+    rc::Rc,
+    sync::Mutex,
     time::{Duration, SystemTime},
 };
 
 use sig::{
-    keys::{PrivateKey, PublicKey},
+    keys::{IdentityKeyPair, PrivateKey, PublicKey},
     messages::{PreKeySignalMessage, SignalMessage},
+    ratchet::{ChainKey, RootKey},
     stores::{
-        InMemoryIdentityKeyStore, InMemoryPreKeyStore, InMemorySessionStore,
-        InMemorySignedPreKeyStore,
+        IdentityChange, IdentityChangeKind, IdentityEvent, IdentityKeyStore,
+        IdentityKeyStoreAdapter, InMemoryIdentityKeyStore, InMemoryPreKeyStore,
+        InMemorySessionStore, InMemorySignedPreKeyStore,
+        ObservedIdentityKeyStore, RequireVerification, TypedIdentityKeyStore,
+        VerifiedStatus,
     },
-    Address, Context, Deserializable, Error, InternalError, PreKeyBundle,
-    Serializable,
+    Address, Buffer, Context, Deserializable, Error, InternalError,
+    PreKeyBundle, Serializable,
 };
 
 use crate::helpers::{fake_random_generator, MockCrypto};
@@ -26,6 +33,8 @@ fn mock_ctx() -> Context {
             type Crypto = sig::crypto::DefaultCrypto;
         } else if #[cfg(feature = "crypto-openssl")] {
             type Crypto = sig::crypto::OpenSSLCrypto;
+        } else if #[cfg(feature = "crypto-ring")] {
+            type Crypto = sig::crypto::RingCrypto;
         } else {
             compile_error!("These tests require one of the crypto features to be enabled");
         }
@@ -172,6 +181,365 @@ fn test_generate_identity_key_pair() {
     assert_eq!(serialized.as_slice(), IDENTITY_KEY_PAIR);
 }
 
+#[test]
+fn test_identity_records() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let store = InMemoryIdentityKeyStore::new(
+        sig::generate_registration_id(&ctx, 0).unwrap(),
+        &sig::generate_identity_key_pair(&ctx).unwrap(),
+    );
+
+    store.save_identity(address.clone(), &[1, 2, 3]).unwrap();
+    store
+        .set_verified_status(address.clone(), VerifiedStatus::Verified)
+        .unwrap();
+    let verified = store.identity_record(address.clone()).unwrap().unwrap();
+    assert_eq!(verified.identity_key, &[1, 2, 3]);
+    assert_eq!(verified.verified, VerifiedStatus::Verified);
+
+    store.save_identity(address.clone(), &[4, 5, 6]).unwrap();
+    let changed = store.identity_record(address.clone()).unwrap().unwrap();
+    assert_eq!(changed.first_seen, verified.first_seen);
+    assert!(changed.last_changed >= verified.last_changed);
+    assert_eq!(changed.verified, VerifiedStatus::Unverified);
+
+    store.save_identity(address.clone(), &[]).unwrap();
+    assert!(store.get_identity(address.clone()).unwrap().is_none());
+    assert_eq!(
+        store.verified_status(address.clone()).unwrap(),
+        VerifiedStatus::Unverified
+    );
+
+    let expected = vec![
+        IdentityChange {
+            address: address.clone(),
+            kind: IdentityChangeKind::StatusChanged {
+                previous: VerifiedStatus::Default,
+                current: VerifiedStatus::Verified,
+            },
+        },
+        IdentityChange {
+            address: address.clone(),
+            kind: IdentityChangeKind::KeyChanged,
+        },
+        IdentityChange {
+            address,
+            kind: IdentityChangeKind::StatusChanged {
+                previous: VerifiedStatus::Verified,
+                current: VerifiedStatus::Unverified,
+            },
+        },
+    ];
+    assert_eq!(store.take_changes(), expected);
+}
+
+#[test]
+fn test_verification_is_revoked_when_a_removed_key_is_replaced() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let store = InMemoryIdentityKeyStore::new(
+        sig::generate_registration_id(&ctx, 0).unwrap(),
+        &sig::generate_identity_key_pair(&ctx).unwrap(),
+    )
+    .with_trust_policy(RequireVerification);
+
+    store.save_identity(address.clone(), &[1, 2, 3]).unwrap();
+    store
+        .set_verified_status(address.clone(), VerifiedStatus::Verified)
+        .unwrap();
+    assert!(store
+        .is_trusted_identity(address.clone(), &[1, 2, 3])
+        .unwrap());
+    store.take_changes();
+
+    store.save_identity(address.clone(), &[]).unwrap();
+    store.save_identity(address.clone(), &[4, 5, 6]).unwrap();
+
+    let record = store.identity_record(address.clone()).unwrap().unwrap();
+    assert_eq!(record.verified, VerifiedStatus::Unverified);
+    assert!(!record.nonblocking_approval);
+    assert!(!store
+        .is_trusted_identity(address.clone(), &[4, 5, 6])
+        .unwrap());
+    let expected = vec![
+        IdentityChange {
+            address: address.clone(),
+            kind: IdentityChangeKind::KeyChanged,
+        },
+        IdentityChange {
+            address,
+            kind: IdentityChangeKind::StatusChanged {
+                previous: VerifiedStatus::Verified,
+                current: VerifiedStatus::Unverified,
+            },
+        },
+    ];
+    assert_eq!(store.take_changes(), expected);
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_trust_on_first_use_can_be_disabled() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let mut store = InMemoryIdentityKeyStore::new(
+        sig::generate_registration_id(&ctx, 0).unwrap(),
+        &sig::generate_identity_key_pair(&ctx).unwrap(),
+    );
+    store.trust_on_first_use = false;
+
+    assert!(!store
+        .is_trusted_identity(address.clone(), &[1, 2, 3])
+        .unwrap());
+
+    store.save_identity(address.clone(), &[1, 2, 3]).unwrap();
+    assert!(store
+        .is_trusted_identity(address.clone(), &[1, 2, 3])
+        .unwrap());
+    assert!(!store
+        .is_trusted_identity(address.clone(), &[4, 5, 6])
+        .unwrap());
+
+    store.trust_on_first_use = true;
+    let stranger = Address::new("+14158888888", 1);
+    assert!(store.is_trusted_identity(stranger, &[4, 5, 6]).unwrap());
+}
+
+#[test]
+fn test_identity_events() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let first_key = sig::generate_identity_key_pair(&ctx).unwrap().public();
+    let second_key = sig::generate_identity_key_pair(&ctx).unwrap().public();
+
+    let store = ObservedIdentityKeyStore::new(
+        &ctx,
+        InMemoryIdentityKeyStore::new(
+            sig::generate_registration_id(&ctx, 0).unwrap(),
+            &sig::generate_identity_key_pair(&ctx).unwrap(),
+        ),
+    );
+    let events = Rc::new(Mutex::new(Vec::new()));
+    let recorded = Rc::clone(&events);
+    store.subscribe(move |event| recorded.lock().unwrap().push(event.clone()));
+
+    let first = first_key.serialize().unwrap();
+    let second = second_key.serialize().unwrap();
+    store.save_identity(address.clone(), first.as_slice()).unwrap();
+    store.save_identity(address.clone(), first.as_slice()).unwrap();
+    store.save_identity(address.clone(), second.as_slice()).unwrap();
+    store
+        .set_verified_status(address.clone(), VerifiedStatus::Verified)
+        .unwrap();
+    store.save_identity(address.clone(), &[]).unwrap();
+
+    let expected = vec![
+        IdentityEvent::FirstSeen {
+            address: address.clone(),
+            identity_key: first_key.clone(),
+        },
+        IdentityEvent::Changed {
+            address: address.clone(),
+            old: first_key,
+            new: second_key,
+        },
+        IdentityEvent::Verified {
+            address: address.clone(),
+            status: VerifiedStatus::Verified,
+        },
+        IdentityEvent::Removed { address },
+    ];
+    assert_eq!(*events.lock().unwrap(), expected);
+}
+
+#[test]
+fn test_corrupt_identity_keys_can_be_replaced() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let new_key = sig::generate_identity_key_pair(&ctx).unwrap().public();
+
+    let inner = InMemoryIdentityKeyStore::new(
+        sig::generate_registration_id(&ctx, 0).unwrap(),
+        &sig::generate_identity_key_pair(&ctx).unwrap(),
+    );
+    inner.save_identity(address.clone(), &[1, 2, 3]).unwrap();
+    let store = ObservedIdentityKeyStore::new(&ctx, inner);
+    let events = Rc::new(Mutex::new(Vec::new()));
+    let recorded = Rc::clone(&events);
+    store.subscribe(move |event| recorded.lock().unwrap().push(event.clone()));
+
+    let serialized = new_key.serialize().unwrap();
+    store
+        .save_identity(address.clone(), serialized.as_slice())
+        .unwrap();
+
+    assert_eq!(
+        store.get_identity(address.clone()).unwrap(),
+        Some(serialized)
+    );
+    let expected = vec![IdentityEvent::InvalidKeyReplaced {
+        address,
+        old: Buffer::from(&[1, 2, 3][..]),
+        new: new_key,
+    }];
+    assert_eq!(*events.lock().unwrap(), expected);
+}
+
+#[derive(Debug)]
+struct TypedStore {
+    identity: IdentityKeyPair,
+    identities: Mutex<HashMap<Address, PublicKey>>,
+    statuses: Mutex<HashMap<Address, VerifiedStatus>>,
+}
+
+impl TypedStore {
+    fn new(identity: &IdentityKeyPair) -> TypedStore {
+        TypedStore {
+            identity: identity.clone(),
+            identities: Mutex::new(HashMap::new()),
+            statuses: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl TypedIdentityKeyStore for TypedStore {
+    fn identity_key_pair(&self) -> Result<IdentityKeyPair, Error> {
+        Ok(self.identity.clone())
+    }
+
+    fn local_registration_id(&self) -> Result<u32, Error> { Ok(42) }
+
+    fn is_trusted_identity(
+        &self,
+        address: Address,
+        identity_key: &PublicKey,
+    ) -> Result<bool, Error> {
+        match self.identities.lock().unwrap().get(&address) {
+            Some(key) => Ok(key == identity_key),
+            None => Ok(true),
+        }
+    }
+
+    fn save_identity(
+        &self,
+        address: Address,
+        identity_key: &PublicKey,
+    ) -> Result<(), Error> {
+        let mut identities = self.identities.lock().unwrap();
+        identities.insert(address, identity_key.clone());
+        Ok(())
+    }
+
+    fn remove_identity(&self, address: Address) -> Result<(), Error> {
+        self.identities.lock().unwrap().remove(&address);
+        Ok(())
+    }
+
+    fn get_identity(
+        &self,
+        address: Address,
+    ) -> Result<Option<PublicKey>, Error> {
+        Ok(self.identities.lock().unwrap().get(&address).cloned())
+    }
+
+    fn verified_status(
+        &self,
+        address: Address,
+    ) -> Result<VerifiedStatus, Error> {
+        let status = self.statuses.lock().unwrap().get(&address).copied();
+        Ok(status.unwrap_or(VerifiedStatus::Default))
+    }
+
+    fn set_verified_status(
+        &self,
+        address: Address,
+        status: VerifiedStatus,
+    ) -> Result<(), Error> {
+        self.statuses.lock().unwrap().insert(address, status);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_typed_identity_key_store() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let identity = sig::generate_identity_key_pair(&ctx).unwrap();
+    let remote = sig::generate_identity_key_pair(&ctx).unwrap().public();
+    let remote_bytes = remote.serialize().unwrap();
+
+    let store = IdentityKeyStoreAdapter::new(&ctx, TypedStore::new(&identity));
+
+    let (public, private) = store.identity_key_pair().unwrap();
+    assert_eq!(public, identity.public().serialize().unwrap());
+    assert_eq!(private, identity.private().serialize().unwrap());
+
+    store
+        .save_identity(address.clone(), remote_bytes.as_slice())
+        .unwrap();
+    assert_eq!(
+        store.inner().get_identity(address.clone()).unwrap(),
+        Some(remote)
+    );
+    assert_eq!(
+        store.get_identity(address.clone()).unwrap(),
+        Some(remote_bytes.clone())
+    );
+    assert!(store
+        .is_trusted_identity(address.clone(), remote_bytes.as_slice())
+        .unwrap());
+
+    store.save_identity(address.clone(), &[]).unwrap();
+    assert!(store.get_identity(address.clone()).unwrap().is_none());
+
+    // garbage is rejected instead of being handed to the typed store
+    assert!(store.save_identity(address, &[0xff; 3]).is_err());
+}
+
+#[test]
+fn test_observed_typed_identity_key_store() {
+    let ctx = mock_ctx();
+    let address = Address::new("+14159999999", 1);
+    let identity = sig::generate_identity_key_pair(&ctx).unwrap();
+    let remote = sig::generate_identity_key_pair(&ctx).unwrap().public();
+    let remote_bytes = remote.serialize().unwrap();
+
+    let store = ObservedIdentityKeyStore::new(
+        &ctx,
+        IdentityKeyStoreAdapter::new(&ctx, TypedStore::new(&identity)),
+    );
+    let events = Rc::new(Mutex::new(Vec::new()));
+    let recorded = Rc::clone(&events);
+    store.subscribe(move |event| recorded.lock().unwrap().push(event.clone()));
+
+    store
+        .save_identity(address.clone(), remote_bytes.as_slice())
+        .unwrap();
+    store
+        .save_identity(address.clone(), remote_bytes.as_slice())
+        .unwrap();
+    store
+        .set_verified_status(address.clone(), VerifiedStatus::Verified)
+        .unwrap();
+
+    assert_eq!(
+        store.verified_status(address.clone()).unwrap(),
+        VerifiedStatus::Verified
+    );
+    let expected = vec![
+        IdentityEvent::FirstSeen {
+            address: address.clone(),
+            identity_key: remote,
+        },
+        IdentityEvent::Verified {
+            address,
+            status: VerifiedStatus::Verified,
+        },
+    ];
+    assert_eq!(*events.lock().unwrap(), expected);
+}
+
 #[test]
 fn test_curve25519_large_signatures() {
     let ctx = mock_ctx();
@@ -579,3 +947,785 @@ fn test_keys_calculate_agreement() {
         agreement
     );
 }
+
+#[test]
+fn test_chain_key_derivation_v2() {
+    const SEED: &[u8] = &[
+        0x8a, 0xb7, 0x2d, 0x6f, 0x4c, 0xc5, 0xac, 0x0d, 0x38, 0x7e, 0xaf, 0x46,
+        0x33, 0x78, 0xdd, 0xb2, 0x8e, 0xdd, 0x07, 0x38, 0x5b, 0x1c, 0xb0, 0x12,
+        0x50, 0xc7, 0x15, 0x98, 0x2e, 0x7a, 0xd4, 0x8f,
+    ];
+    const CIPHER_KEY: &[u8] = &[
+        0x02, 0xa9, 0xaa, 0x6c, 0x7d, 0xbd, 0x64, 0xf9, 0xd3, 0xaa, 0x92, 0xf9,
+        0x2a, 0x27, 0x7b, 0xf5, 0x46, 0x09, 0xda, 0xdf, 0x0b, 0x00, 0x82, 0x8a,
+        0xcf, 0xc6, 0x1e, 0x3c, 0x72, 0x4b, 0x84, 0xa7,
+    ];
+    const MAC_KEY: &[u8] = &[
+        0xbf, 0xbe, 0x5e, 0xfb, 0x60, 0x30, 0x30, 0x52, 0x67, 0x42, 0xe3, 0xee,
+        0x89, 0xc7, 0x02, 0x4e, 0x88, 0x4e, 0x44, 0x0f, 0x1f, 0xf3, 0x76, 0xbb,
+        0x23, 0x17, 0xb2, 0xd6, 0x4d, 0xeb, 0x7c, 0x83,
+    ];
+    const NEXT_CHAIN_KEY: &[u8] = &[
+        0x28, 0xe8, 0xf8, 0xfe, 0xe5, 0x4b, 0x80, 0x1e, 0xef, 0x7c, 0x5c, 0xfb,
+        0x2f, 0x17, 0xf3, 0x2c, 0x7b, 0x33, 0x44, 0x85, 0xbb, 0xb7, 0x0f, 0xac,
+        0x6e, 0xc1, 0x03, 0x42, 0xa2, 0x46, 0xd1, 0x5d,
+    ];
+
+    let ctx = mock_ctx();
+    let kdf = sig::create_hkdf(&ctx, 2).unwrap();
+
+    let chain_key = ChainKey::new(&ctx, &kdf, SEED, 0).unwrap();
+    assert_eq!(chain_key.key().unwrap().as_slice(), SEED);
+    assert_eq!(chain_key.index(), 0);
+
+    let message_keys = chain_key.message_keys().unwrap();
+    assert_eq!(message_keys.cipher_key(), CIPHER_KEY);
+    assert_eq!(message_keys.mac_key(), MAC_KEY);
+    assert_eq!(message_keys.counter(), 0);
+
+    let next = chain_key.next().unwrap();
+    assert_eq!(next.key().unwrap().as_slice(), NEXT_CHAIN_KEY);
+    assert_eq!(next.index(), 1);
+    assert_eq!(next.message_keys().unwrap().counter(), 1);
+}
+
+#[test]
+fn test_root_key_create_chain_v3() {
+    const ALICE_PRIVATE: &[u8] = &[
+        0xc8, 0x06, 0x43, 0x9d, 0xc9, 0xd2, 0xc4, 0x76, 0xff, 0xed, 0x8f, 0x25,
+        0x80, 0xc0, 0x88, 0x8d, 0x58, 0xab, 0x40, 0x6b, 0xf7, 0xae, 0x36, 0x98,
+        0x87, 0x90, 0x21, 0xb9, 0x6b, 0xb4, 0xbf, 0x59,
+    ];
+    const BOB_PUBLIC: &[u8] = &[
+        0x05, 0x6c, 0xee, 0x75, 0x4c, 0x35, 0x15, 0xde, 0x10, 0xc7, 0xad, 0x00,
+        0x5b, 0x5d, 0x02, 0xb6, 0x74, 0x94, 0xff, 0x94, 0x87, 0x25, 0x7f, 0x64,
+        0x5e, 0xfa, 0x51, 0x0c, 0xad, 0xe0, 0xa0, 0xee, 0x16,
+    ];
+    const NEXT_ROOT_KEY: &[u8] = &[
+        0xbb, 0x20, 0xcc, 0x66, 0xcc, 0xbe, 0x61, 0xf6, 0xcb, 0xd2, 0xe7, 0x20,
+        0xd2, 0xf7, 0x7a, 0x4d, 0xe5, 0x29, 0xd1, 0x85, 0xa3, 0xe5, 0x22, 0xf6,
+        0x63, 0x84, 0x86, 0xa7, 0xf8, 0xa8, 0x13, 0x30,
+    ];
+    const CHAIN_KEY: &[u8] = &[
+        0x57, 0x02, 0xc8, 0x61, 0x32, 0xc1, 0x73, 0xe4, 0x05, 0x08, 0x89, 0xf8,
+        0x6b, 0x1e, 0xd3, 0xef, 0x51, 0x3a, 0x0e, 0xa9, 0xdb, 0xaa, 0x3d, 0x36,
+        0x5a, 0x8c, 0xce, 0xa0, 0xf8, 0x0e, 0x5b, 0x83,
+    ];
+    const CIPHER_KEY: &[u8] = &[
+        0xc3, 0xfa, 0x13, 0xc3, 0xe8, 0x65, 0x27, 0x56, 0xb9, 0xf5, 0x56, 0xe1,
+        0x1c, 0x6d, 0x8b, 0x64, 0x3f, 0xd7, 0x31, 0x5f, 0xd4, 0xf7, 0x5d, 0x22,
+        0x5d, 0x0d, 0x26, 0x02, 0xc1, 0x5f, 0x5f, 0x5b,
+    ];
+    const MAC_KEY: &[u8] = &[
+        0x2c, 0xed, 0x63, 0x82, 0xd3, 0xef, 0x9b, 0x8f, 0xcd, 0x22, 0xea, 0xf2,
+        0xac, 0x33, 0xab, 0x51, 0x33, 0x20, 0x6d, 0xe1, 0x0a, 0xd0, 0xa4, 0x9e,
+        0xdb, 0xde, 0x3b, 0xae, 0x42, 0xda, 0x75, 0x5f,
+    ];
+    const IV: &[u8] = &[
+        0xf1, 0x92, 0x55, 0xb9, 0x8b, 0x69, 0x5d, 0xd1, 0x82, 0xba, 0xc9, 0xd4,
+        0x61, 0x23, 0x8b, 0xd2,
+    ];
+    let root_key_seed: Vec<u8> = (0..32).collect();
+
+    let ctx = mock_ctx();
+    let kdf = sig::create_hkdf(&ctx, 3).unwrap();
+    let alice_private = PrivateKey::decode_point(&ctx, ALICE_PRIVATE).unwrap();
+    let bob_public = PublicKey::decode_point(&ctx, BOB_PUBLIC).unwrap();
+
+    let root_key = RootKey::new(&ctx, &kdf, &root_key_seed).unwrap();
+    assert_eq!(root_key.key().unwrap().as_slice(), &root_key_seed[..]);
+
+    let (next_root_key, chain_key) =
+        root_key.create_chain(&bob_public, &alice_private).unwrap();
+    assert_eq!(next_root_key.key().unwrap().as_slice(), NEXT_ROOT_KEY);
+    assert_ne!(next_root_key, root_key);
+    assert_eq!(chain_key.key().unwrap().as_slice(), CHAIN_KEY);
+    assert_eq!(chain_key.index(), 0);
+
+    let message_keys = chain_key.message_keys().unwrap();
+    assert_eq!(message_keys.cipher_key(), CIPHER_KEY);
+    assert_eq!(message_keys.mac_key(), MAC_KEY);
+    assert_eq!(message_keys.iv(), IV);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_deterministic_alice_and_bob() {
+    use sig::test_utils;
+
+    let (alice, bob) = test_utils::alice_and_bob(42).unwrap();
+    alice.start_session_with(&bob).unwrap();
+
+    let outgoing = alice
+        .session_cipher(&bob.address)
+        .unwrap()
+        .encrypt(b"Hello, Bob")
+        .unwrap();
+    let incoming = PreKeySignalMessage::try_from(outgoing.clone()).unwrap();
+    let plaintext = bob
+        .session_cipher(&alice.address)
+        .unwrap()
+        .decrypt_pre_key_message(&incoming)
+        .unwrap();
+    assert_eq!(plaintext.as_slice(), b"Hello, Bob");
+
+    // the same seed gives the same keys and the same ciphertext
+    let (alice_again, bob_again) = test_utils::alice_and_bob(42).unwrap();
+    alice_again.start_session_with(&bob_again).unwrap();
+    let outgoing_again = alice_again
+        .session_cipher(&bob_again.address)
+        .unwrap()
+        .encrypt(b"Hello, Bob")
+        .unwrap();
+
+    assert_eq!(alice.identity.public(), alice_again.identity.public());
+    assert_eq!(
+        outgoing.serialize().unwrap().as_slice(),
+        outgoing_again.serialize().unwrap().as_slice()
+    );
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_padded_session_messages() {
+    use sig::{test_utils, Padding};
+
+    let (alice, bob) = test_utils::alice_and_bob(7).unwrap();
+    alice.start_session_with(&bob).unwrap();
+    let mut alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    alice_cipher.set_padding(Padding::signal());
+    let mut bob_cipher = bob.session_cipher(&alice.address).unwrap();
+    bob_cipher.set_padding(Padding::signal());
+
+    let short = alice_cipher.encrypt(b"Hi").unwrap();
+    let incoming = PreKeySignalMessage::try_from(short.clone()).unwrap();
+    let plaintext = bob_cipher.decrypt_pre_key_message(&incoming).unwrap();
+    assert_eq!(plaintext.as_slice(), b"Hi");
+
+    // messages of a similar length are indistinguishable
+    let longer = alice_cipher.encrypt(&[b'a'; 100]).unwrap();
+    assert_eq!(
+        short.serialize().unwrap().len(),
+        longer.serialize().unwrap().len()
+    );
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_envelope_dispatch() {
+    use sig::{
+        envelope::{Dispatcher, Envelope},
+        messages::CiphertextType,
+        test_utils,
+    };
+
+    let (alice, bob) = test_utils::alice_and_bob(3).unwrap();
+    alice.start_session_with(&bob).unwrap();
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let dispatcher = Dispatcher::new(&bob.ctx, &bob.store_context);
+
+    let mut first = Envelope::new(
+        &alice_cipher.encrypt(b"Hello, Bob").unwrap(),
+        alice.address.clone(),
+        bob.registration_id,
+        SystemTime::now(),
+    )
+    .unwrap();
+    first.set_server_guid("first");
+    assert_eq!(first.message_type(), CiphertextType::PreKey);
+
+    let (envelope, decrypted) = dispatcher
+        .dispatch(first.serialize().unwrap().as_slice())
+        .unwrap();
+    assert_eq!(envelope, first);
+    assert_eq!(decrypted.plaintext().as_slice(), b"Hello, Bob");
+    assert!(decrypted.new_session());
+
+    // once Bob replies, Alice switches to normal signal messages
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+    let reply = bob_cipher.encrypt(b"Hi, Alice").unwrap();
+    let reply = SignalMessage::try_from(reply).unwrap();
+    alice_cipher.decrypt_message(&reply).unwrap();
+
+    let second = Envelope::new(
+        &alice_cipher.encrypt(b"How are you?").unwrap(),
+        alice.address.clone(),
+        bob.registration_id,
+        SystemTime::now(),
+    )
+    .unwrap();
+    assert_eq!(second.message_type(), CiphertextType::Signal);
+    let decrypted = dispatcher.decrypt(&second).unwrap();
+    assert_eq!(decrypted.plaintext().as_slice(), b"How are you?");
+    assert!(!decrypted.new_session());
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_unified_decrypt() {
+    use sig::test_utils::{self, PRE_KEY_ID};
+
+    let (alice, bob) = test_utils::alice_and_bob(11).unwrap();
+    alice.start_session_with(&bob).unwrap();
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+
+    // the first message sets up the session and uses Bob's one-time pre-key
+    let first = alice_cipher.encrypt(b"first").unwrap();
+    let second = alice_cipher.encrypt(b"second").unwrap();
+
+    let decrypted = bob_cipher.decrypt(&first).unwrap();
+    assert_eq!(decrypted.plaintext().as_slice(), b"first");
+    assert!(decrypted.new_session());
+    assert_eq!(decrypted.pre_key_id(), Some(PRE_KEY_ID));
+
+    // Alice keeps sending pre-key messages until Bob replies, but they don't
+    // set up another session
+    let serialized = second.serialize().unwrap();
+    let decrypted = bob_cipher
+        .decrypt_serialized(serialized.as_slice())
+        .unwrap();
+    assert_eq!(decrypted.plaintext().as_slice(), b"second");
+    assert!(!decrypted.new_session());
+    assert_eq!(decrypted.pre_key_id(), None);
+
+    let reply = bob_cipher.encrypt(b"reply").unwrap();
+    let serialized = reply.serialize().unwrap();
+    let decrypted = alice_cipher
+        .decrypt_serialized(serialized.as_slice())
+        .unwrap();
+    assert_eq!(decrypted.plaintext().as_slice(), b"reply");
+    assert!(!decrypted.new_session());
+
+    // a corrupt message reports why it isn't a valid pre-key message
+    let serialized = first.serialize().unwrap();
+    let corrupt = &serialized.as_slice()[..serialized.as_slice().len() / 2];
+    let expected =
+        PreKeySignalMessage::deserialize(&bob.ctx, corrupt).unwrap_err();
+    let got = bob_cipher.decrypt_serialized(corrupt).unwrap_err();
+    assert_eq!(got.to_string(), expected.to_string());
+
+    // messages from ancient clients are rejected up front
+    match bob_cipher.decrypt_serialized(&[0x11, 0x22, 0x33]) {
+        Err(Error::InternalError(InternalError::LegacyMessage)) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_manual_session_initialization() {
+    use sig::{
+        ratchet::{self, AliceParameters, BobParameters},
+        test_utils, SessionRecord,
+    };
+
+    let (alice, bob) = test_utils::alice_and_bob(5).unwrap();
+
+    // the keys Alice and Bob swapped out-of-band
+    let alice_base_key = sig::generate_key_pair(&alice.ctx).unwrap();
+    let bob_signed_pre_key = sig::generate_key_pair(&bob.ctx).unwrap();
+
+    let parameters = AliceParameters::builder()
+        .our_identity_key(&alice.identity)
+        .our_base_key(&alice_base_key)
+        .their_identity_key(&bob.identity.public())
+        .their_signed_pre_key(&bob_signed_pre_key.public())
+        .their_ratchet_key(&bob_signed_pre_key.public())
+        .build()
+        .unwrap();
+    let mut alice_state =
+        ratchet::initialize_alice_session(&alice.ctx, &parameters).unwrap();
+    alice_state.set_remote_registration_id(bob.registration_id);
+    let record = SessionRecord::new(&alice.ctx, &alice_state).unwrap();
+    alice
+        .store_context
+        .store_session(&bob.address, &record)
+        .unwrap();
+
+    let parameters = BobParameters::builder()
+        .our_identity_key(&bob.identity)
+        .our_signed_pre_key(&bob_signed_pre_key)
+        .our_ratchet_key(&bob_signed_pre_key)
+        .their_identity_key(&alice.identity.public())
+        .their_base_key(&alice_base_key.public())
+        .build()
+        .unwrap();
+    let bob_state =
+        ratchet::initialize_bob_session(&bob.ctx, &parameters).unwrap();
+    let record = SessionRecord::new(&bob.ctx, &bob_state).unwrap();
+    bob.store_context
+        .store_session(&alice.address, &record)
+        .unwrap();
+
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+    assert_eq!(alice_cipher.get_session_version().unwrap(), 3);
+    assert_eq!(
+        alice_cipher.get_remote_registration_id().unwrap(),
+        bob.registration_id
+    );
+
+    // there's no pre-key message because the session already exists
+    let message = alice_cipher.encrypt(b"Hello, Bob").unwrap();
+    let message = SignalMessage::try_from(message).unwrap();
+    let plaintext = bob_cipher.decrypt_message(&message).unwrap();
+    assert_eq!(plaintext.as_slice(), b"Hello, Bob");
+
+    let reply = bob_cipher.encrypt(b"Hi, Alice").unwrap();
+    let reply = SignalMessage::try_from(reply).unwrap();
+    let plaintext = alice_cipher.decrypt_message(&reply).unwrap();
+    assert_eq!(plaintext.as_slice(), b"Hi, Alice");
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_symmetric_session_initialization() {
+    use sig::{
+        ratchet::{self, SymmetricParameters},
+        test_utils, SessionRecord,
+    };
+
+    let (alice, bob) = test_utils::alice_and_bob(6).unwrap();
+    let alice_base_key = sig::generate_key_pair(&alice.ctx).unwrap();
+    let alice_ratchet_key = sig::generate_key_pair(&alice.ctx).unwrap();
+    let bob_base_key = sig::generate_key_pair(&bob.ctx).unwrap();
+    let bob_ratchet_key = sig::generate_key_pair(&bob.ctx).unwrap();
+
+    let parameters = SymmetricParameters::builder()
+        .our_identity_key(&alice.identity)
+        .our_base_key(&alice_base_key)
+        .our_ratchet_key(&alice_ratchet_key)
+        .their_identity_key(&bob.identity.public())
+        .their_base_key(&bob_base_key.public())
+        .their_ratchet_key(&bob_ratchet_key.public())
+        .build()
+        .unwrap();
+    let state =
+        ratchet::initialize_symmetric_session(&alice.ctx, &parameters).unwrap();
+    let record = SessionRecord::new(&alice.ctx, &state).unwrap();
+    alice
+        .store_context
+        .store_session(&bob.address, &record)
+        .unwrap();
+
+    let parameters = SymmetricParameters::builder()
+        .our_identity_key(&bob.identity)
+        .our_base_key(&bob_base_key)
+        .our_ratchet_key(&bob_ratchet_key)
+        .their_identity_key(&alice.identity.public())
+        .their_base_key(&alice_base_key.public())
+        .their_ratchet_key(&alice_ratchet_key.public())
+        .build()
+        .unwrap();
+    let state =
+        ratchet::initialize_symmetric_session(&bob.ctx, &parameters).unwrap();
+    let record = SessionRecord::new(&bob.ctx, &state).unwrap();
+    bob.store_context
+        .store_session(&alice.address, &record)
+        .unwrap();
+
+    // either side can send the first message
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+
+    let message = bob_cipher.encrypt(b"Hello, Alice").unwrap();
+    let message = SignalMessage::try_from(message).unwrap();
+    let plaintext = alice_cipher.decrypt_message(&message).unwrap();
+    assert_eq!(plaintext.as_slice(), b"Hello, Alice");
+
+    let message = alice_cipher.encrypt(b"Hello, Bob").unwrap();
+    let message = SignalMessage::try_from(message).unwrap();
+    let plaintext = bob_cipher.decrypt_message(&message).unwrap();
+    assert_eq!(plaintext.as_slice(), b"Hello, Bob");
+}
+
+#[test]
+fn test_session_parameters_require_keys() {
+    use sig::{ratchet::BobParameters, RequiredField};
+
+    let ctx = mock_ctx();
+    let identity = sig::generate_identity_key_pair(&ctx).unwrap();
+
+    let got = BobParameters::builder().our_identity_key(&identity).build();
+
+    match got {
+        Err(Error::MissingRequiredField(RequiredField::OurSignedPreKey)) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_session_limits() {
+    use sig::{test_utils, SessionLimits};
+
+    let (alice, bob) = test_utils::alice_and_bob(13).unwrap();
+    alice.start_session_with(&bob).unwrap();
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let mut bob_cipher = bob.session_cipher(&alice.address).unwrap();
+    bob_cipher.set_limits(
+        SessionLimits::new(2, SessionLimits::MAX_ARCHIVED_STATES).unwrap(),
+    );
+
+    let messages: Vec<_> = (0..5)
+        .map(|i| alice_cipher.encrypt(&[i]).unwrap())
+        .collect();
+
+    // skipping ahead leaves keys for the 4 earlier messages, but only the
+    // newest 2 are kept
+    let decrypted = bob_cipher.decrypt(&messages[4]).unwrap();
+    assert_eq!(decrypted.plaintext().as_slice(), &[4]);
+
+    let record = bob.store_context.load_session(&alice.address).unwrap();
+    assert_eq!(record.state().skipped_message_key_count().unwrap(), 2);
+    assert!(record.archived_states().is_empty());
+
+    for message in &messages[..2] {
+        assert!(bob_cipher.decrypt(message).is_err());
+    }
+    for (i, message) in messages.iter().enumerate().take(4).skip(2) {
+        let decrypted = bob_cipher.decrypt(message).unwrap();
+        assert_eq!(decrypted.plaintext().as_slice(), &[i as u8]);
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_session_diagnostics() {
+    use sig::test_utils;
+
+    let (alice, bob) = test_utils::alice_and_bob(17).unwrap();
+    alice.start_session_with(&bob).unwrap();
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+
+    let record = alice.store_context.load_session(&bob.address).unwrap();
+    let diagnostics = record.diagnostics().unwrap();
+    assert_eq!(diagnostics.version, 3);
+    assert_eq!(
+        diagnostics.serialized_size,
+        record.serialize().unwrap().as_slice().len()
+    );
+    assert_eq!(diagnostics.archived_states, 0);
+    assert!(diagnostics.unacknowledged_pre_key_message);
+    assert!(!diagnostics.needs_refresh);
+
+    // Bob skips a message, then replies so Alice gets a receiver chain
+    let _skipped = alice_cipher.encrypt(b"skipped").unwrap();
+    let message = alice_cipher.encrypt(b"received").unwrap();
+    bob_cipher.decrypt(&message).unwrap();
+    let reply = bob_cipher.encrypt(b"reply").unwrap();
+    alice_cipher.decrypt(&reply).unwrap();
+
+    let record = bob.store_context.load_session(&alice.address).unwrap();
+    let diagnostics = record.diagnostics().unwrap();
+    assert_eq!(diagnostics.receiver_chains.len(), 1);
+    assert_eq!(diagnostics.receiver_chains[0].index, 2);
+    assert_eq!(diagnostics.receiver_chains[0].skipped_message_keys, 1);
+    assert_eq!(diagnostics.message_keys(), 1);
+
+    let record = alice.store_context.load_session(&bob.address).unwrap();
+    let diagnostics = record.diagnostics().unwrap();
+    assert!(!diagnostics.unacknowledged_pre_key_message);
+    assert_eq!(diagnostics.receiver_chains.len(), 1);
+    assert_eq!(diagnostics.message_keys(), 0);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_pre_key_bundle_round_trip() {
+    use sig::test_utils::{self, PRE_KEY_ID};
+
+    let (_alice, bob) = test_utils::alice_and_bob(19).unwrap();
+    let original = &bob.pre_key_bundle;
+    original.verify_signature().unwrap();
+
+    let serialized = original.serialize().unwrap();
+    let got =
+        PreKeyBundle::deserialize(&bob.ctx, serialized.as_slice()).unwrap();
+
+    assert_eq!(got.registration_id(), original.registration_id());
+    assert_eq!(got.device_id(), original.device_id());
+    assert_eq!(got.pre_key_id(), PRE_KEY_ID);
+    assert_eq!(got.pre_key().unwrap(), original.pre_key().unwrap());
+    assert_eq!(got.signed_pre_key_id(), original.signed_pre_key_id());
+    assert_eq!(
+        got.signed_pre_key().unwrap(),
+        original.signed_pre_key().unwrap()
+    );
+    assert_eq!(
+        got.signed_pre_key_signature(),
+        original.signed_pre_key_signature()
+    );
+    assert_eq!(
+        got.identity_key().unwrap(),
+        original.identity_key().unwrap()
+    );
+    got.verify_signature().unwrap();
+
+    // a bundle signed by someone else is rejected
+    let imposter = sig::generate_identity_key_pair(&bob.ctx).unwrap();
+    let forged = PreKeyBundle::builder()
+        .registration_id(got.registration_id())
+        .device_id(got.device_id())
+        .signed_pre_key(got.signed_pre_key_id(), &got.signed_pre_key().unwrap())
+        .signature(got.signed_pre_key_signature())
+        .identity_key(&imposter.public())
+        .build()
+        .unwrap();
+    match forged.verify_signature() {
+        Err(Error::InvalidSignature) => {},
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_simulated_reordering_and_duplication() {
+    use sig::test_utils::{Channel, Simulator};
+
+    let channel = Channel {
+        duplication: 0.3,
+        reordering: 0.5,
+        ..Channel::reliable()
+    };
+    let mut sim =
+        Simulator::new(23, &["alice", "bob", "carol"], channel).unwrap();
+    let mut duplicates = 0;
+
+    for round in 0..4_u8 {
+        for i in 0..5 {
+            sim.send("alice", "bob", &[round, i]).unwrap();
+            sim.send("carol", "alice", &[round, i]).unwrap();
+            sim.send("bob", "carol", &[round, i]).unwrap();
+        }
+        for delivery in sim.deliver_all() {
+            delivery.assert_expected();
+            if delivery.duplicate {
+                duplicates += 1;
+            }
+        }
+
+        // replying moves the ratchet forward while old messages are still
+        // being duplicated
+        for i in 0..3 {
+            sim.send("bob", "alice", &[round, i]).unwrap();
+            sim.send("alice", "carol", &[round, i]).unwrap();
+        }
+        for delivery in sim.deliver_all() {
+            delivery.assert_expected();
+            if delivery.duplicate {
+                duplicates += 1;
+            }
+        }
+    }
+
+    assert_eq!(sim.in_flight(), 0);
+    assert_eq!(sim.dropped(), 0);
+    assert!(duplicates > 0);
+}
+
+#[cfg(feature = "test-utils")]
+#[test]
+fn test_simulated_message_loss() {
+    use sig::test_utils::{Channel, Simulator};
+
+    let channel = Channel {
+        loss: 0.3,
+        reordering: 0.2,
+        ..Channel::reliable()
+    };
+    let mut sim = Simulator::new(29, &["alice", "bob"], channel).unwrap();
+    let mut sent = 0;
+    let mut delivered = 0;
+
+    for round in 0..10_u8 {
+        let (from, to) = if round % 2 == 0 {
+            ("alice", "bob")
+        } else {
+            ("bob", "alice")
+        };
+        for i in 0..5 {
+            sim.send(from, to, &[round, i]).unwrap();
+            sent += 1;
+        }
+
+        for delivery in sim.deliver_all() {
+            // nothing is duplicated, so everything which turns up should
+            // decrypt
+            assert!(!delivery.duplicate);
+            delivery.assert_expected();
+            delivered += 1;
+        }
+    }
+
+    assert!(sim.dropped() > 0);
+    assert_eq!(delivered + sim.dropped(), sent);
+}
+
+/// Save payloads from a real conversation as seeds for the fuzz targets in
+/// `fuzz/`.
+///
+/// Run it with `cargo test --features test-utils -- --ignored
+/// generate_fuzz_corpus`.
+#[cfg(feature = "test-utils")]
+#[test]
+#[ignore]
+fn generate_fuzz_corpus() {
+    use sig::{
+        envelope::Envelope,
+        sealed_sender::{SenderCertificate, ServerCertificate},
+        test_utils,
+    };
+    use std::{fs, path::Path, time::UNIX_EPOCH};
+
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus");
+    // the prefix keeps these from overwriting the test vectors and the seeds
+    // from scripts/fuzz_seeds.py
+    let save = |target: &str, name: &str, data: &[u8]| {
+        let dir = corpus.join(target);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("generated-{}", name)), data).unwrap();
+    };
+    let now = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+    let (alice, bob) = test_utils::alice_and_bob(31).unwrap();
+    alice.start_session_with(&bob).unwrap();
+    let alice_cipher = alice.session_cipher(&bob.address).unwrap();
+    let bob_cipher = bob.session_cipher(&alice.address).unwrap();
+
+    let first = alice_cipher.encrypt(b"Hello, Bob").unwrap();
+    bob_cipher.decrypt(&first).unwrap();
+    let reply = bob_cipher.encrypt(b"Hello, Alice").unwrap();
+    alice_cipher.decrypt(&reply).unwrap();
+    save(
+        "pre_key_signal_message",
+        "first-message",
+        first.serialize().unwrap().as_slice(),
+    );
+    save(
+        "signal_message",
+        "reply",
+        reply.serialize().unwrap().as_slice(),
+    );
+
+    let mut envelope =
+        Envelope::new(&first, alice.address.clone(), bob.registration_id, now)
+            .unwrap();
+    save(
+        "envelope",
+        "pre-key-message",
+        envelope.serialize().unwrap().as_slice(),
+    );
+    envelope.set_server_guid("5f1c3c2e-0b59-4a6c-9f0e-5d2b7c1e8a90");
+    save(
+        "envelope",
+        "with-guid",
+        envelope.serialize().unwrap().as_slice(),
+    );
+
+    for (name, party, other) in
+        &[("alice", &alice, &bob), ("bob", &bob, &alice)]
+    {
+        let record = party.store_context.load_session(&other.address).unwrap();
+        save(
+            "session_record",
+            name,
+            record.serialize().unwrap().as_slice(),
+        );
+        save(
+            "pre_key_bundle",
+            name,
+            party.pre_key_bundle.serialize().unwrap().as_slice(),
+        );
+        save(
+            "public_key",
+            &format!("{}-identity", name),
+            party.identity.public().serialize().unwrap().as_slice(),
+        );
+        save(
+            "private_key",
+            &format!("{}-identity", name),
+            party.identity.private().serialize().unwrap().as_slice(),
+        );
+    }
+
+    let trust_root = sig::generate_key_pair(&alice.ctx).unwrap();
+    let server_key = sig::generate_key_pair(&alice.ctx).unwrap();
+    let server_certificate = ServerCertificate::new(
+        &alice.ctx,
+        1,
+        &server_key.public(),
+        &trust_root.private(),
+    )
+    .unwrap();
+    let sender_certificate = SenderCertificate::new(
+        &alice.ctx,
+        alice.address.clone(),
+        &alice.identity.public(),
+        now,
+        server_certificate.clone(),
+        &server_key.private(),
+    )
+    .unwrap();
+    save(
+        "server_certificate",
+        "server",
+        server_certificate.serialize().unwrap().as_slice(),
+    );
+    save(
+        "sender_certificate",
+        "alice",
+        sender_certificate.serialize().unwrap().as_slice(),
+    );
+}
+
+#[cfg(all(feature = "serde", feature = "test-utils"))]
+#[test]
+fn test_serde_round_trip() {
+    use serde::de::DeserializeSeed;
+    use sig::{
+        messages::CiphertextMessage, test_utils, ContextSeed, SessionBuilder,
+    };
+
+    let (alice, bob) = test_utils::alice_and_bob(23).unwrap();
+
+    // Alice fetches Bob's bundle from a JSON API
+    let json = serde_json::to_string(&bob.pre_key_bundle).unwrap();
+    let mut deserializer = serde_json::Deserializer::from_str(&json);
+    let bundle: PreKeyBundle = ContextSeed::new(&alice.ctx)
+        .deserialize(&mut deserializer)
+        .unwrap();
+    assert_eq!(bundle.identity_key().unwrap(), bob.identity.public());
+    SessionBuilder::new(&alice.ctx, &alice.store_context, &bob.address)
+        .process_pre_key_bundle(&bundle)
+        .unwrap();
+
+    // then sends her first message the same way
+    let message = alice
+        .session_cipher(&bob.address)
+        .unwrap()
+        .encrypt(b"Hello, Bob")
+        .unwrap();
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["type"], "pre_key");
+    let message: CiphertextMessage =
+        ContextSeed::new(&bob.ctx).deserialize(json).unwrap();
+    let decrypted = bob
+        .session_cipher(&alice.address)
+        .unwrap()
+        .decrypt(&message)
+        .unwrap();
+    assert_eq!(decrypted.plaintext().as_slice(), b"Hello, Bob");
+
+    // keys are written using their base64 representation
+    let key = bob.identity.public();
+    let json = serde_json::to_string(&key).unwrap();
+    assert_eq!(json, format!("\"{}\"", key.to_base64().unwrap()));
+    let mut deserializer = serde_json::Deserializer::from_str(&json);
+    let round_tripped: PublicKey = ContextSeed::new(&alice.ctx)
+        .deserialize(&mut deserializer)
+        .unwrap();
+    assert_eq!(round_tripped, key);
+}
@@ -0,0 +1,179 @@
+#!/bin/env python3
+
+"""
+Build hand-written seeds for the fuzz targets in `libsignal-protocol/fuzz/`.
+
+Each seed is assembled from the key test vectors already in
+`corpus/public_key/` and `corpus/private_key/`, using the same wire formats as
+the library. Signatures, MACs and ciphertexts are filler because none of the
+deserializers check them.
+
+Unlike `generate_fuzz_corpus` this doesn't need `libsignal-protocol-c`, so the
+seeds can be rebuilt anywhere.
+"""
+
+import struct
+from pathlib import Path
+
+PROJECT_ROOT = Path(__file__).parent.parent
+CORPUS = PROJECT_ROOT.joinpath("libsignal-protocol", "fuzz", "corpus")
+
+# (current version << 4) | current version
+MESSAGE_VERSION = 0x33
+CIPHERTEXT_SIGNAL_TYPE = 2
+CIPHERTEXT_PREKEY_TYPE = 3
+TIMESTAMP_MILLIS = 1_600_000_000_000
+
+
+def vector(target: str, name: str) -> bytes:
+    return CORPUS.joinpath(target, name).read_bytes()
+
+
+def varint(value: int) -> bytes:
+    out = bytearray()
+    while True:
+        byte = value & 0x7f
+        value >>= 7
+        if value:
+            out.append(byte | 0x80)
+        else:
+            out.append(byte)
+            return bytes(out)
+
+
+def uint_field(tag: int, value: int) -> bytes:
+    return varint(tag << 3) + varint(value)
+
+
+def bytes_field(tag: int, value: bytes) -> bytes:
+    return varint((tag << 3) | 2) + varint(len(value)) + value
+
+
+def length_prefixed(data: bytes) -> bytes:
+    """The framing used by `sealed_sender::put_bytes()`."""
+    return struct.pack(">I", len(data)) + data
+
+
+def signal_message(ratchet_key: bytes, counter: int) -> bytes:
+    proto = (bytes_field(1, ratchet_key)
+             + uint_field(2, counter)
+             + uint_field(3, 0)
+             + bytes_field(4, bytes(range(16))))
+    mac = bytes(8)
+    return bytes([MESSAGE_VERSION]) + proto + mac
+
+
+def pre_key_signal_message(message: bytes) -> bytes:
+    proto = (uint_field(5, 1234)
+             + uint_field(1, 31337)
+             + uint_field(6, 1)
+             + bytes_field(2, vector("public_key", "alice-ephemeral-public"))
+             + bytes_field(3, vector("public_key", "alice-identity-public"))
+             + bytes_field(4, message))
+    return bytes([MESSAGE_VERSION]) + proto
+
+
+def envelope(message_type: int, content: bytes, guid=None) -> bytes:
+    proto = (uint_field(1, message_type)
+             + bytes_field(2, b"+14151111111")
+             + uint_field(3, 1)
+             + uint_field(4, 5678)
+             + uint_field(5, TIMESTAMP_MILLIS)
+             + bytes_field(6, content))
+    if guid is not None:
+        proto += bytes_field(7, guid.encode("utf-8"))
+    return proto
+
+
+def chain(ratchet_key: bytes, index: int, private_key=None,
+          skipped=()) -> bytes:
+    proto = bytes_field(1, ratchet_key)
+    if private_key is not None:
+        proto += bytes_field(2, private_key)
+    proto += bytes_field(3, uint_field(1, index) + bytes_field(2, bytes(32)))
+    for counter in skipped:
+        message_key = (uint_field(1, counter)
+                       + bytes_field(2, bytes(32))
+                       + bytes_field(3, bytes(32))
+                       + bytes_field(4, bytes(16)))
+        proto += bytes_field(4, message_key)
+    return proto
+
+
+def session_structure(receiver_chains) -> bytes:
+    proto = (uint_field(1, 3)
+             + bytes_field(2, vector("public_key", "alice-identity-public"))
+             + bytes_field(3, vector("public_key", "bob-public"))
+             + bytes_field(4, bytes(32))
+             + uint_field(5, 0)
+             + bytes_field(6, chain(vector("public_key", "alice-public"), 2,
+                                    vector("private_key", "alice-private"))))
+    for receiver_chain in receiver_chains:
+        proto += bytes_field(7, receiver_chain)
+    proto += (uint_field(10, 1234)
+              + uint_field(11, 5678)
+              + bytes_field(13, vector("public_key", "alice-ephemeral-public")))
+    return proto
+
+
+def session_record() -> bytes:
+    current = session_structure([
+        chain(vector("public_key", "bob-public"), 3, skipped=[0, 2]),
+    ])
+    previous = session_structure([])
+    return bytes_field(1, current) + bytes_field(2, previous)
+
+
+def pre_key_bundle() -> bytes:
+    return (uint_field(1, 1234)
+            + uint_field(2, 1)
+            + uint_field(3, 31337)
+            + bytes_field(4, vector("public_key", "alice-ephemeral-public"))
+            + uint_field(5, 1)
+            + bytes_field(6, vector("public_key", "bob-public"))
+            + bytes_field(7, bytes(64))
+            + bytes_field(8, vector("public_key", "alice-identity-public")))
+
+
+def server_certificate() -> bytes:
+    certificate = (struct.pack(">I", 1)
+                   + length_prefixed(vector("public_key", "bob-public")))
+    return length_prefixed(certificate) + length_prefixed(bytes(64))
+
+
+def sender_certificate() -> bytes:
+    certificate = (length_prefixed(b"+14151111111")
+                   + struct.pack(">I", 1)
+                   + struct.pack(">Q", TIMESTAMP_MILLIS)
+                   + length_prefixed(vector("public_key",
+                                            "alice-identity-public"))
+                   + length_prefixed(server_certificate()))
+    return length_prefixed(certificate) + length_prefixed(bytes(64))
+
+
+def save(target: str, name: str, data: bytes):
+    directory = CORPUS.joinpath(target)
+    directory.mkdir(parents=True, exist_ok=True)
+    directory.joinpath(name).write_bytes(data)
+
+
+def main():
+    message = signal_message(vector("public_key", "bob-public"), 1)
+    pre_key_message = pre_key_signal_message(
+        signal_message(vector("public_key", "alice-public"), 0))
+
+    save("signal_message", "vector", message)
+    save("pre_key_signal_message", "vector", pre_key_message)
+    save("envelope", "vector-pre-key-message",
+         envelope(CIPHERTEXT_PREKEY_TYPE, pre_key_message))
+    save("envelope", "vector-with-guid",
+         envelope(CIPHERTEXT_SIGNAL_TYPE, message,
+                  "5f1c3c2e-0b59-4a6c-9f0e-5d2b7c1e8a90"))
+    save("session_record", "vector", session_record())
+    save("pre_key_bundle", "vector", pre_key_bundle())
+    save("server_certificate", "vector", server_certificate())
+    save("sender_certificate", "vector", sender_certificate())
+
+
+if __name__ == "__main__":
+    main()
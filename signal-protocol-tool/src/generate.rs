@@ -0,0 +1,165 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context as _, Error};
+use libsignal_protocol::{self as sig, Context, PreKeyBundle, Serializable};
+use structopt::StructOpt;
+
+/// Generate a new identity, registration ID, pre-keys and signed pre-key.
+///
+/// Everything is written to the output directory as raw serialized bytes,
+/// along with a `bundle` containing the public half of the keys. On Unix the
+/// files containing private keys (`identity_key_pair`, `signed_pre_key` and
+/// `pre_keys/*`) are only readable by the current user.
+#[derive(Debug, StructOpt)]
+pub struct Generate {
+    /// The directory to save the keys in.
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+    /// The ID of the first pre-key.
+    #[structopt(long, default_value = "1")]
+    start: u32,
+    /// How many pre-keys to generate.
+    #[structopt(long, default_value = "100")]
+    count: u32,
+    /// The signed pre-key's ID.
+    #[structopt(long, default_value = "1")]
+    signed_pre_key_id: u32,
+    /// The device ID to put in the bundle.
+    #[structopt(long, default_value = "1")]
+    device_id: i32,
+    /// Pick the registration ID from the extended range.
+    #[structopt(long)]
+    extended_range: bool,
+    /// Overwrite any existing keys.
+    #[structopt(long)]
+    force: bool,
+}
+
+impl Generate {
+    pub fn run(&self, ctx: &Context) -> Result<(), Error> {
+        let pre_key_dir = self.output.join("pre_keys");
+        fs::create_dir_all(&pre_key_dir).with_context(|| {
+            format!("Unable to create \"{}\"", pre_key_dir.display())
+        })?;
+
+        let identity = sig::generate_identity_key_pair(ctx)?;
+        let registration_id = sig::generate_registration_id(
+            ctx,
+            if self.extended_range { 1 } else { 0 },
+        )?;
+        let signed_pre_key = sig::generate_signed_pre_key(
+            ctx,
+            &identity,
+            self.signed_pre_key_id,
+            SystemTime::now(),
+        )?;
+        let pre_keys: Vec<_> =
+            sig::generate_pre_keys(ctx, self.start, self.count)?.collect();
+
+        self.write_private(
+            "identity_key_pair",
+            identity.serialize()?.as_slice(),
+        )?;
+        self.write("registration_id", registration_id.to_string().as_bytes())?;
+        self.write_private(
+            "signed_pre_key",
+            signed_pre_key.serialize()?.as_slice(),
+        )?;
+        for pre_key in &pre_keys {
+            let path = Path::new("pre_keys").join(pre_key.id().to_string());
+            self.write_private(&path, pre_key.serialize()?.as_slice())?;
+        }
+
+        let mut bundle = PreKeyBundle::builder()
+            .registration_id(registration_id)
+            .device_id(self.device_id)
+            .identity_key(&identity.public())
+            .signed_pre_key(
+                signed_pre_key.id(),
+                &signed_pre_key.key_pair().public(),
+            )
+            .signature(signed_pre_key.signature());
+        if let Some(pre_key) = pre_keys.first() {
+            bundle = bundle.pre_key(pre_key.id(), &pre_key.key_pair().public());
+        }
+        self.write("bundle", bundle.build()?.serialize()?.as_slice())?;
+
+        println!("Identity key:      {}", identity.public());
+        println!("Registration ID:   {}", registration_id);
+        println!("Signed pre-key ID: {}", signed_pre_key.id());
+        println!("Pre-keys:          {}", pre_keys.len());
+        println!("Saved to:          {}", self.output.display());
+
+        Ok(())
+    }
+
+    fn write<P: AsRef<Path>>(&self, name: P, data: &[u8]) -> Result<(), Error> {
+        self.save(name.as_ref(), data, false)
+    }
+
+    /// Write a file containing private key material, which nobody else
+    /// should be able to read.
+    fn write_private<P: AsRef<Path>>(
+        &self,
+        name: P,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        self.save(name.as_ref(), data, true)
+    }
+
+    fn save(
+        &self,
+        name: &Path,
+        data: &[u8],
+        private: bool,
+    ) -> Result<(), Error> {
+        let path = self.output.join(name);
+
+        let mut options = OpenOptions::new();
+        options
+            .write(true)
+            .truncate(true)
+            .create(self.force)
+            .create_new(!self.force);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            if private {
+                options.mode(0o600);
+            }
+        }
+
+        let mut f = options.open(&path).with_context(|| {
+            format!("Unable to create \"{}\"", path.display())
+        })?;
+
+        // the mode is only used when creating a file, so make sure a file
+        // we're overwriting isn't left readable by everyone
+        #[cfg(unix)]
+        {
+            use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+            if private {
+                f.set_permissions(Permissions::from_mode(0o600))
+                    .with_context(|| {
+                        format!(
+                            "Unable to restrict access to \"{}\"",
+                            path.display()
+                        )
+                    })?;
+            }
+        }
+
+        f.write_all(data).with_context(|| {
+            format!("Unable to write to \"{}\"", path.display())
+        })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+    str::{self, FromStr},
+};
+
+use anyhow::{Context as _, Error};
+use structopt::StructOpt;
+
+/// How a binary payload has been written down.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    /// Guess based on what the payload looks like.
+    Auto,
+    Base64,
+    Hex,
+    /// The bytes themselves.
+    Raw,
+}
+
+impl Encoding {
+    /// Turn an encoded payload back into bytes.
+    pub fn decode(self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Auto => Encoding::guess(payload).decode(payload),
+            Encoding::Base64 => {
+                base64::decode(text(payload)?).context("Invalid base64")
+            },
+            Encoding::Hex => {
+                let text = text(payload)?;
+                let text = text.strip_prefix("0x").unwrap_or(&text);
+                hex::decode(text).context("Invalid hex")
+            },
+            Encoding::Raw => Ok(payload.to_vec()),
+        }
+    }
+
+    /// Work out how a payload is encoded.
+    ///
+    /// Hex is checked first, so text which is valid hex *and* base64 is
+    /// always treated as hex.
+    fn guess(payload: &[u8]) -> Encoding {
+        let text = match text(payload) {
+            Ok(text) if !text.is_empty() => text,
+            _ => return Encoding::Raw,
+        };
+        let text = text.strip_prefix("0x").unwrap_or(&text);
+
+        if text.len() % 2 == 0 && text.chars().all(|c| c.is_ascii_hexdigit()) {
+            Encoding::Hex
+        } else if base64::decode(text).is_ok() {
+            Encoding::Base64
+        } else {
+            Encoding::Raw
+        }
+    }
+}
+
+/// Get the payload as text, ignoring any whitespace (e.g. from line
+/// wrapping).
+fn text(payload: &[u8]) -> Result<String, Error> {
+    let text =
+        str::from_utf8(payload).context("The payload should be plain text")?;
+
+    Ok(text.split_whitespace().collect())
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Encoding::Auto),
+            "base64" => Ok(Encoding::Base64),
+            "hex" => Ok(Encoding::Hex),
+            "raw" => Ok(Encoding::Raw),
+            _ => anyhow::bail!(
+                "Expected one of \"auto\", \"base64\", \"hex\" or \"raw\""
+            ),
+        }
+    }
+}
+
+/// Where to read a payload from.
+#[derive(Debug, StructOpt)]
+pub struct Input {
+    /// The payload to read (read from stdin if neither this nor --file are
+    /// given).
+    payload: Option<String>,
+    /// Read the payload from a file.
+    #[structopt(short, long, parse(from_os_str), conflicts_with = "payload")]
+    file: Option<PathBuf>,
+    /// How the payload is encoded ("auto", "base64", "hex" or "raw").
+    ///
+    /// "auto" treats any even-length text made up of hex digits as hex, even
+    /// if it is also valid base64 (e.g. "abcd"), so use "base64" for those
+    /// payloads.
+    #[structopt(short, long, default_value = "auto")]
+    encoding: Encoding,
+}
+
+impl Input {
+    /// Read and decode the payload.
+    pub fn read(&self) -> Result<Vec<u8>, Error> {
+        let payload = match (&self.payload, &self.file) {
+            (Some(payload), _) => payload.clone().into_bytes(),
+            (None, Some(path)) => fs::read(path).with_context(|| {
+                format!("Unable to read \"{}\"", path.display())
+            })?,
+            (None, None) => {
+                let mut payload = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut payload)
+                    .context("Unable to read stdin")?;
+                payload
+            },
+        };
+
+        self.encoding.decode(&payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_is_detected() {
+        let got = Encoding::Auto.decode(b"0x33 0a0B\n").unwrap();
+
+        assert_eq!(got, vec![0x33, 0x0a, 0x0b]);
+    }
+
+    #[test]
+    fn base64_is_detected() {
+        let got = Encoding::Auto.decode(b"MwoL\n").unwrap();
+
+        assert_eq!(got, vec![0x33, 0x0a, 0x0b]);
+    }
+
+    #[test]
+    fn ambiguous_payloads_are_treated_as_hex() {
+        assert_eq!(Encoding::guess(b"abcd"), Encoding::Hex);
+        assert_eq!(Encoding::Base64.decode(b"abcd").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn binary_is_passed_through() {
+        let payload = [0x33, 0xff, 0x00, 0x80];
+
+        let got = Encoding::Auto.decode(&payload).unwrap();
+
+        assert_eq!(got, payload);
+    }
+
+    #[test]
+    fn explicit_encodings_are_strict() {
+        assert!(Encoding::Hex.decode(b"MwoL").is_err());
+        assert!(Encoding::Base64.decode(&[0xff, 0xfe]).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use anyhow::{Context as _, Error};
+use libsignal_protocol::{
+    keys::PublicKey,
+    messages::{PreKeySignalMessage, SignalMessage},
+    Context, Deserializable, PreKeyBundle, SessionRecord, SessionState,
+};
+use structopt::StructOpt;
+
+use crate::input::{Encoding, Input};
+
+/// How wide the column of field names is.
+const WIDTH: usize = 34;
+
+/// The kinds of payload we know how to decode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    PreKeyMessage,
+    Message,
+    Bundle,
+    Session,
+}
+
+impl FromStr for Kind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pre-key-message" => Ok(Kind::PreKeyMessage),
+            "message" => Ok(Kind::Message),
+            "bundle" => Ok(Kind::Bundle),
+            "session" => Ok(Kind::Session),
+            _ => anyhow::bail!(
+                "Expected one of \"pre-key-message\", \"message\", \"bundle\" \
+                 or \"session\""
+            ),
+        }
+    }
+}
+
+/// Decode a serialized payload and print its contents.
+#[derive(Debug, StructOpt)]
+pub struct Decode {
+    /// What the payload is ("pre-key-message", "message", "bundle" or
+    /// "session").
+    kind: Kind,
+    #[structopt(flatten)]
+    input: Input,
+}
+
+impl Decode {
+    pub fn run(&self, ctx: &Context) -> Result<(), Error> {
+        let payload = self.input.read()?;
+        let stdout = io::stdout();
+
+        decode(ctx, self.kind, &payload, &mut stdout.lock())
+    }
+}
+
+/// Decode `payload` and write a human-readable description of it to `out`.
+pub fn decode(
+    ctx: &Context,
+    kind: Kind,
+    payload: &[u8],
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    match kind {
+        Kind::PreKeyMessage => {
+            let message = PreKeySignalMessage::deserialize(ctx, payload)
+                .context("Unable to decode the pre-key message")?;
+            print_pre_key_message(&message, out)?;
+        },
+        Kind::Message => {
+            let message = SignalMessage::deserialize(ctx, payload)
+                .context("Unable to decode the message")?;
+            writeln!(out, "SignalMessage")?;
+            print_message(&message, 2, out)?;
+        },
+        Kind::Bundle => {
+            let bundle = PreKeyBundle::deserialize(ctx, payload)
+                .context("Unable to decode the pre-key bundle")?;
+            print_bundle(&bundle, out)?;
+        },
+        Kind::Session => {
+            let record = SessionRecord::deserialize(ctx, payload)
+                .context("Unable to decode the session record")?;
+            print_session(&record, out)?;
+        },
+    }
+
+    Ok(())
+}
+
+fn print_pre_key_message(
+    message: &PreKeySignalMessage,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    writeln!(out, "PreKeySignalMessage")?;
+    field(out, 2, "version", message.message_version())?;
+    field(out, 2, "registration ID", message.registration_id())?;
+    field(out, 2, "pre-key ID", optional(message.pre_key_id()))?;
+    field(out, 2, "signed pre-key ID", message.signed_pre_key_id())?;
+    field(out, 2, "base key", message.base_key())?;
+    field(out, 2, "identity key", message.identity_key())?;
+    writeln!(out, "  message:")?;
+    print_message(&message.signal_message(), 4, out)
+}
+
+fn print_message(
+    message: &SignalMessage,
+    indent: usize,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    field(out, indent, "version", message.message_version())?;
+    field(out, indent, "counter", message.counter())?;
+    field(
+        out,
+        indent,
+        "sender ratchet key",
+        message.sender_ratchet_key(),
+    )?;
+    field(
+        out,
+        indent,
+        "ciphertext",
+        format!("{} bytes", message.body().len()),
+    )?;
+
+    Ok(())
+}
+
+fn print_bundle(
+    bundle: &PreKeyBundle,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    writeln!(out, "PreKeyBundle")?;
+    field(out, 2, "registration ID", bundle.registration_id())?;
+    field(out, 2, "device ID", bundle.device_id())?;
+    match bundle.pre_key() {
+        Ok(key) => {
+            field(out, 2, "pre-key ID", bundle.pre_key_id())?;
+            field(out, 2, "pre-key", key)?;
+        },
+        Err(_) => field(out, 2, "pre-key", "none")?,
+    }
+    field(out, 2, "signed pre-key ID", bundle.signed_pre_key_id())?;
+    field(out, 2, "signed pre-key", bundle.signed_pre_key()?)?;
+    field(
+        out,
+        2,
+        "signature",
+        base64::encode(bundle.signed_pre_key_signature()),
+    )?;
+    field(out, 2, "identity key", bundle.identity_key()?)?;
+    field(
+        out,
+        2,
+        "signature valid",
+        yes_no(bundle.verify_signature().is_ok()),
+    )?;
+
+    Ok(())
+}
+
+fn print_session(
+    record: &SessionRecord,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let diagnostics = record.diagnostics()?;
+
+    writeln!(out, "SessionRecord")?;
+    field(
+        out,
+        2,
+        "size",
+        format!("{} bytes", diagnostics.serialized_size),
+    )?;
+    field(out, 2, "archived states", diagnostics.archived_states)?;
+    writeln!(out, "  current state:")?;
+    print_state(&record.state(), 4, out)?;
+
+    for (i, state) in record.archived_states().iter().enumerate() {
+        writeln!(out, "  archived state {}:", i + 1)?;
+        print_state(state, 4, out)?;
+    }
+
+    Ok(())
+}
+
+fn print_state(
+    state: &SessionState,
+    indent: usize,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    field(out, indent, "version", state.version())?;
+    field(
+        out,
+        indent,
+        "local registration ID",
+        state.local_registration_id(),
+    )?;
+    field(
+        out,
+        indent,
+        "remote registration ID",
+        state.remote_registration_id(),
+    )?;
+    field(
+        out,
+        indent,
+        "unacknowledged pre-key message",
+        yes_no(state.has_unacknowledged_pre_key_message()),
+    )?;
+    field(out, indent, "needs refresh", yes_no(state.needs_refresh()))?;
+
+    let chains = state.receiver_chains()?;
+    if chains.is_empty() {
+        field(out, indent, "receiver chains", "none")?;
+    } else {
+        writeln!(out, "{:indent$}receiver chains:", "", indent = indent)?;
+    }
+    for chain in &chains {
+        field(
+            out,
+            indent + 2,
+            chain.sender_ratchet_key(),
+            format!(
+                "index {}, {} skipped message keys",
+                chain.index(),
+                chain.skipped_message_keys().len()
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Check a signature against an identity key.
+#[derive(Debug, StructOpt)]
+pub struct Verify {
+    /// The public key the message was signed with.
+    #[structopt(short, long)]
+    key: String,
+    /// The signature.
+    #[structopt(short, long)]
+    signature: String,
+    /// The message which was signed.
+    #[structopt(flatten)]
+    message: Input,
+}
+
+impl Verify {
+    pub fn run(&self, ctx: &Context) -> Result<(), Error> {
+        let key = Encoding::Auto
+            .decode(self.key.as_bytes())
+            .context("Unable to read the key")?;
+        let key = PublicKey::decode_point(ctx, &key)
+            .context("Unable to decode the key")?;
+        let signature = Encoding::Auto
+            .decode(self.signature.as_bytes())
+            .context("Unable to read the signature")?;
+        let message = self.message.read()?;
+
+        key.verify_signature(&message, &signature)
+            .context("The signature is not valid")?;
+        println!("The signature is valid");
+
+        Ok(())
+    }
+}
+
+fn field<N, V>(
+    out: &mut dyn Write,
+    indent: usize,
+    name: N,
+    value: V,
+) -> io::Result<()>
+where
+    N: Display,
+    V: Display,
+{
+    writeln!(
+        out,
+        "{:indent$}{:width$} {}",
+        "",
+        format!("{}:", name),
+        value,
+        indent = indent,
+        width = WIDTH - indent,
+    )
+}
+
+fn optional<T: Display>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::from("none"),
+    }
+}
+
+const fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_aligned() {
+        let mut out = Vec::new();
+
+        field(&mut out, 2, "version", 3).unwrap();
+        field(&mut out, 4, "counter", 42).unwrap();
+
+        let got = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = got.lines().collect();
+        assert_eq!(lines[0].find('3'), Some(WIDTH + 1));
+        assert_eq!(lines[1].find("42"), Some(WIDTH + 1));
+    }
+
+    #[test]
+    fn bundles_round_trip_through_the_decoder() {
+        let ctx = Context::default();
+        let identity =
+            libsignal_protocol::generate_identity_key_pair(&ctx).unwrap();
+        let signed_pre_key = libsignal_protocol::generate_signed_pre_key(
+            &ctx,
+            &identity,
+            5,
+            std::time::SystemTime::now(),
+        )
+        .unwrap();
+        let bundle = PreKeyBundle::builder()
+            .registration_id(42)
+            .device_id(1)
+            .identity_key(&identity.public())
+            .signed_pre_key(5, &signed_pre_key.key_pair().public())
+            .signature(signed_pre_key.signature())
+            .build()
+            .unwrap();
+        let serialized =
+            libsignal_protocol::Serializable::serialize(&bundle).unwrap();
+        let mut out = Vec::new();
+
+        decode(&ctx, Kind::Bundle, serialized.as_slice(), &mut out).unwrap();
+
+        let got = String::from_utf8(out).unwrap();
+        assert!(got.starts_with("PreKeyBundle\n"));
+        assert!(got.contains("registration ID:"));
+        assert!(got.contains(" 42\n"));
+        assert!(got.contains("pre-key:"));
+        assert!(got.contains(" none\n"));
+        assert!(got.ends_with(" yes\n"));
+    }
+}
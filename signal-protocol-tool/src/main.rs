@@ -0,0 +1,48 @@
+//! A command-line tool for generating keys and inspecting serialized Signal
+//! Protocol payloads.
+//!
+//! ```console
+//! $ signal-protocol-tool generate ./keys --count 10
+//! $ signal-protocol-tool decode bundle --file ./keys/bundle
+//! $ signal-protocol-tool decode pre-key-message MwgBEiEF...
+//! $ echo "33 08 01 12 ..." | signal-protocol-tool decode message
+//! $ signal-protocol-tool verify --key BXu... --signature vJk... --file msg
+//! ```
+//!
+//! Payloads can be given as base64 or hex (or raw bytes when read from a
+//! file), and are read from stdin if they aren't passed on the command line.
+
+mod generate;
+mod input;
+mod inspect;
+
+use anyhow::Error;
+use libsignal_protocol::Context;
+use structopt::StructOpt;
+
+use crate::{
+    generate::Generate,
+    inspect::{Decode, Verify},
+};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    about = "Generate keys and inspect Signal Protocol payloads",
+    rename_all = "kebab-case"
+)]
+enum Command {
+    Generate(Generate),
+    Decode(Decode),
+    Verify(Verify),
+}
+
+fn main() -> Result<(), Error> {
+    env_logger::init();
+    let ctx = Context::default();
+
+    match Command::from_args() {
+        Command::Generate(cmd) => cmd.run(&ctx),
+        Command::Decode(cmd) => cmd.run(&ctx),
+        Command::Verify(cmd) => cmd.run(&ctx),
+    }
+}